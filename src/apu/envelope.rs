@@ -0,0 +1,67 @@
+/// Volume envelope (NRx2), clocked by frame sequencer step 7.
+#[derive(Copy, Clone, Debug)]
+pub struct Envelope {
+    register: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    pub fn new() -> Envelope {
+        Envelope {
+            register: 0,
+            volume: 0,
+            timer: 0,
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        self.register
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.register = value;
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    /// The channel's DAC is powered as long as any of the upper 5 bits are set.
+    pub fn dac_enabled(&self) -> bool {
+        self.register & 0xF8 != 0
+    }
+
+    fn period(&self) -> u8 {
+        self.register & 0x07
+    }
+
+    fn increase(&self) -> bool {
+        self.register & 0x08 != 0
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = self.register >> 4;
+        self.timer = self.period();
+    }
+
+    pub fn clock(&mut self) {
+        if self.period() == 0 {
+            return;
+        }
+
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+
+        if self.timer == 0 {
+            self.timer = self.period();
+
+            if self.increase() && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.increase() && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
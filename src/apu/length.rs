@@ -0,0 +1,68 @@
+/// A length counter, which silences its channel once it runs out.
+///
+/// It's clocked by frame sequencer steps 0, 2, 4 and 6 while enabled via bit 6 of NRx4.
+#[derive(Copy, Clone, Debug)]
+pub struct Length {
+    max: u16,
+    counter: u16,
+    enabled: bool,
+}
+
+impl Length {
+    pub fn new(max: u16) -> Length {
+        Length {
+            max: max,
+            counter: 0,
+            enabled: false,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn load(&mut self, value: u8) {
+        self.counter = self.max - value as u16;
+    }
+
+    /// Returns false if the channel has to be disabled.
+    pub fn clock(&mut self) -> bool {
+        if !self.enabled || self.counter == 0 {
+            return true;
+        }
+
+        self.counter -= 1;
+        self.counter != 0
+    }
+
+    /// Handles a write to NRx4. `length_step_next` tells whether the next
+    /// frame sequencer step clocks length counters.
+    /// Returns false if the channel has to be disabled.
+    pub fn write_control(&mut self, value: u8, length_step_next: bool) -> bool {
+        let was_enabled = self.enabled;
+        let trigger = value & 0x80 != 0;
+        let mut keep = true;
+
+        self.enabled = value & 0x40 != 0;
+
+        // Enabling the counter in the first half of a length period clocks it once
+        if !was_enabled && self.enabled && !length_step_next && self.counter != 0 {
+            self.counter -= 1;
+            keep = self.counter != 0 || trigger;
+        }
+
+        if trigger && self.counter == 0 {
+            self.counter = self.max;
+            if self.enabled && !length_step_next {
+                self.counter -= 1;
+            }
+        }
+
+        keep
+    }
+
+    // Powering the APU off clears NRx4 but keeps the counter
+    pub fn power_off(&mut self) {
+        self.enabled = false;
+    }
+}
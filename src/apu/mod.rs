@@ -0,0 +1,411 @@
+use memory::Model;
+use self::square::Square;
+use self::wave::Wave;
+use self::noise::Noise;
+use self::resampler::Resampler;
+pub use self::resampler::CPU_CLOCK;
+mod length;
+mod envelope;
+mod sweep;
+mod square;
+mod wave;
+mod noise;
+mod resampler;
+
+/// The audio processing unit.
+///
+/// Owns the sound registers (0xFF10 - 0xFF26) and wave RAM (0xFF30 - 0xFF3F).
+/// The frame sequencer is clocked at 512 Hz by the falling edge of DIV bit 4.
+/// On CGB, PCM12 and PCM34 (0xFF76, 0xFF77) read back the channels' outputs.
+///
+/// Once a sample rate is set, the mixed output is resampled into a
+/// stereo stream that can be pulled with `pull_samples`.
+pub struct Apu {
+    model: Model,
+    square1: Square,
+    square2: Square,
+    wave: Wave,
+    noise: Noise,
+    nr50: u8,
+    nr51: u8,
+    powered: bool,
+    frame_step: u8,
+    div_bit: bool,
+    resampler: Option<Resampler>,
+}
+
+impl Apu {
+    pub fn new(model: Model) -> Apu {
+        let mut apu = Apu {
+            model: model,
+            square1: Square::new(true),
+            square2: Square::new(false),
+            wave: Wave::new(model),
+            noise: Noise::new(),
+            nr50: 0,
+            nr51: 0,
+            powered: true,
+            frame_step: 0,
+            div_bit: false,
+            resampler: None,
+        };
+
+        // Register values left behind by the boot ROM
+        apu.write(0xFF11, 0x80);
+        apu.write(0xFF12, 0xF3);
+        apu.write(0xFF24, 0x77);
+        apu.write(0xFF25, 0xF3);
+
+        apu
+    }
+
+    /// Advances the channels by the given number of cycles.
+    /// `div_bit` is the current state of the DIV bit driving the frame sequencer.
+    pub fn tick(&mut self, cycles: u16, div_bit: bool) {
+        let falling_edge = self.div_bit && !div_bit;
+        self.div_bit = div_bit;
+
+        let sample = self.sample();
+        if let Some(resampler) = self.resampler.as_mut() {
+            resampler.push(sample, cycles);
+        }
+
+        if !self.powered {
+            return;
+        }
+
+        self.square1.tick(cycles);
+        self.square2.tick(cycles);
+        self.wave.tick(cycles);
+        self.noise.tick(cycles);
+
+        if falling_edge {
+            self.clock_frame_sequencer();
+        }
+    }
+
+    // Length counters are clocked on even steps,
+    // the sweep on steps 2 and 6 and envelopes on step 7
+    fn clock_frame_sequencer(&mut self) {
+        if self.frame_step % 2 == 0 {
+            self.square1.clock_length();
+            self.square2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+
+        if self.frame_step == 2 || self.frame_step == 6 {
+            self.square1.clock_sweep();
+        }
+
+        if self.frame_step == 7 {
+            self.square1.clock_envelope();
+            self.square2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+
+        self.frame_step = (self.frame_step + 1) % 8;
+    }
+
+    fn length_step_next(&self) -> bool {
+        self.frame_step % 2 == 0
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0xFF10 ..= 0xFF14 => self.square1.read(addr - 0xFF10),
+            0xFF15 ..= 0xFF19 => self.square2.read(addr - 0xFF15),
+            0xFF1A ..= 0xFF1E => self.wave.read(addr - 0xFF1A),
+            0xFF1F ..= 0xFF23 => self.noise.read(addr - 0xFF1F),
+            0xFF24 => self.nr50,
+            0xFF25 => self.nr51,
+            0xFF26 => {
+                0x70 |
+                (self.powered as u8) << 7 |
+                (self.noise.enabled() as u8) << 3 |
+                (self.wave.enabled() as u8) << 2 |
+                (self.square2.enabled() as u8) << 1 |
+                self.square1.enabled() as u8
+            },
+            0xFF27 ..= 0xFF2F => 0xFF,
+            0xFF30 ..= 0xFF3F => self.wave.read_ram(addr - 0xFF30),
+            0xFF76 => self.read_pcm(self.square1.output(), self.square2.output()),
+            0xFF77 => self.read_pcm(self.wave.output(), self.noise.output()),
+            _ => unreachable!()
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0xFF26 => self.write_power(value),
+            0xFF30 ..= 0xFF3F => self.wave.write_ram(addr - 0xFF30, value),
+            0xFF76 | 0xFF77 => {},
+            // While powered off, only the length counters can be written on DMG
+            _ if !self.powered => if self.model == Model::Dmg {
+                match addr {
+                    0xFF11 => self.square1.write_length(value),
+                    0xFF16 => self.square2.write_length(value),
+                    0xFF1B => self.wave.write_length(value),
+                    0xFF20 => self.noise.write_length(value),
+                    _ => {},
+                }
+            },
+            0xFF10 ..= 0xFF14 => {
+                let length_step_next = self.length_step_next();
+                self.square1.write(addr - 0xFF10, value, length_step_next)
+            },
+            0xFF15 ..= 0xFF19 => {
+                let length_step_next = self.length_step_next();
+                self.square2.write(addr - 0xFF15, value, length_step_next)
+            },
+            0xFF1A ..= 0xFF1E => {
+                let length_step_next = self.length_step_next();
+                self.wave.write(addr - 0xFF1A, value, length_step_next)
+            },
+            0xFF1F ..= 0xFF23 => {
+                let length_step_next = self.length_step_next();
+                self.noise.write(addr - 0xFF1F, value, length_step_next)
+            },
+            0xFF24 => self.nr50 = value,
+            0xFF25 => self.nr51 = value,
+            0xFF27 ..= 0xFF2F => {},
+            _ => unreachable!()
+        }
+    }
+
+    fn write_power(&mut self, value: u8) {
+        let powered = value & 0x80 != 0;
+
+        if self.powered && !powered {
+            // Powering off clears all sound registers,
+            // CGB also resets the length counters
+            let keep_length = self.model == Model::Dmg;
+            self.square1.power_off(keep_length);
+            self.square2.power_off(keep_length);
+            self.wave.power_off(keep_length);
+            self.noise.power_off(keep_length);
+            self.nr50 = 0;
+            self.nr51 = 0;
+        } else if !self.powered && powered {
+            self.frame_step = 0;
+        }
+
+        self.powered = powered;
+    }
+
+    /// Starts producing output at the given host sample rate, e.g. 44100 or 48000 Hz.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler = Some(Resampler::new(sample_rate));
+    }
+
+    /// Number of interleaved left/right samples ready to be pulled.
+    pub fn available_samples(&self) -> usize {
+        self.resampler.as_ref().map_or(0, |resampler| resampler.available())
+    }
+
+    /// Moves up to `out.len()` interleaved left/right samples into `out`
+    /// and returns how many were written.
+    pub fn pull_samples(&mut self, out: &mut [f32]) -> usize {
+        self.resampler.as_mut().map_or(0, |resampler| resampler.pull(out))
+    }
+
+    fn read_pcm(&self, low: u8, high: u8) -> u8 {
+        match self.model {
+            Model::Cgb => high << 4 | low,
+            Model::Dmg => 0xFF,
+        }
+    }
+
+    /// The current stereo output in the range -1.0 - 1.0,
+    /// mixed according to NR51 panning and NR50 master volume.
+    pub fn sample(&self) -> (f32, f32) {
+        let channels = [
+            (self.square1.dac_enabled(), self.square1.output()),
+            (self.square2.dac_enabled(), self.square2.output()),
+            (self.wave.dac_enabled(), self.wave.output()),
+            (self.noise.dac_enabled(), self.noise.output()),
+        ];
+
+        let mut left = 0.0;
+        let mut right = 0.0;
+
+        for (index, &(dac_enabled, output)) in channels.iter().enumerate() {
+            // Each DAC maps 0 - 15 to 1.0 - -1.0, a disabled DAC outputs nothing
+            if !dac_enabled {
+                continue;
+            }
+            let analog = 1.0 - output as f32 / 7.5;
+
+            if self.nr51 & 0x10 << index != 0 {
+                left += analog;
+            }
+            if self.nr51 & 0x01 << index != 0 {
+                right += analog;
+            }
+        }
+
+        let left_volume = (self.nr50 >> 4 & 0x07) as f32 + 1.0;
+        let right_volume = (self.nr50 & 0x07) as f32 + 1.0;
+
+        (left / 4.0 * left_volume / 8.0, right / 4.0 * right_volume / 8.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock_frame_sequencer(apu: &mut Apu, steps: usize) {
+        for _ in 0 .. steps {
+            apu.tick(4, true);
+            apu.tick(4, false);
+        }
+    }
+
+    #[test]
+    fn registers_read_back_with_unused_bits_set() {
+        let masks = [
+            0x80, 0x3F, 0x00, 0xFF, 0xBF,
+            0xFF, 0x3F, 0x00, 0xFF, 0xBF,
+            0x7F, 0xFF, 0x9F, 0xFF, 0xBF,
+            0xFF, 0xFF, 0x00, 0x00, 0xBF,
+            0x00, 0x00, 0x70,
+            0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+        ];
+
+        let mut apu = Apu::new(Model::Dmg);
+        apu.write(0xFF26, 0x00);
+
+        for (addr, &mask) in (0xFF10 ..).zip(masks.iter()) {
+            assert_eq!(apu.read(addr), mask, "0x{:04X}", addr);
+        }
+
+        apu.write(0xFF26, 0x80);
+        for addr in (0xFF10 .. 0xFF30).filter(|&addr| addr != 0xFF26) {
+            apu.write(addr, 0xFF);
+            assert_eq!(apu.read(addr), 0xFF, "0x{:04X}", addr);
+        }
+    }
+
+    #[test]
+    fn length_counter_disables_the_channel() {
+        let mut apu = Apu::new(Model::Dmg);
+        apu.write(0xFF17, 0xF0);
+        apu.write(0xFF16, 62);
+        apu.write(0xFF19, 0xC0);
+        assert_eq!(apu.read(0xFF26) & 0x02, 0x02);
+
+        // Only even steps clock it
+        clock_frame_sequencer(&mut apu, 2);
+        assert_eq!(apu.read(0xFF26) & 0x02, 0x02);
+        clock_frame_sequencer(&mut apu, 1);
+        assert_eq!(apu.read(0xFF26) & 0x02, 0x00);
+    }
+
+    #[test]
+    fn enabling_length_before_an_odd_step_clocks_it() {
+        let mut apu = Apu::new(Model::Dmg);
+        clock_frame_sequencer(&mut apu, 1);
+
+        apu.write(0xFF17, 0xF0);
+        apu.write(0xFF16, 62);
+        apu.write(0xFF19, 0x80);
+        apu.write(0xFF19, 0x40);
+
+        clock_frame_sequencer(&mut apu, 1);
+        assert_eq!(apu.read(0xFF26) & 0x02, 0x02);
+        clock_frame_sequencer(&mut apu, 1);
+        assert_eq!(apu.read(0xFF26) & 0x02, 0x00);
+    }
+
+    #[test]
+    fn sweep_overflow_disables_channel_1() {
+        let mut apu = Apu::new(Model::Dmg);
+        apu.write(0xFF12, 0xF0);
+
+        // Checked right away on trigger
+        apu.write(0xFF10, 0x01);
+        apu.write(0xFF13, 0xFF);
+        apu.write(0xFF14, 0x87);
+        assert_eq!(apu.read(0xFF26) & 0x01, 0x00);
+
+        // 0x500 sweeps to 0x780 on step 2, which would overflow on the next sweep
+        apu.write(0xFF10, 0x11);
+        apu.write(0xFF13, 0x00);
+        apu.write(0xFF14, 0x85);
+        clock_frame_sequencer(&mut apu, 2);
+        assert_eq!(apu.read(0xFF26) & 0x01, 0x01);
+        clock_frame_sequencer(&mut apu, 1);
+        assert_eq!(apu.read(0xFF26) & 0x01, 0x00);
+    }
+
+    #[test]
+    fn frame_sequencer_clocks_envelopes_on_step_7() {
+        let mut apu = Apu::new(Model::Dmg);
+        // 50% duty starts high
+        apu.write(0xFF16, 0x80);
+        apu.write(0xFF17, 0xF1);
+        apu.write(0xFF19, 0x80);
+        assert_eq!(apu.square2.output(), 15);
+
+        clock_frame_sequencer(&mut apu, 7);
+        assert_eq!(apu.square2.output(), 15);
+        clock_frame_sequencer(&mut apu, 1);
+        assert_eq!(apu.square2.output(), 14);
+    }
+
+    #[test]
+    fn frame_sequencer_steps_on_falling_div_edges() {
+        let mut apu = Apu::new(Model::Dmg);
+
+        apu.tick(4, true);
+        apu.tick(4, true);
+        assert_eq!(apu.frame_step, 0);
+        apu.tick(4, false);
+        apu.tick(4, false);
+        assert_eq!(apu.frame_step, 1);
+    }
+
+    // Triggers channel 2 with its length counter enabled after cycling the power,
+    // with NR21 set to a length of 2 while the APU was off
+    fn length_after_power_cycle(model: Model) -> Apu {
+        let mut apu = Apu::new(model);
+        apu.write(0xFF26, 0x00);
+        apu.write(0xFF16, 62);
+        apu.write(0xFF26, 0x80);
+
+        apu.write(0xFF17, 0xF0);
+        apu.write(0xFF19, 0xC0);
+        clock_frame_sequencer(&mut apu, 3);
+        apu
+    }
+
+    #[test]
+    fn dmg_length_can_be_written_while_powered_off() {
+        let apu = length_after_power_cycle(Model::Dmg);
+        assert_eq!(apu.read(0xFF26) & 0x02, 0x00);
+    }
+
+    #[test]
+    fn cgb_length_is_reset_while_powered_off() {
+        let apu = length_after_power_cycle(Model::Cgb);
+        assert_eq!(apu.read(0xFF26) & 0x02, 0x02);
+    }
+
+    #[test]
+    fn pcm_registers_read_channel_outputs_on_cgb() {
+        let mut dmg = Apu::new(Model::Dmg);
+        let mut cgb = Apu::new(Model::Cgb);
+
+        for apu in [&mut dmg, &mut cgb].iter_mut() {
+            apu.write(0xFF16, 0x80);
+            apu.write(0xFF17, 0xA0);
+            apu.write(0xFF19, 0x80);
+        }
+
+        assert_eq!(dmg.read(0xFF76), 0xFF);
+        assert_eq!(dmg.read(0xFF77), 0xFF);
+        assert_eq!(cgb.read(0xFF76), 0xA0);
+        assert_eq!(cgb.read(0xFF77), 0x00);
+    }
+}
@@ -0,0 +1,144 @@
+use super::length::Length;
+use super::envelope::Envelope;
+
+const DIVISORS: [u16; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+/// Noise channel 4 (NR41 - NR44), driven by a 15 bit LFSR.
+pub struct Noise {
+    length: Length,
+    envelope: Envelope,
+    enabled: bool,
+    polynomial: u8,
+    lfsr: u16,
+    timer: u32,
+}
+
+impl Noise {
+    pub fn new() -> Noise {
+        Noise {
+            length: Length::new(64),
+            envelope: Envelope::new(),
+            enabled: false,
+            polynomial: 0,
+            lfsr: 0x7FFF,
+            timer: 0,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    fn shift(&self) -> u8 {
+        self.polynomial >> 4
+    }
+
+    fn period(&self) -> u32 {
+        (DIVISORS[self.polynomial as usize & 0x07] as u32) << self.shift()
+    }
+
+    pub fn read(&self, register: u16) -> u8 {
+        match register {
+            0 | 1 => 0xFF,
+            2 => self.envelope.read(),
+            3 => self.polynomial,
+            4 => 0xBF | (self.length.enabled() as u8) << 6,
+            _ => unreachable!()
+        }
+    }
+
+    pub fn write(&mut self, register: u16, value: u8, length_step_next: bool) {
+        match register {
+            0 => {},
+            1 => self.length.load(value & 0x3F),
+            2 => {
+                self.envelope.write(value);
+                if !self.dac_enabled() {
+                    self.enabled = false;
+                }
+            },
+            3 => self.polynomial = value,
+            4 => {
+                if !self.length.write_control(value, length_step_next) {
+                    self.enabled = false;
+                }
+
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            },
+            _ => unreachable!()
+        }
+    }
+
+    pub fn write_length(&mut self, value: u8) {
+        self.length.load(value & 0x3F);
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled();
+        self.lfsr = 0x7FFF;
+        self.timer = self.period();
+        self.envelope.trigger();
+    }
+
+    pub fn tick(&mut self, cycles: u16) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut cycles = cycles as u32;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+
+            // Shifts of 14 and 15 stop the LFSR
+            if self.shift() < 14 {
+                self.step_lfsr();
+            }
+        }
+        self.timer -= cycles;
+    }
+
+    fn step_lfsr(&mut self) {
+        let bit = (self.lfsr ^ self.lfsr >> 1) & 1;
+        self.lfsr = self.lfsr >> 1 | bit << 14;
+
+        // 7 bit mode also feeds back into bit 6
+        if self.polynomial & 0x08 != 0 {
+            self.lfsr = self.lfsr & !0x40 | bit << 6;
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if !self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.enabled && self.lfsr & 1 == 0 {
+            self.envelope.volume()
+        } else {
+            0
+        }
+    }
+
+    pub fn power_off(&mut self, keep_length: bool) {
+        let mut length = self.length;
+        length.power_off();
+
+        *self = Noise::new();
+        if keep_length {
+            self.length = length;
+        }
+    }
+}
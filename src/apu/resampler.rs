@@ -0,0 +1,158 @@
+use std::collections::VecDeque;
+
+pub const CPU_CLOCK: u32 = 4194304;
+
+// How much the DACs' output capacitor charges per cycle
+const CHARGE_FACTOR: f64 = 0.999958;
+
+/// Turns the APU output into a stereo stream at the host sample rate.
+///
+/// The output is averaged over each host sample period, which acts as a
+/// low-pass filter, and passed through a high-pass filter that removes
+/// the DC offset like the capacitors on the Game Boy's audio output.
+pub struct Resampler {
+    sample_rate: u32,
+    cycles_per_sample: f64,
+    phase: f64,
+    sum: (f64, f64),
+    capacitor: (f64, f64),
+    charge: f64,
+    samples: VecDeque<f32>,
+}
+
+impl Resampler {
+    pub fn new(sample_rate: u32) -> Resampler {
+        let cycles_per_sample = CPU_CLOCK as f64 / sample_rate as f64;
+
+        Resampler {
+            sample_rate: sample_rate,
+            cycles_per_sample: cycles_per_sample,
+            phase: 0.0,
+            sum: (0.0, 0.0),
+            capacitor: (0.0, 0.0),
+            charge: CHARGE_FACTOR.powf(cycles_per_sample),
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Adds the given output, held for the given number of cycles.
+    pub fn push(&mut self, (left, right): (f32, f32), cycles: u16) {
+        let mut cycles = cycles as f64;
+
+        while self.phase + cycles >= self.cycles_per_sample {
+            let part = self.cycles_per_sample - self.phase;
+            self.accumulate(left, right, part);
+            self.finish_sample();
+            cycles -= part;
+            self.phase = 0.0;
+        }
+
+        self.accumulate(left, right, cycles);
+        self.phase += cycles;
+    }
+
+    fn accumulate(&mut self, left: f32, right: f32, cycles: f64) {
+        self.sum.0 += left as f64 * cycles;
+        self.sum.1 += right as f64 * cycles;
+    }
+
+    fn finish_sample(&mut self) {
+        let left = self.sum.0 / self.cycles_per_sample;
+        let right = self.sum.1 / self.cycles_per_sample;
+        self.sum = (0.0, 0.0);
+
+        let left = high_pass(&mut self.capacitor.0, left, self.charge);
+        let right = high_pass(&mut self.capacitor.1, right, self.charge);
+
+        // Keep at most one second of audio if nobody pulls
+        if self.samples.len() >= self.sample_rate as usize * 2 {
+            self.samples.pop_front();
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back(left as f32);
+        self.samples.push_back(right as f32);
+    }
+
+    /// Number of interleaved samples ready to be pulled.
+    pub fn available(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn pull(&mut self, out: &mut [f32]) -> usize {
+        let count = out.len().min(self.samples.len()) & !1;
+
+        for (out, sample) in out.iter_mut().zip(self.samples.drain(.. count)) {
+            *out = sample;
+        }
+
+        count
+    }
+}
+
+fn high_pass(capacitor: &mut f64, input: f64, charge: f64) -> f64 {
+    let output = input - *capacitor;
+    *capacitor = input - output * charge;
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Pushes a constant output for the given number of cycles, 4 at a time like the APU
+    fn push_constant(resampler: &mut Resampler, value: f32, cycles: u32) {
+        for _ in 0 .. cycles / 4 {
+            resampler.push((value, value), 4);
+        }
+    }
+
+    fn pull_all(resampler: &mut Resampler) -> Vec<f32> {
+        let mut out = vec![0.0; resampler.available()];
+        let count = resampler.pull(&mut out);
+        out.truncate(count);
+        out
+    }
+
+    #[test]
+    fn output_rate_matches_sample_rate() {
+        for &rate in &[32000, 44100, 48000] {
+            let mut resampler = Resampler::new(rate);
+            push_constant(&mut resampler, 0.0, CPU_CLOCK / 2);
+
+            let frames = resampler.available() / 2;
+            let expected = rate as usize / 2;
+            assert!(frames >= expected - 1 && frames <= expected + 1, "{} Hz: {} frames", rate, frames);
+        }
+    }
+
+    #[test]
+    fn output_is_interleaved_stereo() {
+        let mut resampler = Resampler::new(48000);
+        resampler.push((0.5, -0.5), 200);
+
+        let out = pull_all(&mut resampler);
+        assert_eq!(out.len(), 4);
+        assert!(out[0] > 0.0 && out[1] < 0.0);
+        assert_eq!(out[0], -out[1]);
+    }
+
+    #[test]
+    fn dc_offset_decays() {
+        let mut resampler = Resampler::new(48000);
+        push_constant(&mut resampler, 0.5, CPU_CLOCK / 2);
+
+        let out = pull_all(&mut resampler);
+        // A step passes through at first, then the capacitor charges up
+        assert!((out[0] - 0.5).abs() < 0.01, "first sample {}", out[0]);
+        assert!(out[out.len() - 1].abs() < 0.001, "last sample {}", out[out.len() - 1]);
+    }
+
+    #[test]
+    fn buffers_at_most_one_second() {
+        let mut resampler = Resampler::new(8000);
+        push_constant(&mut resampler, 0.0, CPU_CLOCK * 2);
+
+        assert_eq!(resampler.available(), 8000 * 2);
+    }
+}
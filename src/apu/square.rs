@@ -0,0 +1,163 @@
+use super::length::Length;
+use super::envelope::Envelope;
+use super::sweep::Sweep;
+
+const DUTY_PATTERNS: [u8; 4] = [
+    0b00000001, // 12.5%
+    0b10000001, // 25%
+    0b10000111, // 50%
+    0b01111110, // 75%
+];
+
+/// Square wave channels 1 (with sweep, NR10 - NR14) and 2 (NR21 - NR24).
+pub struct Square {
+    sweep: Option<Sweep>,
+    length: Length,
+    envelope: Envelope,
+    enabled: bool,
+    duty: u8,
+    duty_step: u8,
+    frequency: u16,
+    timer: u16,
+}
+
+impl Square {
+    pub fn new(with_sweep: bool) -> Square {
+        Square {
+            sweep: if with_sweep { Some(Sweep::new()) } else { None },
+            length: Length::new(64),
+            envelope: Envelope::new(),
+            enabled: false,
+            duty: 0,
+            duty_step: 0,
+            frequency: 0,
+            timer: 0,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    fn period(&self) -> u16 {
+        (2048 - self.frequency) * 4
+    }
+
+    /// Reads NRx0 - NRx4 by their index.
+    pub fn read(&self, register: u16) -> u8 {
+        match register {
+            0 => self.sweep.map_or(0xFF, |sweep| sweep.read()),
+            1 => self.duty << 6 | 0x3F,
+            2 => self.envelope.read(),
+            3 => 0xFF,
+            4 => 0xBF | (self.length.enabled() as u8) << 6,
+            _ => unreachable!()
+        }
+    }
+
+    pub fn write(&mut self, register: u16, value: u8, length_step_next: bool) {
+        match register {
+            0 => if let Some(ref mut sweep) = self.sweep {
+                if !sweep.write(value) {
+                    self.enabled = false;
+                }
+            },
+            1 => {
+                self.duty = value >> 6;
+                self.length.load(value & 0x3F);
+            },
+            2 => {
+                self.envelope.write(value);
+                if !self.dac_enabled() {
+                    self.enabled = false;
+                }
+            },
+            3 => self.frequency = self.frequency & 0x700 | value as u16,
+            4 => {
+                self.frequency = self.frequency & 0xFF | (value as u16 & 0x07) << 8;
+
+                if !self.length.write_control(value, length_step_next) {
+                    self.enabled = false;
+                }
+
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            },
+            _ => unreachable!()
+        }
+    }
+
+    pub fn write_length(&mut self, value: u8) {
+        self.length.load(value & 0x3F);
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled();
+        self.timer = self.period();
+        self.envelope.trigger();
+
+        let frequency = self.frequency;
+        if let Some(ref mut sweep) = self.sweep {
+            if !sweep.trigger(frequency) {
+                self.enabled = false;
+            }
+        }
+    }
+
+    pub fn tick(&mut self, cycles: u16) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut cycles = cycles;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+        self.timer -= cycles;
+    }
+
+    pub fn clock_length(&mut self) {
+        if !self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_sweep(&mut self) {
+        if let Some(ref mut sweep) = self.sweep {
+            if !sweep.clock(&mut self.frequency) {
+                self.enabled = false;
+            }
+        }
+    }
+
+    /// The current digital output, 0 - 15.
+    pub fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+
+        let high = DUTY_PATTERNS[self.duty as usize] >> (7 - self.duty_step) & 1 == 1;
+        if high { self.envelope.volume() } else { 0 }
+    }
+
+    pub fn power_off(&mut self, keep_length: bool) {
+        let mut length = self.length;
+        length.power_off();
+
+        *self = Square::new(self.sweep.is_some());
+        if keep_length {
+            self.length = length;
+        }
+    }
+}
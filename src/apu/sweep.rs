@@ -0,0 +1,101 @@
+/// Frequency sweep of channel 1 (NR10), clocked by frame sequencer steps 2 and 6.
+#[derive(Copy, Clone, Debug)]
+pub struct Sweep {
+    period: u8,
+    negate: bool,
+    shift: u8,
+    timer: u8,
+    enabled: bool,
+    shadow: u16,
+    // Whether a subtraction was calculated since the last trigger
+    negated: bool,
+}
+
+impl Sweep {
+    pub fn new() -> Sweep {
+        Sweep {
+            period: 0,
+            negate: false,
+            shift: 0,
+            timer: 0,
+            enabled: false,
+            shadow: 0,
+            negated: false,
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        0x80 | self.period << 4 | (self.negate as u8) << 3 | self.shift
+    }
+
+    /// Returns false if the channel has to be disabled, which happens when
+    /// negate mode is left after it was used for a calculation.
+    pub fn write(&mut self, value: u8) -> bool {
+        self.period = value >> 4 & 0x07;
+        self.negate = value & 0x08 != 0;
+        self.shift = value & 0x07;
+
+        !self.negated || self.negate
+    }
+
+    fn reload_timer(&mut self) {
+        // A period of 0 is treated as 8
+        self.timer = if self.period == 0 { 8 } else { self.period };
+    }
+
+    /// Returns false if the channel has to be disabled.
+    pub fn trigger(&mut self, frequency: u16) -> bool {
+        self.shadow = frequency;
+        self.negated = false;
+        self.reload_timer();
+        self.enabled = self.period != 0 || self.shift != 0;
+
+        // The overflow check is done immediately
+        self.shift == 0 || self.calculate().is_some()
+    }
+
+    // Returns None on overflow
+    fn calculate(&mut self) -> Option<u16> {
+        let delta = self.shadow >> self.shift;
+
+        let frequency = if self.negate {
+            self.negated = true;
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        };
+
+        if frequency > 2047 { None } else { Some(frequency) }
+    }
+
+    /// Returns false if the channel has to be disabled.
+    pub fn clock(&mut self, frequency: &mut u16) -> bool {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+
+        if self.timer != 0 {
+            return true;
+        }
+
+        self.reload_timer();
+
+        if !self.enabled || self.period == 0 {
+            return true;
+        }
+
+        match self.calculate() {
+            None => false,
+            Some(new_frequency) => {
+                if self.shift != 0 {
+                    self.shadow = new_frequency;
+                    *frequency = new_frequency;
+
+                    // The new frequency is checked for overflow again, but not used
+                    return self.calculate().is_some();
+                }
+                true
+            },
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use memory::Model;
+use super::length::Length;
+
+// The first sample is fetched a few cycles later than a full period after triggering
+const TRIGGER_DELAY: u16 = 6;
+// How many cycles after a sample fetch the CPU can still access wave RAM while playing
+const ACCESS_WINDOW: u16 = 2;
+
+/// Wave channel 3 (NR30 - NR34) playing the 32 4 bit samples of wave RAM (0xFF30 - 0xFF3F).
+pub struct Wave {
+    model: Model,
+    length: Length,
+    enabled: bool,
+    dac_enabled: bool,
+    volume: u8,
+    frequency: u16,
+    timer: u16,
+    position: u8,
+    sample_buffer: u8,
+    since_fetch: u16,
+    ram: [u8; 16],
+}
+
+impl Wave {
+    pub fn new(model: Model) -> Wave {
+        Wave {
+            model: model,
+            length: Length::new(256),
+            enabled: false,
+            dac_enabled: false,
+            volume: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            sample_buffer: 0,
+            since_fetch: ACCESS_WINDOW,
+            ram: [0; 16],
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.dac_enabled
+    }
+
+    fn period(&self) -> u16 {
+        (2048 - self.frequency) * 2
+    }
+
+    pub fn read(&self, register: u16) -> u8 {
+        match register {
+            0 => 0x7F | (self.dac_enabled as u8) << 7,
+            1 => 0xFF,
+            2 => 0x9F | self.volume << 5,
+            3 => 0xFF,
+            4 => 0xBF | (self.length.enabled() as u8) << 6,
+            _ => unreachable!()
+        }
+    }
+
+    pub fn write(&mut self, register: u16, value: u8, length_step_next: bool) {
+        match register {
+            0 => {
+                self.dac_enabled = value & 0x80 != 0;
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            },
+            1 => self.length.load(value),
+            2 => self.volume = value >> 5 & 0x03,
+            3 => self.frequency = self.frequency & 0x700 | value as u16,
+            4 => {
+                self.frequency = self.frequency & 0xFF | (value as u16 & 0x07) << 8;
+
+                if !self.length.write_control(value, length_step_next) {
+                    self.enabled = false;
+                }
+
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            },
+            _ => unreachable!()
+        }
+    }
+
+    pub fn write_length(&mut self, value: u8) {
+        self.length.load(value);
+    }
+
+    fn trigger(&mut self) {
+        // On DMG, retriggering right when a sample is fetched corrupts the start of wave RAM
+        if self.model == Model::Dmg && self.enabled && self.timer <= ACCESS_WINDOW {
+            let offset = ((self.position as usize + 1) / 2) % 16;
+            if offset < 4 {
+                self.ram[0] = self.ram[offset];
+            } else {
+                let start = offset & !3;
+                for i in 0 .. 4 {
+                    self.ram[i] = self.ram[start + i];
+                }
+            }
+        }
+
+        self.enabled = self.dac_enabled;
+        self.timer = self.period() + TRIGGER_DELAY;
+        self.position = 0;
+    }
+
+    pub fn tick(&mut self, cycles: u16) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut cycles = cycles;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.position = (self.position + 1) % 32;
+            self.sample_buffer = self.ram[self.position as usize / 2];
+            self.since_fetch = 0;
+        }
+        self.timer -= cycles;
+        self.since_fetch = self.since_fetch.saturating_add(cycles);
+    }
+
+    // While playing, accesses to wave RAM go to the byte being played.
+    // DMG only allows them right as the channel fetches from it.
+    fn ram_index(&self, offset: u16) -> Option<usize> {
+        if !self.enabled {
+            Some(offset as usize)
+        } else if self.model == Model::Cgb || self.since_fetch < ACCESS_WINDOW {
+            Some(self.position as usize / 2)
+        } else {
+            None
+        }
+    }
+
+    pub fn read_ram(&self, offset: u16) -> u8 {
+        self.ram_index(offset).map_or(0xFF, |index| self.ram[index])
+    }
+
+    pub fn write_ram(&mut self, offset: u16, value: u8) {
+        if let Some(index) = self.ram_index(offset) {
+            self.ram[index] = value;
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if !self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+
+        let sample = if self.position % 2 == 0 {
+            self.sample_buffer >> 4
+        } else {
+            self.sample_buffer & 0x0F
+        };
+
+        // Volume codes 0 - 3 are mute, 100%, 50% and 25%
+        match self.volume {
+            0 => 0,
+            volume => sample >> (volume - 1),
+        }
+    }
+
+    // Wave RAM is not affected by powering off
+    pub fn power_off(&mut self, keep_length: bool) {
+        let mut length = self.length;
+        length.power_off();
+
+        let ram = self.ram;
+        *self = Wave::new(self.model);
+        self.ram = ram;
+        if keep_length {
+            self.length = length;
+        }
+    }
+}
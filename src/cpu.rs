@@ -1,6 +1,15 @@
 use std::num::Wrapping;
 use instructions::Instruction;
 use memory::*;
+use interrupts::Interrupt;
+use self::State::*;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum State {
+    Running,
+    Halted,
+    Stopped,
+}
 
 pub struct Cpu {
     pub pc: Wrapping<u16>,
@@ -16,7 +25,13 @@ pub struct Cpu {
     pub h: Wrapping<u8>,
     pub l: Wrapping<u8>,
     interrupts_enabled: bool,
-    pub is_stalling: bool,
+    enable_interrupts_pending: bool,
+    state: State,
+    halt_bug: bool,
+    branch_cycles: u16,
+    /// Prints every instruction before executing it
+    pub trace: bool,
+    pub breakpoint: Option<u16>,
     pub at_breakpoint: bool,
 }
 
@@ -33,8 +48,13 @@ impl Cpu {
             f: 0b10110000,
             h: Wrapping(0),
             l: Wrapping(0),
-            interrupts_enabled: true,
-            is_stalling: false,
+            interrupts_enabled: false,
+            enable_interrupts_pending: false,
+            state: Running,
+            halt_bug: false,
+            branch_cycles: 0,
+            trace: false,
+            breakpoint: None,
             at_breakpoint: false,
         }
     }
@@ -97,10 +117,6 @@ impl Cpu {
         self.e = Wrapping(n);
     }
 
-    pub fn set_f(&mut self, n: u8) {
-        self.f = n;
-    }
-
     pub fn set_h(&mut self, n: u8) {
         self.h = Wrapping(n);
     }
@@ -110,7 +126,8 @@ impl Cpu {
     }
 
     pub fn set_af(&mut self, n: u16) {
-        Self::set_16(&mut self.a.0, &mut self.f, n);
+        // The lower nibble of F is hardwired to zero
+        Self::set_16(&mut self.a.0, &mut self.f, n & 0xFFF0);
     }
 
     pub fn set_bc(&mut self, n: u16) {
@@ -139,52 +156,128 @@ impl Cpu {
     }
 
     pub fn push_u16(&mut self, mem: &mut Memory, value: u16) {
-        self.sp -= Wrapping(2);
-        mem.write_u16(Addr(self.sp()), value);
+        // SP is decremented during an internal cycle before the
+        // high byte is written first
+        mem.tick_inc_dec(Addr(self.sp()));
+        self.push_u8(mem, (value >> 8) as u8);
+        self.push_u8(mem, value as u8);
     }
 
     pub fn pop_u8(&mut self, mem: &mut Memory) -> u8 {
-        let result = mem.read_u8(Addr(self.sp()));
+        let result = mem.read_u8_inc_dec(Addr(self.sp()));
         self.sp += Wrapping(1);
         result
     }
 
     pub fn pop_u16(&mut self, mem: &mut Memory) -> u16 {
-        let result = mem.read_u16(Addr(self.sp()));
-        self.sp += Wrapping(2);
-        result
-    }
+        let low  = self.pop_u8(mem);
+        let high = self.pop_u8(mem);
+
+        (high as u16) << 8 | low as u16
+    }
+
+    /// Executes a single instruction or dispatches a pending interrupt,
+    /// advances the rest of the system by the cycles taken and returns them.
+    pub fn step(&mut self, mem: &mut Memory) -> u16 {
+        let start = mem.cycles();
+        let cycles = self.execute_next(mem);
+
+        // Bus accesses and internal delays already advanced the system
+        // while executing, so only the trailing internal cycles are left
+        let elapsed = (mem.cycles() - start) as u16;
+        debug_assert!(elapsed <= cycles, "{} cycles elapsed, {} expected", elapsed, cycles);
+        mem.tick(cycles.saturating_sub(elapsed));
+
+        cycles
+    }
+
+    fn execute_next(&mut self, mem: &mut Memory) -> u16 {
+        match self.state {
+            Running => {},
+            Halted => {
+                // HALT is exited by any enabled interrupt, even if IME is off
+                if mem.interrupts.pending().is_none() {
+                    return 4;
+                }
+                self.state = Running;
+            },
+            Stopped => {
+                if !mem.interrupts.is_requested(Interrupt::Joypad) {
+                    return 4;
+                }
+                self.state = Running;
+            },
+        }
+
+        if let Some(cycles) = self.handle_interrupts(mem) {
+            return cycles;
+        }
+
+        // EI only takes effect after the instruction following it
+        if self.enable_interrupts_pending {
+            self.enable_interrupts_pending = false;
+            self.interrupts_enabled = true;
+        }
 
-    pub fn step(&mut self, mem: &mut Memory) {
-        let last_pc = self.pc();
-        let inst = Instruction::decode(mem, Addr(self.pc()));
+        // After the HALT bug PC isn't incremented past the opcode,
+        // so its operands start with the opcode byte again
+        let pc = Addr(self.pc());
+        let inst = if self.halt_bug {
+            Instruction::decode_with_operands_at(mem, pc, pc)
+        } else {
+            Instruction::decode(mem, pc)
+        };
 
-        // if self.pc.0 == 0xC2C2 {
-        //     self.at_breakpoint = true;
-        //     self.print_registers();
-        // }
+        if self.breakpoint == Some(self.pc()) {
+            self.at_breakpoint = true;
+            self.print_registers();
+        }
 
-        println!("{:04X} | {:?}", self.pc(), inst);
+        if self.trace || self.at_breakpoint {
+            println!("{:04X} | {:?}", self.pc(), inst);
+        }
+
+        // At a breakpoint every instruction waits for a line on stdin, `c` continues
         if self.at_breakpoint {
             let mut cmd = String::new();
-            ::std::io::stdin().read_line(&mut cmd);
-            let cmd = cmd.trim();
-            if cmd == "c" {
+            ::std::io::stdin().read_line(&mut cmd).expect("stdin");
+            if cmd.trim() == "c" {
                 self.at_breakpoint = false;
             }
         }
 
-        self.pc += Wrapping(inst.len());
-        let cycles = inst.cycles();
+        if self.halt_bug {
+            // The byte after HALT is read twice
+            self.halt_bug = false;
+            self.pc += Wrapping(inst.len() - 1);
+        } else {
+            self.pc += Wrapping(inst.len());
+        }
         inst.execute(self, mem);
+        let cycles = inst.cycles() + self.branch_cycles;
+        self.branch_cycles = 0;
 
-        if self.pc() == last_pc {
-            self.is_stalling = true;
+        if self.at_breakpoint {
+            self.print_registers();
         }
 
-        // if self.at_breakpoint {
-            self.print_registers();
-        // }
+        cycles
+    }
+
+    fn handle_interrupts(&mut self, mem: &mut Memory) -> Option<u16> {
+        if !self.interrupts_enabled {
+            return None;
+        }
+
+        let interrupt = mem.interrupts.pending()?;
+
+        self.interrupts_enabled = false;
+        mem.interrupts.acknowledge(interrupt);
+        mem.tick(4);
+        unborrow!(self.push_u16(mem, self.pc()));
+        self.set_pc(interrupt.vector());
+
+        Some(20)
     }
 
     pub fn add(&mut self, amount: u8) {
@@ -208,11 +301,29 @@ impl Cpu {
     }
 
     pub fn add_carry(&mut self, amount: u8) {
-        let carry = self.f() >> 4 & 0b1;
-        self.add(amount);
-        let f = self.f();
-        self.add(carry);
-        unborrow!(self.set_f(f | self.f()));
+        let a = self.a();
+        let carry = self.flag_c() as u8;
+        let result = a as u16 + amount as u16 + carry as u16;
+        self.set_a(result as u8);
+
+        unborrow!(self.set_flag_z(self.a() == 0));
+        self.set_flag_n(false);
+        self.set_flag_h((a & 0xF) + (amount & 0xF) + carry > 0xF);
+        self.set_flag_c(result > 0xFF);
+    }
+
+    /// Adds a signed offset to SP and returns the result without storing it.
+    /// Shared by `ADD SP,n` and `LD HL,SP+n`, which set flags identically.
+    pub fn add_sp_offset(&mut self, offset: i8) -> u16 {
+        let sp = self.sp();
+        let offset = offset as i16 as u16;
+
+        self.set_flag_z(false);
+        self.set_flag_n(false);
+        self.set_flag_h((sp & 0xF) + (offset & 0xF) > 0xF);
+        self.set_flag_c((sp & 0xFF) + (offset & 0xFF) > 0xFF);
+
+        sp.wrapping_add(offset)
     }
 
     pub fn sub(&mut self, amount: u8) {
@@ -225,6 +336,18 @@ impl Cpu {
         self.set_flag_c(a < amount);
     }
 
+    pub fn sub_carry(&mut self, amount: u8) {
+        let a = self.a();
+        let carry = self.flag_c() as u8;
+        let result = a.wrapping_sub(amount).wrapping_sub(carry);
+        self.set_a(result);
+
+        unborrow!(self.set_flag_z(self.a() == 0));
+        self.set_flag_n(true);
+        self.set_flag_h((a & 0xF) < (amount & 0xF) + carry);
+        self.set_flag_c((a as u16) < amount as u16 + carry as u16);
+    }
+
     pub fn compare(&mut self, value: u8) {
         let a = self.a();
         unborrow!(self.set_flag_z(a == value));
@@ -268,19 +391,27 @@ impl Cpu {
         unborrow!(self.incr_affect_flags(self.l() as u16));
     }
 
-    pub fn incr_bc(&mut self) {
+    // 16 bit increments and decrements don't affect any flags,
+    // but their internal cycle puts the register on the address bus
+
+    pub fn incr_bc(&mut self, mem: &mut Memory) {
+        mem.tick_inc_dec(Addr(self.bc()));
         unborrow!(self.set_bc(self.bc().wrapping_add(1)));
-        unborrow!(self.incr_affect_flags(self.bc()));
     }
 
-    pub fn incr_de(&mut self) {
+    pub fn incr_de(&mut self, mem: &mut Memory) {
+        mem.tick_inc_dec(Addr(self.de()));
         unborrow!(self.set_de(self.de().wrapping_add(1)));
-        unborrow!(self.incr_affect_flags(self.de()));
     }
 
-    pub fn incr_hl(&mut self) {
+    pub fn incr_hl(&mut self, mem: &mut Memory) {
+        mem.tick_inc_dec(Addr(self.hl()));
         unborrow!(self.set_hl(self.hl().wrapping_add(1)));
-        unborrow!(self.incr_affect_flags(self.hl()));
+    }
+
+    pub fn incr_sp(&mut self, mem: &mut Memory) {
+        mem.tick_inc_dec(Addr(self.sp()));
+        self.sp += Wrapping(1);
     }
 
     pub fn incr_hl_without_affecting_flags(&mut self) {
@@ -340,9 +471,24 @@ impl Cpu {
         unborrow!(self.decr_affect_flags(self.l() as u16));
     }
 
-    pub fn decr_hl(&mut self) {
+    pub fn decr_bc(&mut self, mem: &mut Memory) {
+        mem.tick_inc_dec(Addr(self.bc()));
+        unborrow!(self.set_bc(self.bc().wrapping_sub(1)));
+    }
+
+    pub fn decr_de(&mut self, mem: &mut Memory) {
+        mem.tick_inc_dec(Addr(self.de()));
+        unborrow!(self.set_de(self.de().wrapping_sub(1)));
+    }
+
+    pub fn decr_hl(&mut self, mem: &mut Memory) {
+        mem.tick_inc_dec(Addr(self.hl()));
         unborrow!(self.set_hl(self.hl().wrapping_sub(1)));
-        unborrow!(self.decr_affect_flags(self.hl()));
+    }
+
+    pub fn decr_sp(&mut self, mem: &mut Memory) {
+        mem.tick_inc_dec(Addr(self.sp()));
+        self.sp -= Wrapping(1);
     }
 
     pub fn decr_mhl(&mut self, mem: &mut Memory) {
@@ -383,65 +529,215 @@ impl Cpu {
         self.set_flag_c(false);
     }
 
-    pub fn shift_right_logical_b(&mut self) {
-        let msb = self.b.0 & 1;
-        self.b >>= 1;
+    pub fn complement_a(&mut self) {
+        self.a ^= Wrapping(0xFF);
+        self.set_flag_n(true);
+        self.set_flag_h(true);
+    }
+
+    pub fn decimal_adjust_a(&mut self) {
+        let mut a = self.a();
+        let mut carry = self.flag_c();
 
-        unborrow!(self.set_flag_z(self.l() == 0));
+        if !self.flag_n() {
+            if carry || a > 0x99 {
+                a = a.wrapping_add(0x60);
+                carry = true;
+            }
+            if self.flag_h() || a & 0x0F > 0x09 {
+                a = a.wrapping_add(0x06);
+            }
+        } else {
+            if carry {
+                a = a.wrapping_sub(0x60);
+            }
+            if self.flag_h() {
+                a = a.wrapping_sub(0x06);
+            }
+        }
+
+        self.set_a(a);
+        self.set_flag_z(a == 0);
+        self.set_flag_h(false);
+        self.set_flag_c(carry);
+    }
+
+    pub fn set_carry_flag(&mut self) {
         self.set_flag_n(false);
         self.set_flag_h(false);
-        self.set_flag_c(msb == 1);
+        self.set_flag_c(true);
+    }
+
+    pub fn complement_carry_flag(&mut self) {
+        self.set_flag_n(false);
+        self.set_flag_h(false);
+        unborrow!(self.set_flag_c(!self.flag_c()));
+    }
+
+    // The accumulator rotates (RLCA, RLA, RRCA, RRA) always reset Z
+
+    pub fn rotate_left_circular_a(&mut self) {
+        unborrow!(self.set_a(self.rotate_left_circular(self.a())));
+        self.set_flag_z(false);
+    }
+
+    pub fn rotate_left_a(&mut self) {
+        unborrow!(self.set_a(self.rotate_left(self.a())));
+        self.set_flag_z(false);
+    }
+
+    pub fn rotate_right_circular_a(&mut self) {
+        unborrow!(self.set_a(self.rotate_right_circular(self.a())));
+        self.set_flag_z(false);
     }
 
     pub fn rotate_right_a(&mut self) {
-        let new_carry = self.a.0 & 1;
-        self.a >>= 1;
-        self.a.0 |= (self.flag_c() as u8) << 7;
+        unborrow!(self.set_a(self.rotate_right(self.a())));
+        self.set_flag_z(false);
+    }
+
+    // The CB prefixed rotates and shifts take the operand by value
+    // and return the result, so they work for registers and (HL) alike
 
-        unborrow!(self.rotate_right_affect_flags(self.a.0 == 0, new_carry == 1));
+    pub fn rotate_left_circular(&mut self, value: u8) -> u8 {
+        let result = value.rotate_left(1);
+        self.rotate_affect_flags(result == 0, value >> 7 == 1);
+        result
     }
 
-    pub fn rotate_right_c(&mut self) {
-        let new_carry = self.c.0 & 1;
-        self.c >>= 1;
-        self.c.0 |= (self.flag_c() as u8) << 7;
+    pub fn rotate_right_circular(&mut self, value: u8) -> u8 {
+        let result = value.rotate_right(1);
+        self.rotate_affect_flags(result == 0, value & 1 == 1);
+        result
+    }
 
-        unborrow!(self.rotate_right_affect_flags(self.c.0 == 0, new_carry == 1));
+    pub fn rotate_left(&mut self, value: u8) -> u8 {
+        let result = value << 1 | self.flag_c() as u8;
+        self.rotate_affect_flags(result == 0, value >> 7 == 1);
+        result
     }
 
-    pub fn rotate_right_d(&mut self) {
-        let new_carry = self.d.0 & 1;
-        self.d >>= 1;
-        self.d.0 |= (self.flag_c() as u8) << 7;
+    pub fn rotate_right(&mut self, value: u8) -> u8 {
+        let result = value >> 1 | (self.flag_c() as u8) << 7;
+        self.rotate_affect_flags(result == 0, value & 1 == 1);
+        result
+    }
 
-        unborrow!(self.rotate_right_affect_flags(self.d.0 == 0, new_carry == 1));
+    pub fn shift_left_arithmetic(&mut self, value: u8) -> u8 {
+        let result = value << 1;
+        self.rotate_affect_flags(result == 0, value >> 7 == 1);
+        result
     }
 
-    pub fn rotate_right_e(&mut self) {
-        let new_carry = self.e.0 & 1;
-        self.e >>= 1;
-        self.e.0 |= (self.flag_c() as u8) << 7;
+    pub fn shift_right_arithmetic(&mut self, value: u8) -> u8 {
+        let result = value >> 1 | value & 0x80;
+        self.rotate_affect_flags(result == 0, value & 1 == 1);
+        result
+    }
 
-        unborrow!(self.rotate_right_affect_flags(self.e.0 == 0, new_carry == 1));
+    pub fn shift_right_logical(&mut self, value: u8) -> u8 {
+        let result = value >> 1;
+        self.rotate_affect_flags(result == 0, value & 1 == 1);
+        result
     }
 
-    fn rotate_right_affect_flags(&mut self, z: bool, c: bool) {
+    pub fn swap(&mut self, value: u8) -> u8 {
+        let result = value.rotate_left(4);
+        self.rotate_affect_flags(result == 0, false);
+        result
+    }
+
+    fn rotate_affect_flags(&mut self, z: bool, c: bool) {
         self.set_flag_z(z);
         self.set_flag_n(false);
         self.set_flag_h(false);
         self.set_flag_c(c);
     }
 
+    pub fn test_bit(&mut self, bit: u8, value: u8) {
+        self.set_flag_z(value >> bit & 1 == 0);
+        self.set_flag_n(false);
+        self.set_flag_h(true);
+    }
+
     pub fn call(&mut self, mem: &mut Memory, addr: u16) {
         unborrow!(self.push_u16(mem, self.pc()));
         self.set_pc(addr);
     }
 
+    // Taken conditional branches need extra cycles on top of
+    // the ones listed in the instruction table
+
+    pub fn jump_if(&mut self, condition: bool, addr: u16) {
+        if condition {
+            self.set_pc(addr);
+            self.branch_cycles = 4;
+        }
+    }
+
+    pub fn jump_routine_if(&mut self, condition: bool, offset: i8) {
+        if condition {
+            self.jump_routine(offset);
+            self.branch_cycles = 4;
+        }
+    }
+
+    pub fn call_if(&mut self, mem: &mut Memory, condition: bool, addr: u16) {
+        if condition {
+            self.call(mem, addr);
+            self.branch_cycles = 12;
+        }
+    }
+
+    pub fn return_if(&mut self, mem: &mut Memory, condition: bool) {
+        // The condition is checked during an internal cycle
+        mem.tick(4);
+
+        if condition {
+            unborrow!(self.set_pc(self.pop_u16(mem)));
+            self.branch_cycles = 12;
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Whether the CPU is halted without any interrupt enabled that could wake it up.
+    pub fn is_locked_up(&self, mem: &Memory) -> bool {
+        self.state == Halted && mem.interrupts.read_enable() & 0b11111 == 0
+    }
+
+    pub fn halt(&mut self, mem: &mut Memory) {
+        if !self.interrupts_enabled && mem.interrupts.pending().is_some() {
+            // DMG HALT bug: with IME off and an interrupt already pending
+            // the CPU doesn't halt, but the next opcode is read twice
+            self.halt_bug = true;
+        } else {
+            self.state = Halted;
+        }
+    }
+
+    pub fn stop(&mut self, mem: &mut Memory) {
+        // STOP resets the divider
+        mem.timer.write(0xFF04, 0);
+
+        // On CGB a STOP with a prepared speed switch changes speed instead of stopping
+        if !mem.switch_speed() {
+            self.state = Stopped;
+        }
+    }
+
     pub fn disable_interrupts(&mut self) {
         self.interrupts_enabled = false;
+        self.enable_interrupts_pending = false;
     }
 
     pub fn enable_interrupts(&mut self) {
+        self.enable_interrupts_pending = true;
+    }
+
+    pub fn enable_interrupts_immediately(&mut self) {
         self.interrupts_enabled = true;
     }
 
@@ -453,7 +749,7 @@ impl Cpu {
         if offset >= 0 {
             unborrow!(self.set_pc(self.pc().wrapping_add( offset as u16)))
         } else {
-            unborrow!(self.set_pc(self.pc().wrapping_sub(offset.abs() as u16)))
+            unborrow!(self.set_pc(self.pc().wrapping_sub(offset.unsigned_abs() as u16)))
         }
     }
 
@@ -466,7 +762,8 @@ impl Cpu {
         println!(r"| sp: {:04X}", self.sp());
         println!(r"| pc: {:04X}", self.pc());
         println!(r"| z?: {}", self.flag_z());
-        println!("");
+        println!(r"| ime: {}", self.interrupts_enabled());
+        println!();
 
     }
 
@@ -522,3 +819,296 @@ impl Cpu {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rom::Rom;
+
+    // Runs one instruction and checks that the system advanced by the cycles it took
+    fn step_cycles(cpu: &mut Cpu, mem: &mut Memory) -> u16 {
+        let start = mem.cycles();
+        let cycles = cpu.step(mem);
+        assert_eq!(mem.cycles() - start, cycles as u64);
+        cycles
+    }
+
+    #[test]
+    fn add_carry_sets_half_carry_and_carry_including_carry_in() {
+        let mut cpu = Cpu::new();
+
+        cpu.set_a(0x0F);
+        cpu.set_flag_c(true);
+        cpu.add_carry(0x00);
+        assert_eq!(cpu.a(), 0x10);
+        //                   ZNHC
+        assert_eq!(cpu.f(), 0b0010_0000);
+
+        cpu.set_a(0xE1);
+        cpu.set_flag_c(true);
+        cpu.add_carry(0x1E);
+        assert_eq!(cpu.a(), 0x00);
+        assert_eq!(cpu.f(), 0b1011_0000);
+
+        cpu.set_a(0x12);
+        cpu.set_flag_c(false);
+        cpu.add_carry(0x34);
+        assert_eq!(cpu.a(), 0x46);
+        assert_eq!(cpu.f(), 0b0000_0000);
+    }
+
+    #[test]
+    fn sub_carry_sets_half_borrow_and_borrow_including_carry_in() {
+        let mut cpu = Cpu::new();
+
+        cpu.set_a(0x10);
+        cpu.set_flag_c(true);
+        cpu.sub_carry(0x00);
+        assert_eq!(cpu.a(), 0x0F);
+        //                   ZNHC
+        assert_eq!(cpu.f(), 0b0110_0000);
+
+        cpu.set_a(0x00);
+        cpu.set_flag_c(true);
+        cpu.sub_carry(0xFF);
+        assert_eq!(cpu.a(), 0x00);
+        assert_eq!(cpu.f(), 0b1111_0000);
+
+        cpu.set_a(0x46);
+        cpu.set_flag_c(false);
+        cpu.sub_carry(0x12);
+        assert_eq!(cpu.a(), 0x34);
+        assert_eq!(cpu.f(), 0b0100_0000);
+    }
+
+    #[test]
+    fn add_sp_offset_carries_from_the_low_byte() {
+        let mut cpu = Cpu::new();
+
+        cpu.set_sp(0xFFF8);
+        assert_eq!(cpu.add_sp_offset(0x08), 0x0000);
+        //                   ZNHC
+        assert_eq!(cpu.f(), 0b0011_0000);
+
+        cpu.set_sp(0x0001);
+        assert_eq!(cpu.add_sp_offset(-1), 0x0000);
+        assert_eq!(cpu.f(), 0b0011_0000);
+
+        cpu.set_sp(0x1000);
+        assert_eq!(cpu.add_sp_offset(-1), 0x0FFF);
+        assert_eq!(cpu.f(), 0b0000_0000);
+
+        // SP itself is left alone
+        assert_eq!(cpu.sp(), 0x1000);
+    }
+
+    #[test]
+    fn decimal_adjust_a_after_add_and_sub() {
+        let mut cpu = Cpu::new();
+
+        cpu.set_a(0x15);
+        cpu.add(0x27);
+        cpu.decimal_adjust_a();
+        assert_eq!(cpu.a(), 0x42);
+        //                   ZNHC
+        assert_eq!(cpu.f(), 0b0000_0000);
+
+        cpu.set_a(0x99);
+        cpu.add(0x01);
+        cpu.decimal_adjust_a();
+        assert_eq!(cpu.a(), 0x00);
+        assert_eq!(cpu.f(), 0b1001_0000);
+
+        cpu.set_a(0x42);
+        cpu.sub(0x15);
+        cpu.decimal_adjust_a();
+        assert_eq!(cpu.a(), 0x27);
+        assert_eq!(cpu.f(), 0b0100_0000);
+
+        cpu.set_a(0x10);
+        cpu.sub(0x20);
+        cpu.decimal_adjust_a();
+        assert_eq!(cpu.a(), 0x90);
+        assert_eq!(cpu.f(), 0b0101_0000);
+    }
+
+    #[test]
+    fn add_hl_carries_from_bits_11_and_15_and_keeps_z() {
+        let mut cpu = Cpu::new();
+
+        cpu.set_flag_z(true);
+        cpu.set_hl(0x0FFF);
+        cpu.add_hl(0x0001);
+        assert_eq!(cpu.hl(), 0x1000);
+        //                   ZNHC
+        assert_eq!(cpu.f(), 0b1010_0000);
+
+        cpu.set_flag_z(false);
+        cpu.set_hl(0xFFFF);
+        cpu.add_hl(0x0001);
+        assert_eq!(cpu.hl(), 0x0000);
+        assert_eq!(cpu.f(), 0b0011_0000);
+
+        cpu.set_hl(0x00FF);
+        cpu.add_hl(0x0001);
+        assert_eq!(cpu.hl(), 0x0100);
+        assert_eq!(cpu.f(), 0b0000_0000);
+    }
+
+    #[test]
+    fn taken_branches_take_longer() {
+        // JP NZ,nn; JR NZ,e; CALL NZ,nn; RET NZ, not taken and taken
+        let branches: [(&[u8], u16, u16); 4] = [
+            (&[0xC2, 0x00, 0x02], 12, 16),
+            (&[0x20, 0x10], 8, 12),
+            (&[0xC4, 0x00, 0x02], 12, 24),
+            (&[0xC0], 8, 20),
+        ];
+
+        for &(program, not_taken, taken) in &branches {
+            for &(z, cycles) in &[(true, not_taken), (false, taken)] {
+                let mut mem = Memory::new(Rom::with_program(program));
+                let mut cpu = Cpu::new();
+                cpu.set_sp(0xDFF0);
+                mem.write_u8_untimed(Addr(0xDFF0), 0x00);
+                mem.write_u8_untimed(Addr(0xDFF1), 0x02);
+                cpu.set_flag_z(z);
+
+                assert_eq!(step_cycles(&mut cpu, &mut mem), cycles, "{:02X?} with Z = {}", program, z);
+            }
+        }
+    }
+
+    #[test]
+    fn cb_operations_on_hl_take_longer() {
+        // BIT only reads (HL), RLC, RES and SET write it back
+        let operations: [(u8, u16, u8); 4] = [
+            (0x46, 12, 0x80), // BIT 0,(HL)
+            (0x06, 16, 0x01), // RLC (HL)
+            (0xBE, 16, 0x00), // RES 7,(HL)
+            (0xC6, 16, 0x81), // SET 0,(HL)
+        ];
+
+        for &(opcode, cycles, result) in &operations {
+            let mut mem = Memory::new(Rom::with_program(&[0xCB, opcode]));
+            let mut cpu = Cpu::new();
+            cpu.set_hl(0xC000);
+            mem.write_u8_untimed(Addr(0xC000), 0x80);
+
+            assert_eq!(step_cycles(&mut cpu, &mut mem), cycles, "CB {:02X}", opcode);
+            assert_eq!(mem.read_u8_untimed(Addr(0xC000)), result, "CB {:02X}", opcode);
+            assert_eq!(cpu.pc(), 0x0102);
+        }
+    }
+
+    #[test]
+    fn ei_takes_effect_after_the_next_instruction() {
+        // EI; NOP; NOP
+        let mut mem = Memory::new(Rom::with_program(&[0xFB, 0x00, 0x00]));
+        let mut cpu = Cpu::new();
+        mem.interrupts.write_enable(Interrupt::Timer.bit());
+        mem.interrupts.write_flags(Interrupt::Timer.bit());
+
+        cpu.step(&mut mem);
+        assert!(!cpu.interrupts_enabled());
+        cpu.step(&mut mem);
+        assert!(cpu.interrupts_enabled());
+        assert_eq!(cpu.pc(), 0x0102);
+
+        cpu.step(&mut mem);
+        assert_eq!(cpu.pc(), Interrupt::Timer.vector());
+    }
+
+    #[test]
+    fn dispatch_pushes_pc_and_jumps_to_the_vector() {
+        let mut mem = Memory::new(Rom::with_program(&[0x00]));
+        let mut cpu = Cpu::new();
+        cpu.set_sp(0xDFF0);
+        cpu.enable_interrupts_immediately();
+        mem.interrupts.write_enable(Interrupt::Serial.bit());
+        mem.interrupts.write_flags(Interrupt::Serial.bit());
+
+        assert_eq!(step_cycles(&mut cpu, &mut mem), 20);
+        assert_eq!(cpu.pc(), 0x58);
+        assert_eq!(cpu.sp(), 0xDFEE);
+        assert_eq!(mem.read_u8_untimed(Addr(0xDFEF)), 0x01);
+        assert_eq!(mem.read_u8_untimed(Addr(0xDFEE)), 0x00);
+        assert!(!cpu.interrupts_enabled());
+        assert!(!mem.interrupts.is_requested(Interrupt::Serial));
+    }
+
+    #[test]
+    fn dispatch_picks_the_lowest_bit_of_ie_and_if() {
+        let cases = [
+            (0x1F, 0x1E, Interrupt::LcdStat),
+            (0x1C, 0x1E, Interrupt::Timer),
+            (0x18, 0x1A, Interrupt::Serial),
+            (0x11, 0x10, Interrupt::Joypad),
+        ];
+
+        for &(enable, flags, interrupt) in &cases {
+            let mut mem = Memory::new(Rom::with_program(&[0x00]));
+            let mut cpu = Cpu::new();
+            cpu.enable_interrupts_immediately();
+            mem.interrupts.write_enable(enable);
+            mem.interrupts.write_flags(flags);
+
+            cpu.step(&mut mem);
+            assert_eq!(cpu.pc(), interrupt.vector(), "IE = {:02X}, IF = {:02X}", enable, flags);
+            assert_eq!(mem.interrupts.read_flags() & 0x1F, flags & !interrupt.bit());
+        }
+    }
+
+    #[test]
+    fn halt_wakes_up_without_dispatch_when_ime_is_off() {
+        // HALT; INC B
+        let mut mem = Memory::new(Rom::with_program(&[0x76, 0x04]));
+        let mut cpu = Cpu::new();
+        mem.interrupts.write_enable(Interrupt::Timer.bit());
+        mem.interrupts.write_flags(0);
+
+        cpu.step(&mut mem);
+        assert_eq!(cpu.state(), Halted);
+        assert_eq!(step_cycles(&mut cpu, &mut mem), 4);
+        assert_eq!(cpu.state(), Halted);
+
+        // A requested interrupt that isn't enabled doesn't wake it up
+        mem.interrupts.request(Interrupt::VBlank);
+        cpu.step(&mut mem);
+        assert_eq!(cpu.state(), Halted);
+
+        mem.interrupts.request(Interrupt::Timer);
+        let b = cpu.b();
+        cpu.step(&mut mem);
+        assert_eq!(cpu.state(), Running);
+        assert_eq!(cpu.b(), b.wrapping_add(1));
+        assert_eq!(cpu.pc(), 0x0102);
+        assert!(mem.interrupts.is_requested(Interrupt::Timer));
+    }
+
+    #[test]
+    fn halt_bug_reads_opcode_as_operand() {
+        // HALT; LD A,0x04 where 0x04 is INC B
+        let mut mem = Memory::new(Rom::with_program(&[0x76, 0x3E, 0x04]));
+        let mut cpu = Cpu::new();
+
+        // IME is off with an interrupt pending, so HALT doesn't halt
+        mem.interrupts.write_enable(Interrupt::Timer.bit());
+        mem.interrupts.request(Interrupt::Timer);
+
+        cpu.step(&mut mem);
+        assert_eq!(cpu.state(), Running);
+        assert_eq!(cpu.pc(), 0x0101);
+
+        // LD A,n reads its own opcode as the operand
+        cpu.step(&mut mem);
+        assert_eq!(cpu.a(), 0x3E);
+        assert_eq!(cpu.pc(), 0x0102);
+
+        // and the actual operand runs as an instruction
+        let b = cpu.b();
+        cpu.step(&mut mem);
+        assert_eq!(cpu.b(), b.wrapping_add(1));
+        assert_eq!(cpu.pc(), 0x0103);
+    }
+}
@@ -0,0 +1,160 @@
+use self::State::*;
+
+pub const OAM_DMA_LENGTH: u16 = 0xA0;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum State {
+    Idle,
+    Starting,
+    Transferring(u16),
+}
+
+/// OAM DMA (0xFF46).
+///
+/// Copies 160 bytes from XX00 - XX9F into OAM, one byte per M-cycle,
+/// after a one M-cycle startup delay.
+pub struct Dma {
+    source: u8,
+    state: State,
+    last_byte: u8,
+}
+
+impl Dma {
+    pub fn new() -> Dma {
+        Dma {
+            source: 0xFF,
+            state: Idle,
+            last_byte: 0xFF,
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        self.source
+    }
+
+    pub fn start(&mut self, source: u8) {
+        self.source = source;
+        self.state = Starting;
+    }
+
+    /// Whether bytes are being copied, during which the CPU can only access HRAM and I/O.
+    pub fn is_active(&self) -> bool {
+        match self.state {
+            Transferring(_) => true,
+            Idle | Starting => false,
+        }
+    }
+
+    /// The byte currently on the bus, returned to conflicting CPU reads.
+    pub fn last_byte(&self) -> u8 {
+        self.last_byte
+    }
+
+    /// Advances by one M-cycle. Returns the source address and
+    /// OAM offset of the byte to copy during this cycle, if any.
+    pub fn step(&mut self) -> Option<(u16, u16)> {
+        match self.state {
+            Idle => None,
+            Starting => {
+                self.state = Transferring(0);
+                None
+            },
+            Transferring(offset) => {
+                self.state = if offset + 1 == OAM_DMA_LENGTH { Idle } else { Transferring(offset + 1) };
+
+                // Sources above 0xDFFF read from work RAM
+                let mut source = (self.source as u16) << 8 | offset;
+                if source >= 0xE000 {
+                    source -= 0x2000;
+                }
+
+                Some((source, offset))
+            },
+        }
+    }
+
+    pub fn set_last_byte(&mut self, value: u8) {
+        self.last_byte = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rom::Rom;
+    use memory::{Memory, Addr};
+
+    #[test]
+    fn copies_160_bytes_after_a_startup_cycle() {
+        let mut dma = Dma::new();
+        dma.start(0xC1);
+
+        assert_eq!(dma.step(), None);
+        assert!(dma.is_active());
+
+        for offset in 0 .. OAM_DMA_LENGTH {
+            assert!(dma.is_active());
+            assert_eq!(dma.step(), Some((0xC100 + offset, offset)));
+        }
+
+        assert!(!dma.is_active());
+        assert_eq!(dma.step(), None);
+    }
+
+    #[test]
+    fn sources_above_dfff_read_work_ram() {
+        let mut dma = Dma::new();
+        dma.start(0xFE);
+        dma.step();
+
+        assert_eq!(dma.step(), Some((0xDE00, 0)));
+    }
+
+    // Starts a transfer from 0xC000, which holds 0x10, 0x11, ...
+    fn transferring_memory() -> Memory {
+        let mut mem = Memory::new(Rom::with_program(&[]));
+        // LCD off, so OAM can be read back
+        mem.write_u8_untimed(Addr(0xFF40), 0x00);
+
+        for offset in 0 .. OAM_DMA_LENGTH {
+            mem.write_u8_untimed(Addr(0xC000 + offset), 0x10 + offset as u8);
+        }
+
+        mem.write_u8(Addr(0xFF46), 0xC0);
+        mem
+    }
+
+    #[test]
+    fn transfer_takes_160_cycles() {
+        let mut mem = transferring_memory();
+
+        mem.tick(4);
+        for _ in 0 .. OAM_DMA_LENGTH {
+            assert!(mem.dma.is_active());
+            mem.tick(4);
+        }
+        assert!(!mem.dma.is_active());
+
+        for offset in 0 .. OAM_DMA_LENGTH {
+            assert_eq!(mem.read_u8_untimed(Addr(0xFE00 + offset)), 0x10 + offset as u8);
+        }
+    }
+
+    #[test]
+    fn cpu_can_only_access_hram_during_transfer() {
+        let mut mem = transferring_memory();
+        mem.tick(4);
+
+        // Each access waits for the byte of its M-cycle to be copied
+        assert_eq!(mem.read_u8(Addr(0xC080)), 0x10);
+        assert_eq!(mem.read_u8(Addr(0x0000)), 0x11);
+        assert_eq!(mem.read_u8(Addr(0xFE00)), 0xFF);
+
+        mem.write_u8(Addr(0xC080), 0x00);
+        mem.write_u8(Addr(0xFF80), 0x42);
+        assert_eq!(mem.read_u8(Addr(0xFF80)), 0x42);
+
+        mem.tick(OAM_DMA_LENGTH * 4);
+        assert_eq!(mem.read_u8(Addr(0xC080)), 0x90);
+    }
+}
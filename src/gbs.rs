@@ -0,0 +1,342 @@
+use std::path::Path;
+use std::io::{self, Read};
+use std::fs::File;
+use byteorder::{ByteOrder, LittleEndian};
+use rom::Rom;
+use cpu::Cpu;
+use memory::{Memory, Model, Addr};
+use wav::WavWriter;
+
+const HEADER_SIZE: usize = 0x70;
+const MIN_LOAD_ADDR: u16 = 0x400;
+// Routines are called with this address on the stack, so returning from them jumps here
+const RETURN_ADDR: u16 = 0x00F0;
+const VBLANK_PERIOD: u64 = 70224;
+const TIMER_CLOCKS: [u64; 4] = [1024, 16, 64, 256];
+
+/// A GBS (Game Boy Sound) file: the music code of a game
+/// plus the addresses needed to drive it.
+pub struct Gbs {
+    pub song_count: u8,
+    pub first_song: u8,
+    pub load_addr: u16,
+    pub init_addr: u16,
+    pub play_addr: u16,
+    pub stack_pointer: u16,
+    pub timer_modulo: u8,
+    pub timer_control: u8,
+    pub title: String,
+    pub author: String,
+    pub copyright: String,
+    code: Vec<u8>,
+}
+
+impl Gbs {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Gbs> {
+        let mut data = Vec::new();
+        let mut file = File::open(path)?;
+        file.read_to_end(&mut data)?;
+
+        Gbs::parse(data)
+    }
+
+    pub fn parse(data: Vec<u8>) -> io::Result<Gbs> {
+        if data.len() < HEADER_SIZE || &data[0..3] != b"GBS" || data[3] != 1 {
+            return Err(invalid_data("not a version 1 GBS file"));
+        }
+
+        let gbs = Gbs {
+            song_count: data[0x04],
+            first_song: data[0x05],
+            load_addr: LittleEndian::read_u16(&data[0x06..0x08]),
+            init_addr: LittleEndian::read_u16(&data[0x08..0x0A]),
+            play_addr: LittleEndian::read_u16(&data[0x0A..0x0C]),
+            stack_pointer: LittleEndian::read_u16(&data[0x0C..0x0E]),
+            timer_modulo: data[0x0E],
+            timer_control: data[0x0F],
+            title: header_string(&data[0x10..0x30]),
+            author: header_string(&data[0x30..0x50]),
+            copyright: header_string(&data[0x50..0x70]),
+            code: data[HEADER_SIZE..].to_vec(),
+        };
+
+        if gbs.load_addr < MIN_LOAD_ADDR || gbs.load_addr as usize + gbs.code.len() > 0x400000 {
+            return Err(invalid_data("GBS load address out of range"));
+        }
+
+        Ok(gbs)
+    }
+
+    /// Whether the tune runs with the CPU in CGB double speed mode.
+    pub fn double_speed(&self) -> bool {
+        self.timer_control & 0x80 != 0
+    }
+
+    /// Cycles at the normal speed between calls of the play routine,
+    /// set by either the timer registers or the vblank rate.
+    pub fn play_period(&self) -> u64 {
+        if self.timer_control & 0x04 == 0 {
+            return VBLANK_PERIOD;
+        }
+
+        let period = TIMER_CLOCKS[self.timer_control as usize & 0x03] * (256 - self.timer_modulo as u64);
+
+        // Double speed runs the timer twice as fast
+        if self.double_speed() { period / 2 } else { period }
+    }
+
+    /// Builds an MBC5 cartridge image with 8 KiB of RAM and the code at its load address.
+    /// RST vectors jump to their counterparts relative to the load address.
+    pub fn to_rom(&self) -> Rom {
+        let end = self.load_addr as usize + self.code.len();
+        let size = end.max(0x8000).next_power_of_two();
+        let mut data = vec![0; size];
+
+        data[self.load_addr as usize .. end].copy_from_slice(&self.code);
+
+        for vector in (0x00 .. 0x40).step_by(8) {
+            let target = self.load_addr + vector as u16;
+            data[vector] = 0xC3; // JP nn
+            LittleEndian::write_u16(&mut data[vector + 1 .. vector + 3], target);
+        }
+
+        for vector in (0x40 .. 0x68).step_by(8) {
+            data[vector] = 0xD9; // RETI
+        }
+
+        // MBC5 takes any bank number written to 0x2000 - 0x2FFF as is
+        data[0x147] = 0x1A;
+        data[0x148] = (size / 0x8000).trailing_zeros() as u8;
+        data[0x149] = 0x02;
+
+        Rom { data: data }
+    }
+}
+
+fn header_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Plays a song of a GBS file by calling its init routine once and
+/// its play routine periodically. A routine that is still running
+/// is never interrupted by the next play call.
+///
+/// Tunes asking for double speed run on a CGB switched to double speed,
+/// where a second takes twice as many CPU cycles.
+pub struct Player {
+    gbs: Gbs,
+    cpu: Cpu,
+    memory: Memory,
+    next_play: u64,
+}
+
+impl Player {
+    pub fn new(gbs: Gbs, song: u8, sample_rate: u32) -> Player {
+        let model = if gbs.double_speed() { Model::Cgb } else { Model::Dmg };
+        let mut memory = Memory::with_model(gbs.to_rom(), model);
+        memory.apu.set_sample_rate(sample_rate);
+
+        if gbs.double_speed() {
+            memory.write_u8_untimed(Addr(0xFF4D), 0x01);
+            memory.switch_speed();
+        }
+
+        // Cartridge RAM is always available to GBS code
+        memory.write_u8_untimed(Addr(0x0000), 0x0A);
+
+        // The sound hardware is powered and fully panned when the init routine starts
+        memory.write_u8_untimed(Addr(0xFF26), 0x80);
+        memory.write_u8_untimed(Addr(0xFF25), 0xFF);
+        memory.write_u8_untimed(Addr(0xFF24), 0x77);
+        memory.write_u8_untimed(Addr(0xFF06), gbs.timer_modulo);
+        memory.write_u8_untimed(Addr(0xFF07), gbs.timer_control & 0x07);
+
+        let mut player = Player {
+            next_play: 0,
+            gbs: gbs,
+            cpu: Cpu::new(),
+            memory: memory,
+        };
+        player.next_play = player.play_period();
+
+        player.cpu.set_sp(player.gbs.stack_pointer);
+        player.cpu.set_a(song);
+        let init_addr = player.gbs.init_addr;
+        player.call(init_addr);
+
+        player
+    }
+
+    // The play period in CPU cycles, which pass twice as fast in double speed
+    fn play_period(&self) -> u64 {
+        let speed = if self.memory.double_speed() { 2 } else { 1 };
+        self.gbs.play_period() * speed
+    }
+
+    fn call(&mut self, addr: u16) {
+        self.cpu.push_u16(&mut self.memory, RETURN_ADDR);
+        self.cpu.set_pc(addr);
+    }
+
+    fn is_idle(&self) -> bool {
+        self.cpu.pc() == RETURN_ADDR
+    }
+
+    /// Runs for the given number of cycles.
+    pub fn run(&mut self, cycles: u64) {
+        let end = self.memory.cycles() + cycles;
+
+        while self.memory.cycles() < end {
+            if self.memory.cycles() >= self.next_play && self.is_idle() {
+                // Calls missed while a routine was still running are dropped
+                while self.next_play <= self.memory.cycles() {
+                    self.next_play += self.play_period();
+                }
+                let play_addr = self.gbs.play_addr;
+                self.call(play_addr);
+            }
+
+            if self.is_idle() || self.cpu.is_locked_up(&self.memory) {
+                self.memory.tick(4);
+            } else {
+                self.cpu.step(&mut self.memory);
+            }
+        }
+    }
+
+    /// Renders the given number of seconds of audio into `wav`.
+    pub fn render(&mut self, seconds: u32, wav: &mut WavWriter) -> io::Result<()> {
+        let mut samples = vec![0.0; 4096];
+
+        let speed = if self.memory.double_speed() { 2 } else { 1 };
+
+        for _ in 0 .. seconds {
+            self.run(::apu::CPU_CLOCK as u64 * speed);
+
+            while self.memory.apu.available_samples() > 0 {
+                let count = self.memory.apu.pull_samples(&mut samples);
+                wav.write_samples(&samples[..count])?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A header for code loaded at 0x0400, with init at 0x0400 and play at 0x0410
+    fn header(timer_modulo: u8, timer_control: u8) -> Vec<u8> {
+        let mut data = vec![0; HEADER_SIZE];
+        data[0..4].copy_from_slice(b"GBS\x01");
+        data[0x04] = 3;
+        data[0x05] = 2;
+        LittleEndian::write_u16(&mut data[0x06..0x08], 0x0400);
+        LittleEndian::write_u16(&mut data[0x08..0x0A], 0x0400);
+        LittleEndian::write_u16(&mut data[0x0A..0x0C], 0x0410);
+        LittleEndian::write_u16(&mut data[0x0C..0x0E], 0xFFFE);
+        data[0x0E] = timer_modulo;
+        data[0x0F] = timer_control;
+        data[0x10..0x15].copy_from_slice(b"Title");
+        data[0x30..0x36].copy_from_slice(b"Author");
+        data
+    }
+
+    fn gbs_with_code(timer_control: u8, code: &[u8]) -> Gbs {
+        let mut data = header(0, timer_control);
+        data.extend_from_slice(code);
+        Gbs::parse(data).unwrap()
+    }
+
+    #[test]
+    fn parses_header() {
+        let gbs = gbs_with_code(0, &[0xC9]);
+
+        assert_eq!(gbs.song_count, 3);
+        assert_eq!(gbs.first_song, 2);
+        assert_eq!(gbs.load_addr, 0x0400);
+        assert_eq!(gbs.init_addr, 0x0400);
+        assert_eq!(gbs.play_addr, 0x0410);
+        assert_eq!(gbs.stack_pointer, 0xFFFE);
+        assert_eq!(gbs.title, "Title");
+        assert_eq!(gbs.author, "Author");
+        assert_eq!(gbs.copyright, "");
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        assert!(Gbs::parse(b"GBS".to_vec()).is_err());
+
+        let mut data = header(0, 0);
+        data[0] = b'X';
+        assert!(Gbs::parse(data).is_err());
+
+        let mut data = header(0, 0);
+        data[3] = 2;
+        assert!(Gbs::parse(data).is_err());
+
+        let mut data = header(0, 0);
+        LittleEndian::write_u16(&mut data[0x06..0x08], 0x0200);
+        assert!(Gbs::parse(data).is_err());
+    }
+
+    #[test]
+    fn play_period() {
+        assert_eq!(Gbs::parse(header(0, 0)).unwrap().play_period(), VBLANK_PERIOD);
+        // 4096 Hz timer overflowing every 0x100 - 0xC0 = 64 clocks
+        assert_eq!(Gbs::parse(header(0xC0, 0x04)).unwrap().play_period(), 1024 * 64);
+        assert_eq!(Gbs::parse(header(0xC0, 0x84)).unwrap().play_period(), 1024 * 32);
+        assert_eq!(Gbs::parse(header(0xFF, 0x05)).unwrap().play_period(), 16);
+    }
+
+    #[test]
+    fn rom_layout() {
+        let rom = gbs_with_code(0, &[0xC9]).to_rom();
+
+        assert_eq!(rom.data.len(), 0x8000);
+        assert_eq!(rom.data[0x0400], 0xC9);
+        // RST 0x38 jumps to 0x0438
+        assert_eq!(&rom.data[0x38..0x3B], &[0xC3, 0x38, 0x04]);
+        assert_eq!(rom.data[0x40], 0xD9);
+        assert_eq!(rom.data[0x147], 0x1A);
+        assert_eq!(rom.data[0x149], 0x02);
+    }
+
+    #[test]
+    fn init_can_use_cartridge_ram() {
+        // LD A,0x42; LD (0xA000),A; LD A,(0xA000); LD (0xC000),A; RET
+        let code = [0x3E, 0x42, 0xEA, 0x00, 0xA0, 0xFA, 0x00, 0xA0, 0xEA, 0x00, 0xC0, 0xC9];
+        let mut player = Player::new(gbs_with_code(0, &code), 0, 44100);
+        player.run(1000);
+
+        assert_eq!(player.memory.read_u8_untimed(Addr(0xC000)), 0x42);
+    }
+
+    #[test]
+    fn banks_beyond_0x1f_can_be_selected() {
+        // LD A,0x21; LD (0x2000),A; LD A,(0x4000); LD (0xC000),A; RET
+        let mut code = vec![0x3E, 0x21, 0xEA, 0x00, 0x20, 0xFA, 0x00, 0x40, 0xEA, 0x00, 0xC0, 0xC9];
+        code.resize(0x22 * 0x4000 - 0x0400, 0);
+        code[0x21 * 0x4000 - 0x0400] = 0x5A;
+
+        let mut player = Player::new(gbs_with_code(0, &code), 0, 44100);
+        player.run(1000);
+
+        assert_eq!(player.memory.read_u8_untimed(Addr(0xC000)), 0x5A);
+    }
+
+    #[test]
+    fn double_speed_tunes_switch_speed() {
+        let player = Player::new(gbs_with_code(0x84, &[0xC9]), 0, 44100);
+
+        assert!(player.memory.double_speed());
+        assert_eq!(player.play_period(), 2 * 1024 * 256 / 2);
+    }
+}
@@ -7,24 +7,12 @@ pub const NINTENDO_LOGO: [u8; 0x30] = [
     0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
 ];
 
-pub fn entry_point(rom: &[u8]) -> &[u8] {
-    &rom[0x100..0x104]
-}
-
 pub fn logo(rom: &[u8]) -> &[u8] {
     &rom[0x104..0x0134]
 }
 
-pub fn title(rom: &[u8]) -> &[u8] {
-    &rom[0x134..0x144]
-}
-
-pub fn manufacturer(rom: &[u8]) -> &[u8] {
-    &rom[0x013F..0x143]
-}
-
-pub fn version(rom: &[u8]) -> u8 {
-    rom[0x14C]
+pub fn cgb_flag(rom: &[u8]) -> u8 {
+    rom[0x143]
 }
 
 pub fn typ(rom: &[u8]) -> u8 {
@@ -38,3 +26,13 @@ pub fn rom_size(rom: &[u8]) -> u8 {
 pub fn ram_size(rom: &[u8]) -> u8 {
     rom[0x149]
 }
+
+pub fn header_checksum(rom: &[u8]) -> u8 {
+    rom[0x14D]
+}
+
+/// Whether the header has the Nintendo logo and a matching checksum, like the boot ROM checks.
+pub fn is_valid(rom: &[u8]) -> bool {
+    let checksum = rom[0x134..0x14D].iter().fold(0u8, |x, &byte| x.wrapping_sub(byte).wrapping_sub(1));
+    logo(rom) == &NINTENDO_LOGO[..] && checksum == header_checksum(rom)
+}
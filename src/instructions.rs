@@ -1,10 +1,6 @@
-use byteorder::{ByteOrder, LittleEndian};
 use cpu::Cpu;
-use bit_range::BitRange;
 use memory::*;
 
-type LE = LittleEndian;
-
 // https://danielkeep.github.io/tlborm/book/pat-repetition-replacement.html
 macro_rules! replace_expr {
     ($_t:tt $sub:expr) => {$sub};
@@ -23,6 +19,8 @@ macro_rules! instructions {
         );+
         $(;)*
     ) => (
+        // Variants are named after the mnemonics, like LD_B_n
+        #[allow(non_camel_case_types)]
         #[derive(Copy, Clone, Debug, Eq, PartialEq)]
         pub enum $struct_name {
             $(
@@ -32,10 +30,18 @@ macro_rules! instructions {
 
         impl $struct_name {
             pub fn decode($mem: &mut Memory, $addr: Addr) -> $struct_name {
+                $struct_name::decode_with_operands_at($mem, $addr, $addr + 1)
+            }
+
+            /// Decodes the opcode at `addr` with its operands starting at `operands`,
+            /// which isn't the following byte when the HALT bug repeats the opcode.
+            // Tables without operands don't use `addr`, and the CB table covers every opcode
+            #[allow(dead_code, unused_mut, unused_variables, unreachable_patterns)]
+            pub fn decode_with_operands_at($mem: &mut Memory, $addr: Addr, operands: Addr) -> $struct_name {
                 use self::$struct_name::*;
 
                 let op = $mem.read_u8($addr);
-                let mut $addr = $addr + 1;
+                let mut $addr = operands;
 
                 match op {
                     $(
@@ -115,23 +121,12 @@ impl Param for ExtendedInstruction {
 instructions! {
     Instruction
     |cpu, mem, addr|
-    // op, len, cycles
+    // op, len, cycles (for conditional branches if not taken)
     0x00, 1,  4, NOP => {};
+    0x10, 2,  4, STOP => cpu.stop(mem);
+    0x76, 1,  4, HALT => cpu.halt(mem);
     0xFB, 1,  4, EI => cpu.enable_interrupts();
     0xF3, 1,  4, DI => cpu.disable_interrupts();
-    0xC3, 3, 12, JP_nn(pc: u16) => cpu.set_pc(pc);
-    0x7F, 1,  4, LD_A_A => unborrow!(cpu.set_a(cpu.a()));
-    0x78, 1,  4, LD_A_B => unborrow!(cpu.set_a(cpu.b()));
-    0x79, 1,  4, LD_A_C => unborrow!(cpu.set_a(cpu.c()));
-    0x7A, 1,  4, LD_A_D => unborrow!(cpu.set_a(cpu.d()));
-    0x7B, 1,  4, LD_A_E => unborrow!(cpu.set_a(cpu.e()));
-    0x7C, 1,  4, LD_A_H => unborrow!(cpu.set_a(cpu.h()));
-    0x7D, 1,  4, LD_A_L => unborrow!(cpu.set_a(cpu.l()));
-    0x1A, 1,  8, LD_A_MDE => unborrow!(cpu.set_a(mem.read_u8(Addr(cpu.de()))));
-    0x7E, 1,  8, LD_A_MHL => unborrow!(cpu.set_a(mem.read_u8(Addr(cpu.hl()))));
-    0x3E, 2,  8, LD_A_n(value: u8) => cpu.set_a(value);
-    0xFA, 3, 16, LD_A_Mnn(p: u16) => cpu.set_a(mem.read_u8(Addr(p)));
-    0x47, 1,  4, LD_B_A => unborrow!(cpu.set_b(cpu.a()));
     0x40, 1,  4, LD_B_B => unborrow!(cpu.set_b(cpu.b()));
     0x41, 1,  4, LD_B_C => unborrow!(cpu.set_b(cpu.c()));
     0x42, 1,  4, LD_B_D => unborrow!(cpu.set_b(cpu.d()));
@@ -139,8 +134,8 @@ instructions! {
     0x44, 1,  4, LD_B_H => unborrow!(cpu.set_b(cpu.h()));
     0x45, 1,  4, LD_B_L => unborrow!(cpu.set_b(cpu.l()));
     0x46, 1,  8, LD_B_MHL => unborrow!(cpu.set_b(mem.read_u8(Addr(cpu.hl()))));
+    0x47, 1,  4, LD_B_A => unborrow!(cpu.set_b(cpu.a()));
     0x06, 2,  8, LD_B_n(value: u8) => cpu.set_b(value);
-    0x4F, 1,  4, LD_C_A => unborrow!(cpu.set_c(cpu.a()));
     0x48, 1,  4, LD_C_B => unborrow!(cpu.set_c(cpu.b()));
     0x49, 1,  4, LD_C_C => unborrow!(cpu.set_c(cpu.c()));
     0x4A, 1,  4, LD_C_D => unborrow!(cpu.set_c(cpu.d()));
@@ -148,16 +143,17 @@ instructions! {
     0x4C, 1,  4, LD_C_H => unborrow!(cpu.set_c(cpu.h()));
     0x4D, 1,  4, LD_C_L => unborrow!(cpu.set_c(cpu.l()));
     0x4E, 1,  8, LD_C_MHL => unborrow!(cpu.set_c(mem.read_u8(Addr(cpu.hl()))));
-    0x57, 1,  4, LD_D_A => unborrow!(cpu.set_d(cpu.a()));
+    0x4F, 1,  4, LD_C_A => unborrow!(cpu.set_c(cpu.a()));
+    0x0E, 2,  8, LD_C_n(value: u8) => cpu.set_c(value);
     0x50, 1,  4, LD_D_B => unborrow!(cpu.set_d(cpu.b()));
     0x51, 1,  4, LD_D_C => unborrow!(cpu.set_d(cpu.c()));
     0x52, 1,  4, LD_D_D => unborrow!(cpu.set_d(cpu.d()));
     0x53, 1,  4, LD_D_E => unborrow!(cpu.set_d(cpu.e()));
     0x54, 1,  4, LD_D_H => unborrow!(cpu.set_d(cpu.h()));
     0x55, 1,  4, LD_D_L => unborrow!(cpu.set_d(cpu.l()));
-    0x0E, 2,  8, LD_C_n(value: u8) => cpu.set_c(value);
     0x56, 1,  8, LD_D_MHL => unborrow!(cpu.set_d(mem.read_u8(Addr(cpu.hl()))));
-    0x5F, 1,  4, LD_E_A => unborrow!(cpu.set_e(cpu.a()));
+    0x57, 1,  4, LD_D_A => unborrow!(cpu.set_d(cpu.a()));
+    0x16, 2,  8, LD_D_n(value: u8) => cpu.set_d(value);
     0x58, 1,  4, LD_E_B => unborrow!(cpu.set_e(cpu.b()));
     0x59, 1,  4, LD_E_C => unborrow!(cpu.set_e(cpu.c()));
     0x5A, 1,  4, LD_E_D => unborrow!(cpu.set_e(cpu.d()));
@@ -165,7 +161,8 @@ instructions! {
     0x5C, 1,  4, LD_E_H => unborrow!(cpu.set_e(cpu.h()));
     0x5D, 1,  4, LD_E_L => unborrow!(cpu.set_e(cpu.l()));
     0x5E, 1,  8, LD_E_MHL => unborrow!(cpu.set_e(mem.read_u8(Addr(cpu.hl()))));
-    0x67, 1,  4, LD_H_A => unborrow!(cpu.set_h(cpu.a()));
+    0x5F, 1,  4, LD_E_A => unborrow!(cpu.set_e(cpu.a()));
+    0x1E, 2,  8, LD_E_n(value: u8) => cpu.set_e(value);
     0x60, 1,  4, LD_H_B => unborrow!(cpu.set_h(cpu.b()));
     0x61, 1,  4, LD_H_C => unborrow!(cpu.set_h(cpu.c()));
     0x62, 1,  4, LD_H_D => unborrow!(cpu.set_h(cpu.d()));
@@ -173,8 +170,8 @@ instructions! {
     0x64, 1,  4, LD_H_H => unborrow!(cpu.set_h(cpu.h()));
     0x65, 1,  4, LD_H_L => unborrow!(cpu.set_h(cpu.l()));
     0x66, 1,  8, LD_H_MHL => unborrow!(cpu.set_h(mem.read_u8(Addr(cpu.hl()))));
+    0x67, 1,  4, LD_H_A => unborrow!(cpu.set_h(cpu.a()));
     0x26, 2,  8, LD_H_n(value: u8) => cpu.set_h(value);
-    0x6F, 1,  4, LD_L_A => unborrow!(cpu.set_l(cpu.a()));
     0x68, 1,  4, LD_L_B => unborrow!(cpu.set_l(cpu.b()));
     0x69, 1,  4, LD_L_C => unborrow!(cpu.set_l(cpu.c()));
     0x6A, 1,  4, LD_L_D => unborrow!(cpu.set_l(cpu.d()));
@@ -182,26 +179,45 @@ instructions! {
     0x6C, 1,  4, LD_L_H => unborrow!(cpu.set_l(cpu.h()));
     0x6D, 1,  4, LD_L_L => unborrow!(cpu.set_l(cpu.l()));
     0x6E, 1,  8, LD_L_MHL => unborrow!(cpu.set_l(mem.read_u8(Addr(cpu.hl()))));
+    0x6F, 1,  4, LD_L_A => unborrow!(cpu.set_l(cpu.a()));
     0x2E, 2,  8, LD_L_n(value: u8) => cpu.set_l(value);
-    0x01, 3, 12, LD_BC_nn(value: u16) => cpu.set_bc(value);
-    0x11, 3, 12, LD_DE_nn(value: u16) => cpu.set_de(value);
-    0x21, 3, 12, LD_HL_nn(value: u16) => cpu.set_hl(value);
-    0x31, 3, 12, LD_SP_nn(value: u16) => cpu.set_sp(value);
-    0x12, 1,  8, LD_MDE_A => mem.write_u8(Addr(cpu.de()), cpu.a());
-    0x77, 1,  8, LD_MHL_A => mem.write_u8(Addr(cpu.hl()), cpu.a());
     0x70, 1,  8, LD_MHL_B => mem.write_u8(Addr(cpu.hl()), cpu.b());
     0x71, 1,  8, LD_MHL_C => mem.write_u8(Addr(cpu.hl()), cpu.c());
     0x72, 1,  8, LD_MHL_D => mem.write_u8(Addr(cpu.hl()), cpu.d());
     0x73, 1,  8, LD_MHL_E => mem.write_u8(Addr(cpu.hl()), cpu.e());
     0x74, 1,  8, LD_MHL_H => mem.write_u8(Addr(cpu.hl()), cpu.h());
     0x75, 1,  8, LD_MHL_L => mem.write_u8(Addr(cpu.hl()), cpu.l());
+    0x77, 1,  8, LD_MHL_A => mem.write_u8(Addr(cpu.hl()), cpu.a());
+    0x36, 2, 12, LD_MHL_n(value: u8) => mem.write_u8(Addr(cpu.hl()), value);
+    0x78, 1,  4, LD_A_B => unborrow!(cpu.set_a(cpu.b()));
+    0x79, 1,  4, LD_A_C => unborrow!(cpu.set_a(cpu.c()));
+    0x7A, 1,  4, LD_A_D => unborrow!(cpu.set_a(cpu.d()));
+    0x7B, 1,  4, LD_A_E => unborrow!(cpu.set_a(cpu.e()));
+    0x7C, 1,  4, LD_A_H => unborrow!(cpu.set_a(cpu.h()));
+    0x7D, 1,  4, LD_A_L => unborrow!(cpu.set_a(cpu.l()));
+    0x7E, 1,  8, LD_A_MHL => unborrow!(cpu.set_a(mem.read_u8(Addr(cpu.hl()))));
+    0x7F, 1,  4, LD_A_A => unborrow!(cpu.set_a(cpu.a()));
+    0x3E, 2,  8, LD_A_n(value: u8) => cpu.set_a(value);
+    0x0A, 1,  8, LD_A_MBC => unborrow!(cpu.set_a(mem.read_u8(Addr(cpu.bc()))));
+    0x1A, 1,  8, LD_A_MDE => unborrow!(cpu.set_a(mem.read_u8(Addr(cpu.de()))));
+    0xFA, 3, 16, LD_A_Mnn(p: u16) => cpu.set_a(mem.read_u8(Addr(p)));
+    0x02, 1,  8, LD_MBC_A => mem.write_u8(Addr(cpu.bc()), cpu.a());
+    0x12, 1,  8, LD_MDE_A => mem.write_u8(Addr(cpu.de()), cpu.a());
     0xEA, 3, 16, LD_Mnn_A(p: u16) => mem.write_u8(Addr(p), cpu.a());
     0xE0, 2, 12, LD_Mn_A(p: u8) => mem.write_u8(Addr(0xFF00 + p as u16), cpu.a());
+    0xF0, 2, 12, LD_A_Mn(offset: u8) => unborrow!(cpu.set_a(mem.read_u8(Addr(0xFF00 + offset as u16))));
+    0xE2, 1,  8, LD_MC_A => mem.write_u8(Addr(0xFF00 + cpu.c() as u16), cpu.a());
+    0xF2, 1,  8, LD_A_MC => unborrow!(cpu.set_a(mem.read_u8(Addr(0xFF00 + cpu.c() as u16))));
     0x2A, 1,  8, LDI_A_MHL => {
-        let value = mem.read_u8(Addr(cpu.hl()));
+        let value = mem.read_u8_inc_dec(Addr(cpu.hl()));
         cpu.set_a(value);
         cpu.incr_hl_without_affecting_flags();
     };
+    0x3A, 1,  8, LDD_A_MHL => {
+        let value = mem.read_u8_inc_dec(Addr(cpu.hl()));
+        cpu.set_a(value);
+        cpu.decr_hl_without_affecting_flags();
+    };
     0x22, 1,  8, LDI_MHL_A => {
         mem.write_u8(Addr(cpu.hl()), cpu.a());
         cpu.incr_hl_without_affecting_flags();
@@ -210,69 +226,162 @@ instructions! {
         mem.write_u8(Addr(cpu.hl()), cpu.a());
         cpu.decr_hl_without_affecting_flags();
     };
-    0xF0, 2, 12, LD_A_Mn(offset: u8) => unborrow!(cpu.set_a(mem.read_u8(Addr(0xFF00 + offset as u16))));
-    0xC4, 3, 12, CALL_NZ_nn(addr: u16) => if !cpu.flag_z() { cpu.call(mem, addr) };
-    0xCD, 3, 12, CALL_nn(addr: u16) => cpu.call(mem, addr);
-    0xE9, 1,  4, JP_HL => unborrow!(cpu.set_pc(cpu.hl()));
-    0xC2, 3, 12, JP_NZ_nn(addr: u16) => if !cpu.flag_z() { cpu.set_pc(addr) };
-    0x18, 2,  8, JR_n(offset: i8) => cpu.jump_routine(offset);
-    0x20, 2,  8, JR_NZ_n(offset: i8) => if !cpu.flag_z() { cpu.jump_routine(offset) };
-    0x28, 2,  8, JR_Z(offset: i8) => if cpu.flag_z() { cpu.jump_routine(offset) };
-    0x30, 2,  8, JR_NC(offset: i8) => if !cpu.flag_c() { cpu.jump_routine(offset) };
-    0xC9, 1,  8, RET => unborrow!(cpu.set_pc(cpu.pop_u16(mem)));
-    0xC8, 1,  8, RET_Z => if cpu.flag_z() { RET.execute(cpu, mem) };
-    0xC0, 1,  8, RET_NZ => if !cpu.flag_z() { RET.execute(cpu, mem) };
-    0xD8, 1,  8, RET_C => if cpu.flag_c() { RET.execute(cpu, mem) };
-    0xD0, 1,  8, RET_NC => if !cpu.flag_c() { RET.execute(cpu, mem) };
-    0xF5, 1, 16, PUSH_AF => unborrow!(cpu.push_u16(mem, cpu.af()));
+    0x01, 3, 12, LD_BC_nn(value: u16) => cpu.set_bc(value);
+    0x11, 3, 12, LD_DE_nn(value: u16) => cpu.set_de(value);
+    0x21, 3, 12, LD_HL_nn(value: u16) => cpu.set_hl(value);
+    0x31, 3, 12, LD_SP_nn(value: u16) => cpu.set_sp(value);
+    0x08, 3, 20, LD_Mnn_SP(p: u16) => mem.write_u16(Addr(p), cpu.sp());
+    0xF9, 1,  8, LD_SP_HL => unborrow!(cpu.set_sp(cpu.hl()));
+    0xF8, 2, 12, LD_HL_SP_n(offset: i8) => unborrow!(cpu.set_hl(cpu.add_sp_offset(offset)));
     0xC5, 1, 16, PUSH_BC => unborrow!(cpu.push_u16(mem, cpu.bc()));
     0xD5, 1, 16, PUSH_DE => unborrow!(cpu.push_u16(mem, cpu.de()));
     0xE5, 1, 16, PUSH_HL => unborrow!(cpu.push_u16(mem, cpu.hl()));
-    0xF1, 1, 12, POP_AF => unborrow!(cpu.set_af(cpu.pop_u16(mem)));
+    0xF5, 1, 16, PUSH_AF => unborrow!(cpu.push_u16(mem, cpu.af()));
     0xC1, 1, 12, POP_BC => unborrow!(cpu.set_bc(cpu.pop_u16(mem)));
     0xD1, 1, 12, POP_DE => unborrow!(cpu.set_de(cpu.pop_u16(mem)));
     0xE1, 1, 12, POP_HL => unborrow!(cpu.set_hl(cpu.pop_u16(mem)));
-    0x29, 1,  8, ADD_HL_HL => unborrow!(cpu.add_hl(cpu.hl()));
-    0xC6, 2,  8, ADD_n(amount: u8) => cpu.add(amount);
-    0xD6, 2,  8, SUB_n(amount: u8) => cpu.sub(amount);
+    0xF1, 1, 12, POP_AF => unborrow!(cpu.set_af(cpu.pop_u16(mem)));
+    0xC3, 3, 16, JP_nn(pc: u16) => cpu.set_pc(pc);
+    0xC2, 3, 12, JP_NZ_nn(addr: u16) => unborrow!(cpu.jump_if(!cpu.flag_z(), addr));
+    0xCA, 3, 12, JP_Z_nn(addr: u16) => unborrow!(cpu.jump_if(cpu.flag_z(), addr));
+    0xD2, 3, 12, JP_NC_nn(addr: u16) => unborrow!(cpu.jump_if(!cpu.flag_c(), addr));
+    0xDA, 3, 12, JP_C_nn(addr: u16) => unborrow!(cpu.jump_if(cpu.flag_c(), addr));
+    0xE9, 1,  4, JP_HL => unborrow!(cpu.set_pc(cpu.hl()));
+    0x18, 2, 12, JR_n(offset: i8) => cpu.jump_routine(offset);
+    0x20, 2,  8, JR_NZ_n(offset: i8) => unborrow!(cpu.jump_routine_if(!cpu.flag_z(), offset));
+    0x28, 2,  8, JR_Z(offset: i8) => unborrow!(cpu.jump_routine_if(cpu.flag_z(), offset));
+    0x30, 2,  8, JR_NC(offset: i8) => unborrow!(cpu.jump_routine_if(!cpu.flag_c(), offset));
+    0x38, 2,  8, JR_C(offset: i8) => unborrow!(cpu.jump_routine_if(cpu.flag_c(), offset));
+    0xCD, 3, 24, CALL_nn(addr: u16) => cpu.call(mem, addr);
+    0xC4, 3, 12, CALL_NZ_nn(addr: u16) => unborrow!(cpu.call_if(mem, !cpu.flag_z(), addr));
+    0xCC, 3, 12, CALL_Z_nn(addr: u16) => unborrow!(cpu.call_if(mem, cpu.flag_z(), addr));
+    0xD4, 3, 12, CALL_NC_nn(addr: u16) => unborrow!(cpu.call_if(mem, !cpu.flag_c(), addr));
+    0xDC, 3, 12, CALL_C_nn(addr: u16) => unborrow!(cpu.call_if(mem, cpu.flag_c(), addr));
+    0xC9, 1, 16, RET => unborrow!(cpu.set_pc(cpu.pop_u16(mem)));
+    0xC0, 1,  8, RET_NZ => unborrow!(cpu.return_if(mem, !cpu.flag_z()));
+    0xC8, 1,  8, RET_Z => unborrow!(cpu.return_if(mem, cpu.flag_z()));
+    0xD0, 1,  8, RET_NC => unborrow!(cpu.return_if(mem, !cpu.flag_c()));
+    0xD8, 1,  8, RET_C => unborrow!(cpu.return_if(mem, cpu.flag_c()));
+    0xD9, 1, 16, RETI => {
+        RET.execute(cpu, mem);
+        cpu.enable_interrupts_immediately();
+    };
+    0xC7, 1, 16, RST_00 => cpu.call(mem, 0x00);
+    0xCF, 1, 16, RST_08 => cpu.call(mem, 0x08);
+    0xD7, 1, 16, RST_10 => cpu.call(mem, 0x10);
+    0xDF, 1, 16, RST_18 => cpu.call(mem, 0x18);
+    0xE7, 1, 16, RST_20 => cpu.call(mem, 0x20);
+    0xEF, 1, 16, RST_28 => cpu.call(mem, 0x28);
+    0xF7, 1, 16, RST_30 => cpu.call(mem, 0x30);
+    0xFF, 1, 16, RST_38 => cpu.call(mem, 0x38);
+    0x80, 1,  4, ADD_B => unborrow!(cpu.add(cpu.b()));
+    0x81, 1,  4, ADD_C => unborrow!(cpu.add(cpu.c()));
+    0x82, 1,  4, ADD_D => unborrow!(cpu.add(cpu.d()));
+    0x83, 1,  4, ADD_E => unborrow!(cpu.add(cpu.e()));
+    0x84, 1,  4, ADD_H => unborrow!(cpu.add(cpu.h()));
+    0x85, 1,  4, ADD_L => unborrow!(cpu.add(cpu.l()));
+    0x86, 1,  8, ADD_MHL => unborrow!(cpu.add(mem.read_u8(Addr(cpu.hl()))));
+    0x87, 1,  4, ADD_A => unborrow!(cpu.add(cpu.a()));
+    0xC6, 2,  8, ADD_n(value: u8) => cpu.add(value);
+    0x88, 1,  4, ADC_B => unborrow!(cpu.add_carry(cpu.b()));
+    0x89, 1,  4, ADC_C => unborrow!(cpu.add_carry(cpu.c()));
     0x8A, 1,  4, ADC_D => unborrow!(cpu.add_carry(cpu.d()));
-    0xCE, 2,  8, ADC_n(amount: u8) => cpu.add_carry(amount);
-    0x3C, 1,  4, INC_A => cpu.incr_a();
+    0x8B, 1,  4, ADC_E => unborrow!(cpu.add_carry(cpu.e()));
+    0x8C, 1,  4, ADC_H => unborrow!(cpu.add_carry(cpu.h()));
+    0x8D, 1,  4, ADC_L => unborrow!(cpu.add_carry(cpu.l()));
+    0x8E, 1,  8, ADC_MHL => unborrow!(cpu.add_carry(mem.read_u8(Addr(cpu.hl()))));
+    0x8F, 1,  4, ADC_A => unborrow!(cpu.add_carry(cpu.a()));
+    0xCE, 2,  8, ADC_n(value: u8) => cpu.add_carry(value);
+    0x90, 1,  4, SUB_B => unborrow!(cpu.sub(cpu.b()));
+    0x91, 1,  4, SUB_C => unborrow!(cpu.sub(cpu.c()));
+    0x92, 1,  4, SUB_D => unborrow!(cpu.sub(cpu.d()));
+    0x93, 1,  4, SUB_E => unborrow!(cpu.sub(cpu.e()));
+    0x94, 1,  4, SUB_H => unborrow!(cpu.sub(cpu.h()));
+    0x95, 1,  4, SUB_L => unborrow!(cpu.sub(cpu.l()));
+    0x96, 1,  8, SUB_MHL => unborrow!(cpu.sub(mem.read_u8(Addr(cpu.hl()))));
+    0x97, 1,  4, SUB_A => unborrow!(cpu.sub(cpu.a()));
+    0xD6, 2,  8, SUB_n(value: u8) => cpu.sub(value);
+    0x98, 1,  4, SBC_B => unborrow!(cpu.sub_carry(cpu.b()));
+    0x99, 1,  4, SBC_C => unborrow!(cpu.sub_carry(cpu.c()));
+    0x9A, 1,  4, SBC_D => unborrow!(cpu.sub_carry(cpu.d()));
+    0x9B, 1,  4, SBC_E => unborrow!(cpu.sub_carry(cpu.e()));
+    0x9C, 1,  4, SBC_H => unborrow!(cpu.sub_carry(cpu.h()));
+    0x9D, 1,  4, SBC_L => unborrow!(cpu.sub_carry(cpu.l()));
+    0x9E, 1,  8, SBC_MHL => unborrow!(cpu.sub_carry(mem.read_u8(Addr(cpu.hl()))));
+    0x9F, 1,  4, SBC_A => unborrow!(cpu.sub_carry(cpu.a()));
+    0xDE, 2,  8, SBC_n(value: u8) => cpu.sub_carry(value);
+    0xA0, 1,  4, AND_B => unborrow!(cpu.and(cpu.b()));
+    0xA1, 1,  4, AND_C => unborrow!(cpu.and(cpu.c()));
+    0xA2, 1,  4, AND_D => unborrow!(cpu.and(cpu.d()));
+    0xA3, 1,  4, AND_E => unborrow!(cpu.and(cpu.e()));
+    0xA4, 1,  4, AND_H => unborrow!(cpu.and(cpu.h()));
+    0xA5, 1,  4, AND_L => unborrow!(cpu.and(cpu.l()));
+    0xA6, 1,  8, AND_MHL => unborrow!(cpu.and(mem.read_u8(Addr(cpu.hl()))));
+    0xA7, 1,  4, AND_A => unborrow!(cpu.and(cpu.a()));
+    0xE6, 2,  8, AND_n(value: u8) => cpu.and(value);
+    0xA8, 1,  4, XOR_B => unborrow!(cpu.xor(cpu.b()));
+    0xA9, 1,  4, XOR_C => unborrow!(cpu.xor(cpu.c()));
+    0xAA, 1,  4, XOR_D => unborrow!(cpu.xor(cpu.d()));
+    0xAB, 1,  4, XOR_E => unborrow!(cpu.xor(cpu.e()));
+    0xAC, 1,  4, XOR_H => unborrow!(cpu.xor(cpu.h()));
+    0xAD, 1,  4, XOR_L => unborrow!(cpu.xor(cpu.l()));
+    0xAE, 1,  8, XOR_MHL => unborrow!(cpu.xor(mem.read_u8(Addr(cpu.hl()))));
+    0xAF, 1,  4, XOR_A => unborrow!(cpu.xor(cpu.a()));
+    0xEE, 2,  8, XOR_n(value: u8) => cpu.xor(value);
+    0xB0, 1,  4, OR_B => unborrow!(cpu.or(cpu.b()));
+    0xB1, 1,  4, OR_C => unborrow!(cpu.or(cpu.c()));
+    0xB2, 1,  4, OR_D => unborrow!(cpu.or(cpu.d()));
+    0xB3, 1,  4, OR_E => unborrow!(cpu.or(cpu.e()));
+    0xB4, 1,  4, OR_H => unborrow!(cpu.or(cpu.h()));
+    0xB5, 1,  4, OR_L => unborrow!(cpu.or(cpu.l()));
+    0xB6, 1,  8, OR_MHL => unborrow!(cpu.or(mem.read_u8(Addr(cpu.hl()))));
+    0xB7, 1,  4, OR_A => unborrow!(cpu.or(cpu.a()));
+    0xF6, 2,  8, OR_n(value: u8) => cpu.or(value);
+    0xB8, 1,  4, CP_B => unborrow!(cpu.compare(cpu.b()));
+    0xB9, 1,  4, CP_C => unborrow!(cpu.compare(cpu.c()));
+    0xBA, 1,  4, CP_D => unborrow!(cpu.compare(cpu.d()));
+    0xBB, 1,  4, CP_E => unborrow!(cpu.compare(cpu.e()));
+    0xBC, 1,  4, CP_H => unborrow!(cpu.compare(cpu.h()));
+    0xBD, 1,  4, CP_L => unborrow!(cpu.compare(cpu.l()));
+    0xBE, 1,  8, CP_MHL => unborrow!(cpu.compare(mem.read_u8(Addr(cpu.hl()))));
+    0xBF, 1,  4, CP_A => unborrow!(cpu.compare(cpu.a()));
+    0xFE, 2,  8, CP_n(value: u8) => cpu.compare(value);
+    0x09, 1,  8, ADD_HL_BC => unborrow!(cpu.add_hl(cpu.bc()));
+    0x19, 1,  8, ADD_HL_DE => unborrow!(cpu.add_hl(cpu.de()));
+    0x29, 1,  8, ADD_HL_HL => unborrow!(cpu.add_hl(cpu.hl()));
+    0x39, 1,  8, ADD_HL_SP => unborrow!(cpu.add_hl(cpu.sp()));
+    0xE8, 2, 16, ADD_SP_n(offset: i8) => unborrow!(cpu.set_sp(cpu.add_sp_offset(offset)));
     0x04, 1,  4, INC_B => cpu.incr_b();
     0x0C, 1,  4, INC_C => cpu.incr_c();
     0x14, 1,  4, INC_D => cpu.incr_d();
     0x1C, 1,  4, INC_E => cpu.incr_e();
     0x24, 1,  4, INC_H => cpu.incr_h();
     0x2C, 1,  4, INC_L => cpu.incr_l();
-    0x03, 1,  8, INC_BC => cpu.incr_bc();
-    0x13, 1,  8, INC_DE => cpu.incr_de();
-    0x23, 1,  8, INC_HL => cpu.incr_hl();
     0x34, 1, 12, INC_MHL => cpu.incr_mhl(mem);
-    0x3D, 1,  4, DEC_A => cpu.decr_a();
+    0x3C, 1,  4, INC_A => cpu.incr_a();
     0x05, 1,  4, DEC_B => cpu.decr_b();
     0x0D, 1,  4, DEC_C => cpu.decr_c();
     0x15, 1,  4, DEC_D => cpu.decr_d();
     0x1D, 1,  4, DEC_E => cpu.decr_e();
     0x25, 1,  4, DEC_H => cpu.decr_h();
     0x2D, 1,  4, DEC_L => cpu.decr_l();
-    0x35, 1,  12, DEC_MHL => cpu.decr_mhl(mem);
-    0xB7, 1,  4, OR_A => unborrow!(cpu.or(cpu.a()));
-    0xB0, 1,  4, OR_B => unborrow!(cpu.or(cpu.b()));
-    0xB1, 1,  4, OR_C => unborrow!(cpu.or(cpu.c()));
-    0xB6, 1,  8, OR_MHL => unborrow!(cpu.or(mem.read_u8(Addr(cpu.hl()))));
-    0xE6, 2,  8, AND_n(value: u8) => cpu.and(value);
-    0xAE, 1,  8, XOR_MHL => unborrow!(cpu.xor(mem.read_u8(Addr(cpu.hl()))));
-    0xAF, 1,  4, XOR_A => unborrow!(cpu.xor(cpu.a()));
-    0xA8, 1,  4, XOR_B => unborrow!(cpu.xor(cpu.b()));
-    0xA9, 1,  4, XOR_C => unborrow!(cpu.xor(cpu.c()));
-    0xAA, 1,  4, XOR_D => unborrow!(cpu.xor(cpu.d()));
-    0xAB, 1,  4, XOR_E => unborrow!(cpu.xor(cpu.e()));
-    0xAC, 1,  4, XOR_H => unborrow!(cpu.xor(cpu.h()));
-    0xAD, 1,  4, XOR_L => unborrow!(cpu.xor(cpu.l()));
-    0xEE, 2,  8, XOR_n(value: u8) => cpu.xor(value);
-    0xB9, 1,  4, CP_C => unborrow!(cpu.compare(cpu.c()));
-    0xFE, 2,  8, CP_n(value: u8) => unborrow!(cpu.compare(value));
-    0x2F, 1,  4, CPL => unborrow!(cpu.set_a(cpu.a() ^ 0xFF));
+    0x35, 1, 12, DEC_MHL => cpu.decr_mhl(mem);
+    0x3D, 1,  4, DEC_A => cpu.decr_a();
+    0x03, 1,  8, INC_BC => cpu.incr_bc(mem);
+    0x13, 1,  8, INC_DE => cpu.incr_de(mem);
+    0x23, 1,  8, INC_HL => cpu.incr_hl(mem);
+    0x33, 1,  8, INC_SP => cpu.incr_sp(mem);
+    0x0B, 1,  8, DEC_BC => cpu.decr_bc(mem);
+    0x1B, 1,  8, DEC_DE => cpu.decr_de(mem);
+    0x2B, 1,  8, DEC_HL => cpu.decr_hl(mem);
+    0x3B, 1,  8, DEC_SP => cpu.decr_sp(mem);
+    0x27, 1,  4, DAA => cpu.decimal_adjust_a();
+    0x2F, 1,  4, CPL => cpu.complement_a();
+    0x37, 1,  4, SCF => cpu.set_carry_flag();
+    0x3F, 1,  4, CCF => cpu.complement_carry_flag();
+    0x07, 1,  4, RLCA => cpu.rotate_left_circular_a();
+    0x17, 1,  4, RLA => cpu.rotate_left_a();
+    0x0F, 1,  4, RRCA => cpu.rotate_right_circular_a();
     0x1F, 1,  4, RRA => cpu.rotate_right_a();
     0xCB, instr.len() + 1, instr.cycles(), Extended(instr: ExtendedInstruction) => instr.execute(cpu, mem);
 }
@@ -281,8 +390,356 @@ instructions! {
     ExtendedInstruction
     |cpu, mem, addr|
     // op, len, cycles
-    0x38, 1,  8, SRL_B => cpu.shift_right_logical_b();
-    0x19, 1,  8, RR_C => cpu.rotate_right_c();
-    0x1A, 1,  8, RR_D => cpu.rotate_right_d();
-    0x1B, 1,  8, RR_E => cpu.rotate_right_e();
+    0x00, 1,  8, RLC_B => unborrow!(cpu.set_b(cpu.rotate_left_circular(cpu.b())));
+    0x01, 1,  8, RLC_C => unborrow!(cpu.set_c(cpu.rotate_left_circular(cpu.c())));
+    0x02, 1,  8, RLC_D => unborrow!(cpu.set_d(cpu.rotate_left_circular(cpu.d())));
+    0x03, 1,  8, RLC_E => unborrow!(cpu.set_e(cpu.rotate_left_circular(cpu.e())));
+    0x04, 1,  8, RLC_H => unborrow!(cpu.set_h(cpu.rotate_left_circular(cpu.h())));
+    0x05, 1,  8, RLC_L => unborrow!(cpu.set_l(cpu.rotate_left_circular(cpu.l())));
+    0x06, 1, 16, RLC_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = cpu.rotate_left_circular(mem.read_u8(addr));
+        mem.write_u8(addr, value);
+    };
+    0x07, 1,  8, RLC_A => unborrow!(cpu.set_a(cpu.rotate_left_circular(cpu.a())));
+    0x08, 1,  8, RRC_B => unborrow!(cpu.set_b(cpu.rotate_right_circular(cpu.b())));
+    0x09, 1,  8, RRC_C => unborrow!(cpu.set_c(cpu.rotate_right_circular(cpu.c())));
+    0x0A, 1,  8, RRC_D => unborrow!(cpu.set_d(cpu.rotate_right_circular(cpu.d())));
+    0x0B, 1,  8, RRC_E => unborrow!(cpu.set_e(cpu.rotate_right_circular(cpu.e())));
+    0x0C, 1,  8, RRC_H => unborrow!(cpu.set_h(cpu.rotate_right_circular(cpu.h())));
+    0x0D, 1,  8, RRC_L => unborrow!(cpu.set_l(cpu.rotate_right_circular(cpu.l())));
+    0x0E, 1, 16, RRC_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = cpu.rotate_right_circular(mem.read_u8(addr));
+        mem.write_u8(addr, value);
+    };
+    0x0F, 1,  8, RRC_A => unborrow!(cpu.set_a(cpu.rotate_right_circular(cpu.a())));
+    0x10, 1,  8, RL_B => unborrow!(cpu.set_b(cpu.rotate_left(cpu.b())));
+    0x11, 1,  8, RL_C => unborrow!(cpu.set_c(cpu.rotate_left(cpu.c())));
+    0x12, 1,  8, RL_D => unborrow!(cpu.set_d(cpu.rotate_left(cpu.d())));
+    0x13, 1,  8, RL_E => unborrow!(cpu.set_e(cpu.rotate_left(cpu.e())));
+    0x14, 1,  8, RL_H => unborrow!(cpu.set_h(cpu.rotate_left(cpu.h())));
+    0x15, 1,  8, RL_L => unborrow!(cpu.set_l(cpu.rotate_left(cpu.l())));
+    0x16, 1, 16, RL_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = cpu.rotate_left(mem.read_u8(addr));
+        mem.write_u8(addr, value);
+    };
+    0x17, 1,  8, RL_A => unborrow!(cpu.set_a(cpu.rotate_left(cpu.a())));
+    0x18, 1,  8, RR_B => unborrow!(cpu.set_b(cpu.rotate_right(cpu.b())));
+    0x19, 1,  8, RR_C => unborrow!(cpu.set_c(cpu.rotate_right(cpu.c())));
+    0x1A, 1,  8, RR_D => unborrow!(cpu.set_d(cpu.rotate_right(cpu.d())));
+    0x1B, 1,  8, RR_E => unborrow!(cpu.set_e(cpu.rotate_right(cpu.e())));
+    0x1C, 1,  8, RR_H => unborrow!(cpu.set_h(cpu.rotate_right(cpu.h())));
+    0x1D, 1,  8, RR_L => unborrow!(cpu.set_l(cpu.rotate_right(cpu.l())));
+    0x1E, 1, 16, RR_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = cpu.rotate_right(mem.read_u8(addr));
+        mem.write_u8(addr, value);
+    };
+    0x1F, 1,  8, RR_A => unborrow!(cpu.set_a(cpu.rotate_right(cpu.a())));
+    0x20, 1,  8, SLA_B => unborrow!(cpu.set_b(cpu.shift_left_arithmetic(cpu.b())));
+    0x21, 1,  8, SLA_C => unborrow!(cpu.set_c(cpu.shift_left_arithmetic(cpu.c())));
+    0x22, 1,  8, SLA_D => unborrow!(cpu.set_d(cpu.shift_left_arithmetic(cpu.d())));
+    0x23, 1,  8, SLA_E => unborrow!(cpu.set_e(cpu.shift_left_arithmetic(cpu.e())));
+    0x24, 1,  8, SLA_H => unborrow!(cpu.set_h(cpu.shift_left_arithmetic(cpu.h())));
+    0x25, 1,  8, SLA_L => unborrow!(cpu.set_l(cpu.shift_left_arithmetic(cpu.l())));
+    0x26, 1, 16, SLA_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = cpu.shift_left_arithmetic(mem.read_u8(addr));
+        mem.write_u8(addr, value);
+    };
+    0x27, 1,  8, SLA_A => unborrow!(cpu.set_a(cpu.shift_left_arithmetic(cpu.a())));
+    0x28, 1,  8, SRA_B => unborrow!(cpu.set_b(cpu.shift_right_arithmetic(cpu.b())));
+    0x29, 1,  8, SRA_C => unborrow!(cpu.set_c(cpu.shift_right_arithmetic(cpu.c())));
+    0x2A, 1,  8, SRA_D => unborrow!(cpu.set_d(cpu.shift_right_arithmetic(cpu.d())));
+    0x2B, 1,  8, SRA_E => unborrow!(cpu.set_e(cpu.shift_right_arithmetic(cpu.e())));
+    0x2C, 1,  8, SRA_H => unborrow!(cpu.set_h(cpu.shift_right_arithmetic(cpu.h())));
+    0x2D, 1,  8, SRA_L => unborrow!(cpu.set_l(cpu.shift_right_arithmetic(cpu.l())));
+    0x2E, 1, 16, SRA_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = cpu.shift_right_arithmetic(mem.read_u8(addr));
+        mem.write_u8(addr, value);
+    };
+    0x2F, 1,  8, SRA_A => unborrow!(cpu.set_a(cpu.shift_right_arithmetic(cpu.a())));
+    0x30, 1,  8, SWAP_B => unborrow!(cpu.set_b(cpu.swap(cpu.b())));
+    0x31, 1,  8, SWAP_C => unborrow!(cpu.set_c(cpu.swap(cpu.c())));
+    0x32, 1,  8, SWAP_D => unborrow!(cpu.set_d(cpu.swap(cpu.d())));
+    0x33, 1,  8, SWAP_E => unborrow!(cpu.set_e(cpu.swap(cpu.e())));
+    0x34, 1,  8, SWAP_H => unborrow!(cpu.set_h(cpu.swap(cpu.h())));
+    0x35, 1,  8, SWAP_L => unborrow!(cpu.set_l(cpu.swap(cpu.l())));
+    0x36, 1, 16, SWAP_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = cpu.swap(mem.read_u8(addr));
+        mem.write_u8(addr, value);
+    };
+    0x37, 1,  8, SWAP_A => unborrow!(cpu.set_a(cpu.swap(cpu.a())));
+    0x38, 1,  8, SRL_B => unborrow!(cpu.set_b(cpu.shift_right_logical(cpu.b())));
+    0x39, 1,  8, SRL_C => unborrow!(cpu.set_c(cpu.shift_right_logical(cpu.c())));
+    0x3A, 1,  8, SRL_D => unborrow!(cpu.set_d(cpu.shift_right_logical(cpu.d())));
+    0x3B, 1,  8, SRL_E => unborrow!(cpu.set_e(cpu.shift_right_logical(cpu.e())));
+    0x3C, 1,  8, SRL_H => unborrow!(cpu.set_h(cpu.shift_right_logical(cpu.h())));
+    0x3D, 1,  8, SRL_L => unborrow!(cpu.set_l(cpu.shift_right_logical(cpu.l())));
+    0x3E, 1, 16, SRL_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = cpu.shift_right_logical(mem.read_u8(addr));
+        mem.write_u8(addr, value);
+    };
+    0x3F, 1,  8, SRL_A => unborrow!(cpu.set_a(cpu.shift_right_logical(cpu.a())));
+    0x40, 1,  8, BIT_0_B => unborrow!(cpu.test_bit(0, cpu.b()));
+    0x41, 1,  8, BIT_0_C => unborrow!(cpu.test_bit(0, cpu.c()));
+    0x42, 1,  8, BIT_0_D => unborrow!(cpu.test_bit(0, cpu.d()));
+    0x43, 1,  8, BIT_0_E => unborrow!(cpu.test_bit(0, cpu.e()));
+    0x44, 1,  8, BIT_0_H => unborrow!(cpu.test_bit(0, cpu.h()));
+    0x45, 1,  8, BIT_0_L => unborrow!(cpu.test_bit(0, cpu.l()));
+    0x46, 1, 12, BIT_0_MHL => unborrow!(cpu.test_bit(0, mem.read_u8(Addr(cpu.hl()))));
+    0x47, 1,  8, BIT_0_A => unborrow!(cpu.test_bit(0, cpu.a()));
+    0x48, 1,  8, BIT_1_B => unborrow!(cpu.test_bit(1, cpu.b()));
+    0x49, 1,  8, BIT_1_C => unborrow!(cpu.test_bit(1, cpu.c()));
+    0x4A, 1,  8, BIT_1_D => unborrow!(cpu.test_bit(1, cpu.d()));
+    0x4B, 1,  8, BIT_1_E => unborrow!(cpu.test_bit(1, cpu.e()));
+    0x4C, 1,  8, BIT_1_H => unborrow!(cpu.test_bit(1, cpu.h()));
+    0x4D, 1,  8, BIT_1_L => unborrow!(cpu.test_bit(1, cpu.l()));
+    0x4E, 1, 12, BIT_1_MHL => unborrow!(cpu.test_bit(1, mem.read_u8(Addr(cpu.hl()))));
+    0x4F, 1,  8, BIT_1_A => unborrow!(cpu.test_bit(1, cpu.a()));
+    0x50, 1,  8, BIT_2_B => unborrow!(cpu.test_bit(2, cpu.b()));
+    0x51, 1,  8, BIT_2_C => unborrow!(cpu.test_bit(2, cpu.c()));
+    0x52, 1,  8, BIT_2_D => unborrow!(cpu.test_bit(2, cpu.d()));
+    0x53, 1,  8, BIT_2_E => unborrow!(cpu.test_bit(2, cpu.e()));
+    0x54, 1,  8, BIT_2_H => unborrow!(cpu.test_bit(2, cpu.h()));
+    0x55, 1,  8, BIT_2_L => unborrow!(cpu.test_bit(2, cpu.l()));
+    0x56, 1, 12, BIT_2_MHL => unborrow!(cpu.test_bit(2, mem.read_u8(Addr(cpu.hl()))));
+    0x57, 1,  8, BIT_2_A => unborrow!(cpu.test_bit(2, cpu.a()));
+    0x58, 1,  8, BIT_3_B => unborrow!(cpu.test_bit(3, cpu.b()));
+    0x59, 1,  8, BIT_3_C => unborrow!(cpu.test_bit(3, cpu.c()));
+    0x5A, 1,  8, BIT_3_D => unborrow!(cpu.test_bit(3, cpu.d()));
+    0x5B, 1,  8, BIT_3_E => unborrow!(cpu.test_bit(3, cpu.e()));
+    0x5C, 1,  8, BIT_3_H => unborrow!(cpu.test_bit(3, cpu.h()));
+    0x5D, 1,  8, BIT_3_L => unborrow!(cpu.test_bit(3, cpu.l()));
+    0x5E, 1, 12, BIT_3_MHL => unborrow!(cpu.test_bit(3, mem.read_u8(Addr(cpu.hl()))));
+    0x5F, 1,  8, BIT_3_A => unborrow!(cpu.test_bit(3, cpu.a()));
+    0x60, 1,  8, BIT_4_B => unborrow!(cpu.test_bit(4, cpu.b()));
+    0x61, 1,  8, BIT_4_C => unborrow!(cpu.test_bit(4, cpu.c()));
+    0x62, 1,  8, BIT_4_D => unborrow!(cpu.test_bit(4, cpu.d()));
+    0x63, 1,  8, BIT_4_E => unborrow!(cpu.test_bit(4, cpu.e()));
+    0x64, 1,  8, BIT_4_H => unborrow!(cpu.test_bit(4, cpu.h()));
+    0x65, 1,  8, BIT_4_L => unborrow!(cpu.test_bit(4, cpu.l()));
+    0x66, 1, 12, BIT_4_MHL => unborrow!(cpu.test_bit(4, mem.read_u8(Addr(cpu.hl()))));
+    0x67, 1,  8, BIT_4_A => unborrow!(cpu.test_bit(4, cpu.a()));
+    0x68, 1,  8, BIT_5_B => unborrow!(cpu.test_bit(5, cpu.b()));
+    0x69, 1,  8, BIT_5_C => unborrow!(cpu.test_bit(5, cpu.c()));
+    0x6A, 1,  8, BIT_5_D => unborrow!(cpu.test_bit(5, cpu.d()));
+    0x6B, 1,  8, BIT_5_E => unborrow!(cpu.test_bit(5, cpu.e()));
+    0x6C, 1,  8, BIT_5_H => unborrow!(cpu.test_bit(5, cpu.h()));
+    0x6D, 1,  8, BIT_5_L => unborrow!(cpu.test_bit(5, cpu.l()));
+    0x6E, 1, 12, BIT_5_MHL => unborrow!(cpu.test_bit(5, mem.read_u8(Addr(cpu.hl()))));
+    0x6F, 1,  8, BIT_5_A => unborrow!(cpu.test_bit(5, cpu.a()));
+    0x70, 1,  8, BIT_6_B => unborrow!(cpu.test_bit(6, cpu.b()));
+    0x71, 1,  8, BIT_6_C => unborrow!(cpu.test_bit(6, cpu.c()));
+    0x72, 1,  8, BIT_6_D => unborrow!(cpu.test_bit(6, cpu.d()));
+    0x73, 1,  8, BIT_6_E => unborrow!(cpu.test_bit(6, cpu.e()));
+    0x74, 1,  8, BIT_6_H => unborrow!(cpu.test_bit(6, cpu.h()));
+    0x75, 1,  8, BIT_6_L => unborrow!(cpu.test_bit(6, cpu.l()));
+    0x76, 1, 12, BIT_6_MHL => unborrow!(cpu.test_bit(6, mem.read_u8(Addr(cpu.hl()))));
+    0x77, 1,  8, BIT_6_A => unborrow!(cpu.test_bit(6, cpu.a()));
+    0x78, 1,  8, BIT_7_B => unborrow!(cpu.test_bit(7, cpu.b()));
+    0x79, 1,  8, BIT_7_C => unborrow!(cpu.test_bit(7, cpu.c()));
+    0x7A, 1,  8, BIT_7_D => unborrow!(cpu.test_bit(7, cpu.d()));
+    0x7B, 1,  8, BIT_7_E => unborrow!(cpu.test_bit(7, cpu.e()));
+    0x7C, 1,  8, BIT_7_H => unborrow!(cpu.test_bit(7, cpu.h()));
+    0x7D, 1,  8, BIT_7_L => unborrow!(cpu.test_bit(7, cpu.l()));
+    0x7E, 1, 12, BIT_7_MHL => unborrow!(cpu.test_bit(7, mem.read_u8(Addr(cpu.hl()))));
+    0x7F, 1,  8, BIT_7_A => unborrow!(cpu.test_bit(7, cpu.a()));
+    0x80, 1,  8, RES_0_B => unborrow!(cpu.set_b(cpu.b() & !(1 << 0)));
+    0x81, 1,  8, RES_0_C => unborrow!(cpu.set_c(cpu.c() & !(1 << 0)));
+    0x82, 1,  8, RES_0_D => unborrow!(cpu.set_d(cpu.d() & !(1 << 0)));
+    0x83, 1,  8, RES_0_E => unborrow!(cpu.set_e(cpu.e() & !(1 << 0)));
+    0x84, 1,  8, RES_0_H => unborrow!(cpu.set_h(cpu.h() & !(1 << 0)));
+    0x85, 1,  8, RES_0_L => unborrow!(cpu.set_l(cpu.l() & !(1 << 0)));
+    0x86, 1, 16, RES_0_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = mem.read_u8(addr);
+        mem.write_u8(addr, value & !(1 << 0));
+    };
+    0x87, 1,  8, RES_0_A => unborrow!(cpu.set_a(cpu.a() & !(1 << 0)));
+    0x88, 1,  8, RES_1_B => unborrow!(cpu.set_b(cpu.b() & !(1 << 1)));
+    0x89, 1,  8, RES_1_C => unborrow!(cpu.set_c(cpu.c() & !(1 << 1)));
+    0x8A, 1,  8, RES_1_D => unborrow!(cpu.set_d(cpu.d() & !(1 << 1)));
+    0x8B, 1,  8, RES_1_E => unborrow!(cpu.set_e(cpu.e() & !(1 << 1)));
+    0x8C, 1,  8, RES_1_H => unborrow!(cpu.set_h(cpu.h() & !(1 << 1)));
+    0x8D, 1,  8, RES_1_L => unborrow!(cpu.set_l(cpu.l() & !(1 << 1)));
+    0x8E, 1, 16, RES_1_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = mem.read_u8(addr);
+        mem.write_u8(addr, value & !(1 << 1));
+    };
+    0x8F, 1,  8, RES_1_A => unborrow!(cpu.set_a(cpu.a() & !(1 << 1)));
+    0x90, 1,  8, RES_2_B => unborrow!(cpu.set_b(cpu.b() & !(1 << 2)));
+    0x91, 1,  8, RES_2_C => unborrow!(cpu.set_c(cpu.c() & !(1 << 2)));
+    0x92, 1,  8, RES_2_D => unborrow!(cpu.set_d(cpu.d() & !(1 << 2)));
+    0x93, 1,  8, RES_2_E => unborrow!(cpu.set_e(cpu.e() & !(1 << 2)));
+    0x94, 1,  8, RES_2_H => unborrow!(cpu.set_h(cpu.h() & !(1 << 2)));
+    0x95, 1,  8, RES_2_L => unborrow!(cpu.set_l(cpu.l() & !(1 << 2)));
+    0x96, 1, 16, RES_2_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = mem.read_u8(addr);
+        mem.write_u8(addr, value & !(1 << 2));
+    };
+    0x97, 1,  8, RES_2_A => unborrow!(cpu.set_a(cpu.a() & !(1 << 2)));
+    0x98, 1,  8, RES_3_B => unborrow!(cpu.set_b(cpu.b() & !(1 << 3)));
+    0x99, 1,  8, RES_3_C => unborrow!(cpu.set_c(cpu.c() & !(1 << 3)));
+    0x9A, 1,  8, RES_3_D => unborrow!(cpu.set_d(cpu.d() & !(1 << 3)));
+    0x9B, 1,  8, RES_3_E => unborrow!(cpu.set_e(cpu.e() & !(1 << 3)));
+    0x9C, 1,  8, RES_3_H => unborrow!(cpu.set_h(cpu.h() & !(1 << 3)));
+    0x9D, 1,  8, RES_3_L => unborrow!(cpu.set_l(cpu.l() & !(1 << 3)));
+    0x9E, 1, 16, RES_3_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = mem.read_u8(addr);
+        mem.write_u8(addr, value & !(1 << 3));
+    };
+    0x9F, 1,  8, RES_3_A => unborrow!(cpu.set_a(cpu.a() & !(1 << 3)));
+    0xA0, 1,  8, RES_4_B => unborrow!(cpu.set_b(cpu.b() & !(1 << 4)));
+    0xA1, 1,  8, RES_4_C => unborrow!(cpu.set_c(cpu.c() & !(1 << 4)));
+    0xA2, 1,  8, RES_4_D => unborrow!(cpu.set_d(cpu.d() & !(1 << 4)));
+    0xA3, 1,  8, RES_4_E => unborrow!(cpu.set_e(cpu.e() & !(1 << 4)));
+    0xA4, 1,  8, RES_4_H => unborrow!(cpu.set_h(cpu.h() & !(1 << 4)));
+    0xA5, 1,  8, RES_4_L => unborrow!(cpu.set_l(cpu.l() & !(1 << 4)));
+    0xA6, 1, 16, RES_4_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = mem.read_u8(addr);
+        mem.write_u8(addr, value & !(1 << 4));
+    };
+    0xA7, 1,  8, RES_4_A => unborrow!(cpu.set_a(cpu.a() & !(1 << 4)));
+    0xA8, 1,  8, RES_5_B => unborrow!(cpu.set_b(cpu.b() & !(1 << 5)));
+    0xA9, 1,  8, RES_5_C => unborrow!(cpu.set_c(cpu.c() & !(1 << 5)));
+    0xAA, 1,  8, RES_5_D => unborrow!(cpu.set_d(cpu.d() & !(1 << 5)));
+    0xAB, 1,  8, RES_5_E => unborrow!(cpu.set_e(cpu.e() & !(1 << 5)));
+    0xAC, 1,  8, RES_5_H => unborrow!(cpu.set_h(cpu.h() & !(1 << 5)));
+    0xAD, 1,  8, RES_5_L => unborrow!(cpu.set_l(cpu.l() & !(1 << 5)));
+    0xAE, 1, 16, RES_5_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = mem.read_u8(addr);
+        mem.write_u8(addr, value & !(1 << 5));
+    };
+    0xAF, 1,  8, RES_5_A => unborrow!(cpu.set_a(cpu.a() & !(1 << 5)));
+    0xB0, 1,  8, RES_6_B => unborrow!(cpu.set_b(cpu.b() & !(1 << 6)));
+    0xB1, 1,  8, RES_6_C => unborrow!(cpu.set_c(cpu.c() & !(1 << 6)));
+    0xB2, 1,  8, RES_6_D => unborrow!(cpu.set_d(cpu.d() & !(1 << 6)));
+    0xB3, 1,  8, RES_6_E => unborrow!(cpu.set_e(cpu.e() & !(1 << 6)));
+    0xB4, 1,  8, RES_6_H => unborrow!(cpu.set_h(cpu.h() & !(1 << 6)));
+    0xB5, 1,  8, RES_6_L => unborrow!(cpu.set_l(cpu.l() & !(1 << 6)));
+    0xB6, 1, 16, RES_6_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = mem.read_u8(addr);
+        mem.write_u8(addr, value & !(1 << 6));
+    };
+    0xB7, 1,  8, RES_6_A => unborrow!(cpu.set_a(cpu.a() & !(1 << 6)));
+    0xB8, 1,  8, RES_7_B => unborrow!(cpu.set_b(cpu.b() & !(1 << 7)));
+    0xB9, 1,  8, RES_7_C => unborrow!(cpu.set_c(cpu.c() & !(1 << 7)));
+    0xBA, 1,  8, RES_7_D => unborrow!(cpu.set_d(cpu.d() & !(1 << 7)));
+    0xBB, 1,  8, RES_7_E => unborrow!(cpu.set_e(cpu.e() & !(1 << 7)));
+    0xBC, 1,  8, RES_7_H => unborrow!(cpu.set_h(cpu.h() & !(1 << 7)));
+    0xBD, 1,  8, RES_7_L => unborrow!(cpu.set_l(cpu.l() & !(1 << 7)));
+    0xBE, 1, 16, RES_7_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = mem.read_u8(addr);
+        mem.write_u8(addr, value & !(1 << 7));
+    };
+    0xBF, 1,  8, RES_7_A => unborrow!(cpu.set_a(cpu.a() & !(1 << 7)));
+    0xC0, 1,  8, SET_0_B => unborrow!(cpu.set_b(cpu.b() | 1 << 0));
+    0xC1, 1,  8, SET_0_C => unborrow!(cpu.set_c(cpu.c() | 1 << 0));
+    0xC2, 1,  8, SET_0_D => unborrow!(cpu.set_d(cpu.d() | 1 << 0));
+    0xC3, 1,  8, SET_0_E => unborrow!(cpu.set_e(cpu.e() | 1 << 0));
+    0xC4, 1,  8, SET_0_H => unborrow!(cpu.set_h(cpu.h() | 1 << 0));
+    0xC5, 1,  8, SET_0_L => unborrow!(cpu.set_l(cpu.l() | 1 << 0));
+    0xC6, 1, 16, SET_0_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = mem.read_u8(addr);
+        mem.write_u8(addr, value | 1 << 0);
+    };
+    0xC7, 1,  8, SET_0_A => unborrow!(cpu.set_a(cpu.a() | 1 << 0));
+    0xC8, 1,  8, SET_1_B => unborrow!(cpu.set_b(cpu.b() | 1 << 1));
+    0xC9, 1,  8, SET_1_C => unborrow!(cpu.set_c(cpu.c() | 1 << 1));
+    0xCA, 1,  8, SET_1_D => unborrow!(cpu.set_d(cpu.d() | 1 << 1));
+    0xCB, 1,  8, SET_1_E => unborrow!(cpu.set_e(cpu.e() | 1 << 1));
+    0xCC, 1,  8, SET_1_H => unborrow!(cpu.set_h(cpu.h() | 1 << 1));
+    0xCD, 1,  8, SET_1_L => unborrow!(cpu.set_l(cpu.l() | 1 << 1));
+    0xCE, 1, 16, SET_1_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = mem.read_u8(addr);
+        mem.write_u8(addr, value | 1 << 1);
+    };
+    0xCF, 1,  8, SET_1_A => unborrow!(cpu.set_a(cpu.a() | 1 << 1));
+    0xD0, 1,  8, SET_2_B => unborrow!(cpu.set_b(cpu.b() | 1 << 2));
+    0xD1, 1,  8, SET_2_C => unborrow!(cpu.set_c(cpu.c() | 1 << 2));
+    0xD2, 1,  8, SET_2_D => unborrow!(cpu.set_d(cpu.d() | 1 << 2));
+    0xD3, 1,  8, SET_2_E => unborrow!(cpu.set_e(cpu.e() | 1 << 2));
+    0xD4, 1,  8, SET_2_H => unborrow!(cpu.set_h(cpu.h() | 1 << 2));
+    0xD5, 1,  8, SET_2_L => unborrow!(cpu.set_l(cpu.l() | 1 << 2));
+    0xD6, 1, 16, SET_2_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = mem.read_u8(addr);
+        mem.write_u8(addr, value | 1 << 2);
+    };
+    0xD7, 1,  8, SET_2_A => unborrow!(cpu.set_a(cpu.a() | 1 << 2));
+    0xD8, 1,  8, SET_3_B => unborrow!(cpu.set_b(cpu.b() | 1 << 3));
+    0xD9, 1,  8, SET_3_C => unborrow!(cpu.set_c(cpu.c() | 1 << 3));
+    0xDA, 1,  8, SET_3_D => unborrow!(cpu.set_d(cpu.d() | 1 << 3));
+    0xDB, 1,  8, SET_3_E => unborrow!(cpu.set_e(cpu.e() | 1 << 3));
+    0xDC, 1,  8, SET_3_H => unborrow!(cpu.set_h(cpu.h() | 1 << 3));
+    0xDD, 1,  8, SET_3_L => unborrow!(cpu.set_l(cpu.l() | 1 << 3));
+    0xDE, 1, 16, SET_3_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = mem.read_u8(addr);
+        mem.write_u8(addr, value | 1 << 3);
+    };
+    0xDF, 1,  8, SET_3_A => unborrow!(cpu.set_a(cpu.a() | 1 << 3));
+    0xE0, 1,  8, SET_4_B => unborrow!(cpu.set_b(cpu.b() | 1 << 4));
+    0xE1, 1,  8, SET_4_C => unborrow!(cpu.set_c(cpu.c() | 1 << 4));
+    0xE2, 1,  8, SET_4_D => unborrow!(cpu.set_d(cpu.d() | 1 << 4));
+    0xE3, 1,  8, SET_4_E => unborrow!(cpu.set_e(cpu.e() | 1 << 4));
+    0xE4, 1,  8, SET_4_H => unborrow!(cpu.set_h(cpu.h() | 1 << 4));
+    0xE5, 1,  8, SET_4_L => unborrow!(cpu.set_l(cpu.l() | 1 << 4));
+    0xE6, 1, 16, SET_4_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = mem.read_u8(addr);
+        mem.write_u8(addr, value | 1 << 4);
+    };
+    0xE7, 1,  8, SET_4_A => unborrow!(cpu.set_a(cpu.a() | 1 << 4));
+    0xE8, 1,  8, SET_5_B => unborrow!(cpu.set_b(cpu.b() | 1 << 5));
+    0xE9, 1,  8, SET_5_C => unborrow!(cpu.set_c(cpu.c() | 1 << 5));
+    0xEA, 1,  8, SET_5_D => unborrow!(cpu.set_d(cpu.d() | 1 << 5));
+    0xEB, 1,  8, SET_5_E => unborrow!(cpu.set_e(cpu.e() | 1 << 5));
+    0xEC, 1,  8, SET_5_H => unborrow!(cpu.set_h(cpu.h() | 1 << 5));
+    0xED, 1,  8, SET_5_L => unborrow!(cpu.set_l(cpu.l() | 1 << 5));
+    0xEE, 1, 16, SET_5_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = mem.read_u8(addr);
+        mem.write_u8(addr, value | 1 << 5);
+    };
+    0xEF, 1,  8, SET_5_A => unborrow!(cpu.set_a(cpu.a() | 1 << 5));
+    0xF0, 1,  8, SET_6_B => unborrow!(cpu.set_b(cpu.b() | 1 << 6));
+    0xF1, 1,  8, SET_6_C => unborrow!(cpu.set_c(cpu.c() | 1 << 6));
+    0xF2, 1,  8, SET_6_D => unborrow!(cpu.set_d(cpu.d() | 1 << 6));
+    0xF3, 1,  8, SET_6_E => unborrow!(cpu.set_e(cpu.e() | 1 << 6));
+    0xF4, 1,  8, SET_6_H => unborrow!(cpu.set_h(cpu.h() | 1 << 6));
+    0xF5, 1,  8, SET_6_L => unborrow!(cpu.set_l(cpu.l() | 1 << 6));
+    0xF6, 1, 16, SET_6_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = mem.read_u8(addr);
+        mem.write_u8(addr, value | 1 << 6);
+    };
+    0xF7, 1,  8, SET_6_A => unborrow!(cpu.set_a(cpu.a() | 1 << 6));
+    0xF8, 1,  8, SET_7_B => unborrow!(cpu.set_b(cpu.b() | 1 << 7));
+    0xF9, 1,  8, SET_7_C => unborrow!(cpu.set_c(cpu.c() | 1 << 7));
+    0xFA, 1,  8, SET_7_D => unborrow!(cpu.set_d(cpu.d() | 1 << 7));
+    0xFB, 1,  8, SET_7_E => unborrow!(cpu.set_e(cpu.e() | 1 << 7));
+    0xFC, 1,  8, SET_7_H => unborrow!(cpu.set_h(cpu.h() | 1 << 7));
+    0xFD, 1,  8, SET_7_L => unborrow!(cpu.set_l(cpu.l() | 1 << 7));
+    0xFE, 1, 16, SET_7_MHL => {
+        let addr = Addr(cpu.hl());
+        let value = mem.read_u8(addr);
+        mem.write_u8(addr, value | 1 << 7);
+    };
+    0xFF, 1,  8, SET_7_A => unborrow!(cpu.set_a(cpu.a() | 1 << 7));
 }
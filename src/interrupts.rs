@@ -0,0 +1,85 @@
+use self::Interrupt::*;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Interrupt {
+    VBlank,
+    LcdStat,
+    Timer,
+    Serial,
+    Joypad,
+}
+
+impl Interrupt {
+    // Ordered by priority, highest first
+    pub const ALL: [Interrupt; 5] = [VBlank, LcdStat, Timer, Serial, Joypad];
+
+    pub fn bit(self) -> u8 {
+        match self {
+            VBlank  => 0b00001,
+            LcdStat => 0b00010,
+            Timer   => 0b00100,
+            Serial  => 0b01000,
+            Joypad  => 0b10000,
+        }
+    }
+
+    pub fn vector(self) -> u16 {
+        match self {
+            VBlank  => 0x40,
+            LcdStat => 0x48,
+            Timer   => 0x50,
+            Serial  => 0x58,
+            Joypad  => 0x60,
+        }
+    }
+}
+
+/// The IE (0xFFFF) and IF (0xFF0F) registers.
+pub struct Interrupts {
+    enable: u8,
+    flags: u8,
+}
+
+impl Interrupts {
+    pub fn new() -> Interrupts {
+        Interrupts {
+            enable: 0x00,
+            flags: 0x01,
+        }
+    }
+
+    pub fn read_enable(&self) -> u8 {
+        self.enable
+    }
+
+    pub fn write_enable(&mut self, value: u8) {
+        self.enable = value;
+    }
+
+    pub fn read_flags(&self) -> u8 {
+        // The upper 3 bits of IF are unused and always read as 1
+        self.flags | 0b11100000
+    }
+
+    pub fn write_flags(&mut self, value: u8) {
+        self.flags = value & 0b00011111;
+    }
+
+    pub fn request(&mut self, interrupt: Interrupt) {
+        self.flags |= interrupt.bit();
+    }
+
+    pub fn acknowledge(&mut self, interrupt: Interrupt) {
+        self.flags &= !interrupt.bit();
+    }
+
+    pub fn is_requested(&self, interrupt: Interrupt) -> bool {
+        self.flags & interrupt.bit() != 0
+    }
+
+    /// Returns the highest priority interrupt that is both requested and enabled.
+    pub fn pending(&self) -> Option<Interrupt> {
+        let active = self.enable & self.flags;
+        Interrupt::ALL.iter().cloned().find(|interrupt| active & interrupt.bit() != 0)
+    }
+}
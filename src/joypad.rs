@@ -0,0 +1,225 @@
+use std::path::Path;
+use std::io::{self, Read};
+use std::fs::File;
+use std::collections::VecDeque;
+use interrupts::{Interrupts, Interrupt};
+use self::Button::*;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [Right, Left, Up, Down, A, B, Select, Start];
+
+    pub fn from_name(name: &str) -> Option<Button> {
+        Button::ALL.iter().cloned().find(|button| format!("{:?}", button).eq_ignore_ascii_case(name))
+    }
+
+    // Directions occupy the low nibble, actions the high nibble
+    fn bit(self) -> u8 {
+        match self {
+            Right  => 0x01,
+            Left   => 0x02,
+            Up     => 0x04,
+            Down   => 0x08,
+            A      => 0x10,
+            B      => 0x20,
+            Select => 0x40,
+            Start  => 0x80,
+        }
+    }
+}
+
+/// The P1/JOYP register (0xFF00).
+///
+/// Bits 4 and 5 select the direction and action buttons respectively
+/// when cleared, bits 0 - 3 read as 0 for each pressed selected button.
+pub struct Joypad {
+    select: u8,
+    pressed: u8,
+}
+
+impl Joypad {
+    pub fn new() -> Joypad {
+        Joypad {
+            select: 0x00,
+            pressed: 0,
+        }
+    }
+
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.pressed & button.bit() != 0
+    }
+
+    // The selected buttons, active high
+    fn lines(&self) -> u8 {
+        let mut lines = 0;
+
+        if self.select & 0x10 == 0 {
+            lines |= self.pressed & 0x0F;
+        }
+        if self.select & 0x20 == 0 {
+            lines |= self.pressed >> 4;
+        }
+
+        lines
+    }
+
+    pub fn read(&self) -> u8 {
+        0xC0 | self.select | !self.lines() & 0x0F
+    }
+
+    pub fn write(&mut self, value: u8, interrupts: &mut Interrupts) {
+        let lines = self.lines();
+        self.select = value & 0x30;
+        self.update_interrupt(lines, interrupts);
+    }
+
+    pub fn press(&mut self, button: Button, interrupts: &mut Interrupts) {
+        let lines = self.lines();
+        self.pressed |= button.bit();
+        self.update_interrupt(lines, interrupts);
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.pressed &= !button.bit();
+    }
+
+    // The interrupt is requested when any of P10 - P13 goes from high to low
+    fn update_interrupt(&self, old_lines: u8, interrupts: &mut Interrupts) {
+        if self.lines() & !old_lines != 0 {
+            interrupts.request(Interrupt::Joypad);
+        }
+    }
+}
+
+/// Scripted input, one line per change: a frame number followed by
+/// the buttons held from that frame on, e.g. `120 A Start`.
+/// Empty lines and lines starting with `#` are ignored.
+pub struct InputScript {
+    events: VecDeque<(u64, Vec<Button>)>,
+}
+
+impl InputScript {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<InputScript> {
+        let mut text = String::new();
+        let mut file = File::open(path)?;
+        file.read_to_string(&mut text)?;
+
+        let mut events = VecDeque::new();
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("invalid input line: {}", line));
+            let mut words = line.split_whitespace();
+            let frame = words.next().and_then(|frame| frame.parse().ok()).ok_or_else(&invalid)?;
+            let mut buttons = Vec::new();
+            for word in words {
+                buttons.push(Button::from_name(word).ok_or_else(&invalid)?);
+            }
+
+            events.push_back((frame, buttons));
+        }
+
+        Ok(InputScript { events: events })
+    }
+
+    /// Returns the buttons to hold if the input changes at or before the given frame.
+    pub fn next(&mut self, frame: u64) -> Option<Vec<Button>> {
+        let mut buttons = None;
+
+        while self.events.front().map_or(false, |&(start, _)| start <= frame) {
+            buttons = self.events.pop_front().map(|(_, buttons)| buttons);
+        }
+
+        buttons
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SELECT_DIRECTIONS: u8 = 0x20;
+    const SELECT_ACTIONS: u8 = 0x10;
+    const SELECT_NONE: u8 = 0x30;
+
+    fn joypad_flag(interrupts: &mut Interrupts) -> bool {
+        let requested = interrupts.is_requested(Interrupt::Joypad);
+        interrupts.acknowledge(Interrupt::Joypad);
+        requested
+    }
+
+    #[test]
+    fn selected_group_reads_pressed_buttons() {
+        let mut joypad = Joypad::new();
+        let mut interrupts = Interrupts::new();
+
+        joypad.press(Right, &mut interrupts);
+        joypad.press(Start, &mut interrupts);
+
+        joypad.write(SELECT_DIRECTIONS, &mut interrupts);
+        assert_eq!(joypad.read(), 0xC0 | SELECT_DIRECTIONS | 0x0E);
+
+        joypad.write(SELECT_ACTIONS, &mut interrupts);
+        assert_eq!(joypad.read(), 0xC0 | SELECT_ACTIONS | 0x07);
+
+        joypad.write(SELECT_NONE, &mut interrupts);
+        assert_eq!(joypad.read(), 0xFF);
+
+        joypad.release(Start);
+        joypad.write(SELECT_ACTIONS, &mut interrupts);
+        assert_eq!(joypad.read(), 0xC0 | SELECT_ACTIONS | 0x0F);
+    }
+
+    #[test]
+    fn press_in_selected_group_requests_interrupt() {
+        let mut joypad = Joypad::new();
+        let mut interrupts = Interrupts::new();
+
+        joypad.write(SELECT_DIRECTIONS, &mut interrupts);
+        joypad.press(Down, &mut interrupts);
+        assert!(joypad_flag(&mut interrupts));
+
+        // Down shares a line with Select, but its group is deselected
+        joypad.release(Down);
+        joypad.write(SELECT_ACTIONS, &mut interrupts);
+        joypad.press(Select, &mut interrupts);
+        assert!(joypad_flag(&mut interrupts));
+        joypad.press(Down, &mut interrupts);
+        assert!(!joypad_flag(&mut interrupts));
+    }
+
+    #[test]
+    fn press_in_unselected_group_requests_no_interrupt() {
+        let mut joypad = Joypad::new();
+        let mut interrupts = Interrupts::new();
+
+        joypad.write(SELECT_ACTIONS, &mut interrupts);
+        joypad.press(Up, &mut interrupts);
+        assert!(!joypad_flag(&mut interrupts));
+
+        // Selecting the group pulls the line low
+        joypad.write(SELECT_DIRECTIONS, &mut interrupts);
+        assert!(joypad_flag(&mut interrupts));
+    }
+
+    #[test]
+    fn button_names() {
+        assert_eq!(Button::from_name("start"), Some(Start));
+        assert_eq!(Button::from_name("A"), Some(A));
+        assert_eq!(Button::from_name("turbo"), None);
+    }
+}
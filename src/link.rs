@@ -0,0 +1,344 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::Path;
+use serial::{SerialDevice, Port};
+
+/// Cycles between two synchronizations of the linked instances.
+pub const SYNC_CYCLES: u32 = 512;
+
+const FRAME_SIZE: usize = 3;
+const READY: u8 = 0x01;
+const SENT: u8 = 0x02;
+
+/// What each side tells the other at every sync point.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct Frame {
+    port: Port,
+    sent: Option<u8>,
+}
+
+impl Frame {
+    fn encode(&self) -> [u8; FRAME_SIZE] {
+        let mut flags = 0;
+        if self.port.external_ready {
+            flags |= READY;
+        }
+        if self.sent.is_some() {
+            flags |= SENT;
+        }
+
+        [flags, self.port.data, self.sent.unwrap_or(0xFF)]
+    }
+
+    fn decode(bytes: [u8; FRAME_SIZE]) -> Frame {
+        Frame {
+            port: Port {
+                data: bytes[1],
+                external_ready: bytes[0] & READY != 0,
+            },
+            sent: if bytes[0] & SENT != 0 { Some(bytes[2]) } else { None },
+        }
+    }
+}
+
+/// Carries frames between the two ends of a link cable.
+trait Wire {
+    fn send(&mut self, frame: Frame) -> io::Result<()>;
+    fn receive(&mut self) -> io::Result<Frame>;
+}
+
+struct StreamWire<S> {
+    stream: S,
+}
+
+impl<S: Read + Write> Wire for StreamWire<S> {
+    fn send(&mut self, frame: Frame) -> io::Result<()> {
+        self.stream.write_all(&frame.encode())?;
+        self.stream.flush()
+    }
+
+    fn receive(&mut self) -> io::Result<Frame> {
+        let mut bytes = [0; FRAME_SIZE];
+        self.stream.read_exact(&mut bytes)?;
+        Ok(Frame::decode(bytes))
+    }
+}
+
+/// A link cable connecting two emulator instances.
+///
+/// Both ends exchange a frame every `SYNC_CYCLES` emulated cycles, carrying
+/// their SB, whether they wait for an external clock and the byte they
+/// started sending with their internal clock, if any. The frame sent at
+/// one sync point is used by the other end at its next sync point, so what
+/// happens only depends on emulated time and both sides stay deterministic.
+///
+/// A byte sent with the internal clock receives the other side's SB from
+/// its last frame if it was waiting for an external clock, or 0xFF otherwise.
+/// At most one byte per sync period is transferred.
+pub struct LinkCable {
+    wire: Box<dyn Wire>,
+    connected: bool,
+    cycles: u32,
+    synced: bool,
+    // Frames of the other side that didn't arrive yet
+    owed: u32,
+    sent: Option<u8>,
+    peer: Port,
+    received: Option<u8>,
+}
+
+impl LinkCable {
+    fn new(wire: Box<dyn Wire>) -> LinkCable {
+        LinkCable {
+            wire: wire,
+            connected: true,
+            cycles: 0,
+            synced: false,
+            owed: 0,
+            sent: None,
+            peer: Port { data: 0xFF, external_ready: false },
+            received: None,
+        }
+    }
+
+    fn from_stream<S: Read + Write + 'static>(stream: S) -> LinkCable {
+        LinkCable::new(Box::new(StreamWire { stream: stream }))
+    }
+
+    /// Waits for the other instance to connect over TCP.
+    pub fn listen_tcp<A: ToSocketAddrs>(addr: A) -> io::Result<LinkCable> {
+        let listener = TcpListener::bind(addr)?;
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        Ok(LinkCable::from_stream(stream))
+    }
+
+    pub fn connect_tcp<A: ToSocketAddrs>(addr: A) -> io::Result<LinkCable> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Ok(LinkCable::from_stream(stream))
+    }
+
+    /// Waits for the other instance to connect over a Unix domain socket.
+    #[cfg(unix)]
+    pub fn listen_unix<P: AsRef<Path>>(path: P) -> io::Result<LinkCable> {
+        let listener = UnixListener::bind(path)?;
+        let (stream, _) = listener.accept()?;
+        Ok(LinkCable::from_stream(stream))
+    }
+
+    #[cfg(unix)]
+    pub fn connect_unix<P: AsRef<Path>>(path: P) -> io::Result<LinkCable> {
+        Ok(LinkCable::from_stream(UnixStream::connect(path)?))
+    }
+
+    fn sync(&mut self, port: Port) {
+        let frame = Frame { port: port, sent: self.sent.take() };
+
+        if let Err(err) = self.sync_wire(frame) {
+            println!("Link cable disconnected: {}", err);
+            self.connected = false;
+            self.peer = Port { data: 0xFF, external_ready: false };
+        }
+    }
+
+    fn sync_wire(&mut self, frame: Frame) -> io::Result<()> {
+        self.wire.send(frame)?;
+
+        // The other side's first frame is only needed from the second sync point on
+        if !self.synced {
+            self.synced = true;
+            return Ok(());
+        }
+
+        // An instance that got ahead catches up with the missing frames at a later sync point
+        self.owed += 1;
+
+        while self.owed > 0 {
+            let peer = match self.wire.receive() {
+                Ok(peer) => peer,
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(err) => return Err(err),
+            };
+            self.owed -= 1;
+            self.peer = peer.port;
+
+            // A byte clocked by the other side only arrives if this side was waiting for one
+            if frame.port.external_ready && peer.sent.is_some() {
+                self.received = peer.sent;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl SerialDevice for LinkCable {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        if !self.connected {
+            return 0xFF;
+        }
+
+        self.sent = Some(byte);
+
+        if self.peer.external_ready { self.peer.data } else { 0xFF }
+    }
+
+    fn external_clock(&mut self, _byte: u8) -> Option<u8> {
+        self.received.take()
+    }
+
+    fn tick(&mut self, cycles: u16, port: Port) {
+        if !self.connected {
+            return;
+        }
+
+        self.cycles += cycles as u32;
+
+        while self.cycles >= SYNC_CYCLES {
+            self.cycles -= SYNC_CYCLES;
+            self.sync(port);
+        }
+    }
+}
+
+// Linking two instances in the same thread, for tests
+#[cfg(test)]
+mod local {
+    use std::io;
+    use std::rc::Rc;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use cpu::Cpu;
+    use memory::Memory;
+    use super::{LinkCable, Wire, Frame, SYNC_CYCLES};
+
+    struct LocalWire {
+        outgoing: Rc<RefCell<VecDeque<Frame>>>,
+        incoming: Rc<RefCell<VecDeque<Frame>>>,
+    }
+
+    impl Wire for LocalWire {
+        fn send(&mut self, frame: Frame) -> io::Result<()> {
+            self.outgoing.borrow_mut().push_back(frame);
+            Ok(())
+        }
+
+        fn receive(&mut self) -> io::Result<Frame> {
+            self.incoming.borrow_mut().pop_front()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::WouldBlock, "linked instance is behind"))
+        }
+    }
+
+    impl LinkCable {
+        /// Two ends of a cable for instances running in the same thread,
+        /// which have to be advanced in turns with `run_linked`.
+        pub fn local_pair() -> (LinkCable, LinkCable) {
+            let a_to_b = Rc::new(RefCell::new(VecDeque::new()));
+            let b_to_a = Rc::new(RefCell::new(VecDeque::new()));
+
+            let a = LinkCable::new(Box::new(LocalWire { outgoing: a_to_b.clone(), incoming: b_to_a.clone() }));
+            let b = LinkCable::new(Box::new(LocalWire { outgoing: b_to_a, incoming: a_to_b }));
+
+            (a, b)
+        }
+    }
+
+    /// Runs two instances connected by `LinkCable::local_pair` in the same thread
+    /// for the given number of cycles, taking turns every sync period.
+    /// The cables have to be attached before either instance ran.
+    pub fn run_linked(a: (&mut Cpu, &mut Memory), b: (&mut Cpu, &mut Memory), cycles: u64) {
+        let (cpu_a, memory_a) = a;
+        let (cpu_b, memory_b) = b;
+        let end = memory_a.cycles() + cycles;
+
+        while memory_a.cycles() < end {
+            let sync_cycles = SYNC_CYCLES as u64;
+            let turn_end = (memory_a.cycles() / sync_cycles + 1) * sync_cycles;
+
+            while memory_a.cycles() < turn_end {
+                cpu_a.step(memory_a);
+            }
+            while memory_b.cycles() < turn_end {
+                cpu_b.step(memory_b);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::local::run_linked;
+    use cpu::Cpu;
+    use memory::{Memory, Addr};
+    use interrupts::Interrupt;
+    use rom::Rom;
+
+    fn linked_pair(master: &[u8], slave: &[u8]) -> ((Cpu, Memory), (Cpu, Memory)) {
+        let (cable_a, cable_b) = LinkCable::local_pair();
+
+        let mut memory_a = Memory::new(Rom::with_program(master));
+        let mut memory_b = Memory::new(Rom::with_program(slave));
+        memory_a.serial.attach(Box::new(cable_a));
+        memory_b.serial.attach(Box::new(cable_b));
+
+        ((Cpu::new(), memory_a), (Cpu::new(), memory_b))
+    }
+
+    #[test]
+    fn internal_clock_transfers_to_external_clock() {
+        let master = [
+            0xF3,             // DI
+            0x06, 0x00,       // LD B,0
+            0x05,             // DEC B
+            0x20, 0xFD,       // JR NZ,-3 (give the other side time to get ready)
+            0x3E, 0x42,       // LD A,0x42
+            0xE0, 0x01,       // LDH (SB),A
+            0x3E, 0x81,       // LD A,0x81
+            0xE0, 0x02,       // LDH (SC),A
+            0x18, 0xFE,       // JR -2
+        ];
+        let slave = [
+            0xF3,             // DI
+            0x3E, 0x99,       // LD A,0x99
+            0xE0, 0x01,       // LDH (SB),A
+            0x3E, 0x80,       // LD A,0x80
+            0xE0, 0x02,       // LDH (SC),A
+            0x18, 0xFE,       // JR -2
+        ];
+        let ((mut cpu_a, mut memory_a), (mut cpu_b, mut memory_b)) = linked_pair(&master, &slave);
+
+        run_linked((&mut cpu_a, &mut memory_a), (&mut cpu_b, &mut memory_b), 32 * SYNC_CYCLES as u64);
+
+        assert_eq!(memory_a.read_u8_untimed(Addr(0xFF01)), 0x99);
+        assert_eq!(memory_b.read_u8_untimed(Addr(0xFF01)), 0x42);
+        assert!(memory_a.interrupts.is_requested(Interrupt::Serial));
+        assert!(memory_b.interrupts.is_requested(Interrupt::Serial));
+    }
+
+    #[test]
+    fn instance_ahead_catches_up_instead_of_disconnecting() {
+        let (mut a, mut b) = LinkCable::local_pair();
+        let idle = Port { data: 0xFF, external_ready: false };
+        let waiting = Port { data: 0x99, external_ready: true };
+
+        // `a` runs three sync periods before `b` gets its turn
+        for _ in 0..3 {
+            a.tick(SYNC_CYCLES as u16, idle);
+        }
+        assert!(a.connected);
+        assert_eq!(a.owed, 2);
+
+        for _ in 0..3 {
+            b.tick(SYNC_CYCLES as u16, waiting);
+        }
+        a.tick(SYNC_CYCLES as u16, idle);
+        assert!(a.connected);
+        assert_eq!(a.owed, 0);
+        assert_eq!(a.exchange(0x42), 0x99);
+    }
+}
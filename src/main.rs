@@ -1,42 +1,246 @@
+// Struct fields are initialized explicitly, and names like HALT and VRAM follow the hardware docs
+#![allow(clippy::redundant_field_names, clippy::upper_case_acronyms)]
+
 extern crate byteorder;
 extern crate monster;
 extern crate bit_range;
 #[macro_use] extern crate unborrow;
 #[macro_use] extern crate custom_derive;
-#[macro_use] extern crate conv;
-use std::ascii::AsciiExt;
+extern crate conv;
 use std::str;
-use monster::incubation::SliceTakeWhile;
+use std::env;
+use std::io::{self, Read, Write, BufWriter};
+use std::fs::File;
+use std::path::Path;
 use bit_range::BitRange;
 
 mod header;
 mod cpu;
 mod instructions;
+mod interrupts;
 mod memory;
 mod rom;
 mod mapper;
+mod timer;
+mod ppu;
+mod dma;
+mod apu;
+mod wav;
+mod gbs;
+mod joypad;
+mod serial;
+mod link;
+mod png;
+mod printer;
 
 use self::header::*;
 use self::cpu::*;
 use self::rom::*;
 use self::memory::*;
+use self::wav::WavWriter;
+use self::gbs::{Gbs, Player};
+use self::joypad::InputScript;
+use self::serial::{SerialDevice, SerialLogger};
+use self::link::LinkCable;
+use self::printer::Printer;
+use self::mapper::ClockSource;
+use self::ppu::{Renderer, SCREEN_WIDTH, SCREEN_HEIGHT};
+
+// One minute of emulated time
+const MAX_CYCLES: u64 = 4194304 * 60;
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+const DEFAULT_GBS_SECONDS: u32 = 60;
 
 fn main() {
-    let rom = Rom::load("./gb-tests/cpu_instrs/individual/06-ld r,r.gb").expect("rom");
+    let (flags, args): (Vec<String>, Vec<String>) = env::args().skip(1).partition(|arg| arg.starts_with("--"));
+    let force_cgb = flags.iter().any(|flag| flag == "--cgb");
+    let wav_path = flag_value(&flags, "--wav");
+    let sample_rate = flag_value(&flags, "--sample-rate")
+        .map(|rate| rate.parse().expect("sample rate"))
+        .unwrap_or(DEFAULT_SAMPLE_RATE);
+
+    if args.first().map(|arg| arg == "gbs").unwrap_or(false) {
+        let path = args.get(1).expect("usage: rust-gb gbs <file.gbs> [--song=N] [--seconds=N] [--wav=out.wav]");
+        play_gbs(path, &flags, wav_path, sample_rate);
+        return;
+    }
+
+    let path = args.into_iter().next().unwrap_or("./gb-tests/cpu_instrs/individual/06-ld r,r.gb".into());
+    let rom = Rom::load(&path).expect("rom");
+    let save_path = Path::new(&path).with_extension("sav");
 
     print_logo(logo(&rom.data));
     println!("Rom type: {:?}", rom.typ());
     println!("Rom size: {:?}", rom.rom_size());
     println!("Ram size: {:?}", rom.ram_size());
 
-    let mut memory = Memory::new(rom);
+    // CGB only ROMs always run on a CGB, others only with `--cgb`
+    let model = if force_cgb || rom.requires_cgb() { Model::Cgb } else { Model::Dmg };
+    println!("Model: {:?}", model);
+
+    let mut memory = Memory::with_model(rom, model);
     let mut cpu = Cpu::new();
 
-    while !cpu.is_stalling {
-        cpu.step(&mut memory);
+    // `--renderer=fifo` emulates the pixel FIFO instead of drawing whole scanlines
+    match flag_value(&flags, "--renderer").as_deref() {
+        Some("fifo") => memory.ppu.set_renderer(Renderer::Fifo),
+        Some("scanline") | None => {},
+        Some(renderer) => panic!("Unknown renderer: {}", renderer),
+    }
+
+    // `--trace` prints each instruction, `--break=ADDR` stops at a hex address
+    cpu.trace = flags.iter().any(|flag| flag == "--trace");
+    cpu.breakpoint = flag_value(&flags, "--break")
+        .map(|addr| u16::from_str_radix(addr.trim_start_matches("0x"), 16).expect("breakpoint"));
+
+    // `--rtc=emulated` runs the cartridge clock on emulated time instead of the host's
+    match flag_value(&flags, "--rtc").as_deref() {
+        Some("emulated") => memory.set_clock_source(ClockSource::Emulated),
+        Some("host") | None => {},
+        Some(source) => panic!("Unknown clock source: {}", source),
+    }
+
+    // `--rumble` reports the motor of rumble carts turning on and off
+    if flags.iter().any(|flag| flag == "--rumble") {
+        memory.set_rumble_callback(Box::new(|on| println!("Rumble: {}", if on { "on" } else { "off" })));
+    }
+
+    if memory.battery_save().is_some() && save_path.exists() {
+        load_save(&save_path, &mut memory).expect("save");
+    }
+
+    memory.serial.attach(serial_device(&flags));
+
+    let mut input = flag_value(&flags, "--input").map(|path| InputScript::load(path).expect("input"));
+
+    let mut wav = wav_path.map(|path| WavWriter::create(path, sample_rate).expect("wav"));
+    let mut samples = vec![0.0; 4096];
+    if wav.is_some() {
+        memory.apu.set_sample_rate(sample_rate);
+    }
+
+    let mut cycles = 0;
+
+    while cycles < MAX_CYCLES {
+        if cpu.is_locked_up(&memory) || cpu.state() == State::Stopped {
+            break;
+        }
+
+        cycles += cpu.step(&mut memory) as u64;
+
+        if let Some(ref mut input) = input {
+            if let Some(buttons) = input.next(memory.ppu.frame_count()) {
+                memory.set_buttons(&buttons);
+            }
+        }
+
+        if let Some(ref mut wav) = wav {
+            if memory.apu.available_samples() >= samples.len() {
+                let count = memory.apu.pull_samples(&mut samples);
+                wav.write_samples(&samples[..count]).expect("wav");
+            }
+        }
+    }
+
+    if let Some(mut wav) = wav {
+        while memory.apu.available_samples() > 0 {
+            let count = memory.apu.pull_samples(&mut samples);
+            wav.write_samples(&samples[..count]).expect("wav");
+        }
+        wav.finish().expect("wav");
+    }
+
+    // `--screenshot=FILE` saves the last frame as a PNG
+    if let Some(path) = flag_value(&flags, "--screenshot") {
+        save_screenshot(&path, memory.ppu.frame()).expect("screenshot");
+    }
+
+    if let Some(save) = memory.battery_save() {
+        let mut file = File::create(&save_path).expect("save");
+        file.write_all(&save).expect("save");
     }
 }
 
+fn save_screenshot(path: &str, frame: &[u8]) -> io::Result<()> {
+    let pixels: Vec<u8> = frame.iter().map(|&shade| 255 - shade * 85).collect();
+    let mut file = BufWriter::new(File::create(path)?);
+    png::write_grayscale(&mut file, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, &pixels)
+}
+
+// Battery backed RAM and clocks are kept next to the ROM, with the `.sav` extension
+fn load_save(path: &Path, memory: &mut Memory) -> io::Result<()> {
+    let mut data = Vec::new();
+    let mut file = File::open(path)?;
+    file.read_to_end(&mut data)?;
+    memory.load_battery_save(&data);
+    Ok(())
+}
+
+// `--link-listen=ADDR` and `--link-connect=ADDR` link to another instance over
+// TCP, or a Unix domain socket if ADDR starts with `unix:`.
+// `--printer=DIR` attaches a Game Boy Printer saving its printouts to DIR.
+fn serial_device(flags: &[String]) -> Box<dyn SerialDevice> {
+    if let Some(dir) = flag_value(flags, "--printer") {
+        Box::new(Printer::new(dir))
+    } else if let Some(addr) = flag_value(flags, "--link-listen") {
+        println!("Waiting for link cable connection on {}", addr);
+        let link = match unix_path(&addr) {
+            Some(path) => LinkCable::listen_unix(path),
+            None => LinkCable::listen_tcp(&*addr),
+        };
+        Box::new(link.expect("link cable"))
+    } else if let Some(addr) = flag_value(flags, "--link-connect") {
+        let link = match unix_path(&addr) {
+            Some(path) => LinkCable::connect_unix(path),
+            None => LinkCable::connect_tcp(&*addr),
+        };
+        Box::new(link.expect("link cable"))
+    } else {
+        Box::new(SerialLogger::new())
+    }
+}
+
+fn unix_path(addr: &str) -> Option<&str> {
+    addr.strip_prefix("unix:")
+}
+
+// Renders a song of a GBS file to a WAV file, next to the GBS file by default
+fn play_gbs(path: &str, flags: &[String], wav_path: Option<String>, sample_rate: u32) {
+    let gbs = Gbs::load(path).expect("gbs");
+
+    println!("Title: {}", gbs.title);
+    println!("Author: {}", gbs.author);
+    println!("Copyright: {}", gbs.copyright);
+    println!("Songs: {}", gbs.song_count);
+
+    let song = flag_value(flags, "--song")
+        .map(|song| song.parse().expect("song"))
+        .unwrap_or(gbs.first_song);
+    let seconds = flag_value(flags, "--seconds")
+        .map(|seconds| seconds.parse().expect("seconds"))
+        .unwrap_or(DEFAULT_GBS_SECONDS);
+    let wav_path = wav_path.unwrap_or_else(|| Path::new(path).with_extension("wav").to_string_lossy().into_owned());
+
+    if song == 0 || song > gbs.song_count {
+        panic!("Song {} out of range 1 - {}", song, gbs.song_count);
+    }
+
+    println!("Rendering song {} ({} s) to {}", song, seconds, wav_path);
+
+    let mut wav = WavWriter::create(wav_path, sample_rate).expect("wav");
+    let mut player = Player::new(gbs, song - 1, sample_rate);
+    player.render(seconds, &mut wav).expect("wav");
+    wav.finish().expect("wav");
+}
+
+// Flags with values are passed as `--name=value`
+fn flag_value(flags: &[String], name: &str) -> Option<String> {
+    let prefix = format!("{}=", name);
+    flags.iter()
+        .find(|flag| flag.starts_with(&prefix))
+        .map(|flag| flag[prefix.len() ..].to_string())
+}
+
 // Kudos to Pokechu22: http://stackoverflow.com/a/24630503
 fn matrix_from_logo(logo: &[u8]) -> [[bool; 48]; 8] {
     debug_assert!(logo.len() >= 0x30);
@@ -72,6 +276,6 @@ fn print_logo(logo: &[u8]) {
                 print!(" ");
             }
         }
-        println!("");
+        println!();
     }
 }
\ No newline at end of file
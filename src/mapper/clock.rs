@@ -0,0 +1,171 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Emulated cycles per second at the normal speed
+const CYCLES_PER_SECOND: u64 = 4194304;
+
+/// Clock registers stored in a battery save's footer.
+pub const FOOTER_WORDS: usize = 10;
+const FOOTER_SIZE: usize = FOOTER_WORDS * 4 + 8;
+// Older saves end with a 32 bit timestamp
+const SHORT_FOOTER_SIZE: usize = FOOTER_WORDS * 4 + 4;
+
+/// What drives a cartridge's real-time clock.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ClockSource {
+    /// The host's wall clock
+    Host,
+    /// Emulated time, which keeps runs deterministic
+    Emulated,
+}
+
+/// Counts the seconds passed for a cartridge's real-time clock.
+pub struct Clock {
+    source: ClockSource,
+    cycles: u64,
+    last: SystemTime,
+}
+
+impl Clock {
+    pub fn new(source: ClockSource) -> Clock {
+        Clock {
+            source: source,
+            cycles: 0,
+            last: SystemTime::now(),
+        }
+    }
+
+    pub fn set_source(&mut self, source: ClockSource) {
+        *self = Clock::new(source);
+    }
+
+    pub fn tick(&mut self, cycles: u16) {
+        if self.source == ClockSource::Emulated {
+            self.cycles += cycles as u64;
+        }
+    }
+
+    /// The whole seconds passed since they were last taken.
+    pub fn seconds_pending(&self) -> u64 {
+        match self.source {
+            ClockSource::Host => self.last.elapsed().map(|elapsed| elapsed.as_secs()).unwrap_or(0),
+            ClockSource::Emulated => self.cycles / CYCLES_PER_SECOND,
+        }
+    }
+
+    /// Takes the whole seconds passed since the last call,
+    /// keeping the fraction of the current one.
+    pub fn take_seconds(&mut self) -> u64 {
+        let seconds = self.seconds_pending();
+
+        match self.source {
+            ClockSource::Host => self.last += Duration::from_secs(seconds),
+            ClockSource::Emulated => self.cycles -= seconds * CYCLES_PER_SECOND,
+        }
+
+        seconds
+    }
+
+    /// The seconds passed since the given UNIX time,
+    /// always 0 when running on emulated time.
+    pub fn seconds_since(&self, timestamp: u64) -> u64 {
+        match self.source {
+            ClockSource::Host => unix_time().saturating_sub(timestamp),
+            ClockSource::Emulated => 0,
+        }
+    }
+
+    /// Starts counting the current second anew.
+    pub fn reset_subsecond(&mut self) {
+        self.cycles = 0;
+        self.last = SystemTime::now();
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0)
+}
+
+/// Encodes the footer that battery saves of carts with a clock commonly end with:
+/// the clock registers as 32 bit little endian words followed by
+/// the 64 bit UNIX time they were saved at.
+pub fn write_footer(words: &[u32; FOOTER_WORDS]) -> Vec<u8> {
+    let mut footer = Vec::with_capacity(FOOTER_SIZE);
+
+    for &word in words {
+        write_le(&mut footer, word as u64, 4);
+    }
+    write_le(&mut footer, unix_time(), 8);
+
+    footer
+}
+
+/// Decodes a footer written by `write_footer`, returning the clock registers
+/// and the UNIX time they were saved at.
+pub fn read_footer(footer: &[u8]) -> Option<([u32; FOOTER_WORDS], u64)> {
+    if footer.len() != FOOTER_SIZE && footer.len() != SHORT_FOOTER_SIZE {
+        return None;
+    }
+
+    let mut words = [0; FOOTER_WORDS];
+    for (word, bytes) in words.iter_mut().zip(footer.chunks(4)) {
+        *word = read_le(bytes) as u32;
+    }
+
+    Some((words, read_le(&footer[FOOTER_WORDS * 4 ..])))
+}
+
+fn write_le(out: &mut Vec<u8>, value: u64, len: usize) {
+    for i in 0 .. len {
+        out.push((value >> (i * 8)) as u8);
+    }
+}
+
+fn read_le(bytes: &[u8]) -> u64 {
+    bytes.iter().rev().fold(0, |value, &byte| value << 8 | byte as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emulated_clock_counts_cycles() {
+        let mut clock = Clock::new(ClockSource::Emulated);
+
+        for _ in 0 .. CYCLES_PER_SECOND / 0x8000 * 3 - 1 {
+            clock.tick(0x8000);
+        }
+        assert_eq!(clock.take_seconds(), 2);
+        clock.tick(0x8000);
+        assert_eq!(clock.take_seconds(), 1);
+        assert_eq!(clock.take_seconds(), 0);
+
+        clock.tick(0x8000);
+        clock.reset_subsecond();
+        assert_eq!(clock.cycles, 0);
+    }
+
+    #[test]
+    fn footer_round_trip() {
+        let words = [1, 2, 3, 4, 5, 6, 7, 8, 9, 0x12345678];
+        let footer = write_footer(&words);
+        assert_eq!(footer.len(), 48);
+        assert_eq!(&footer[36..40], &[0x78, 0x56, 0x34, 0x12]);
+
+        let (read, timestamp) = read_footer(&footer).unwrap();
+        assert_eq!(read, words);
+        assert!(unix_time() - timestamp < 2);
+
+        assert_eq!(read_footer(&footer[..44]).map(|(words, _)| words), Some(words));
+        assert_eq!(read_footer(&footer[..40]), None);
+    }
+
+    #[test]
+    fn catches_up_with_host_time_only() {
+        let an_hour_ago = unix_time() - 3600;
+
+        let seconds = Clock::new(ClockSource::Host).seconds_since(an_hour_ago);
+        assert!((3600 .. 3602).contains(&seconds));
+        assert_eq!(Clock::new(ClockSource::Emulated).seconds_since(an_hour_ago), 0);
+    }
+}
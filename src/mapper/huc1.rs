@@ -0,0 +1,104 @@
+use super::{Mapper, rom_offset, ram_offset};
+use memory::Addr;
+
+// Read in IR mode while no light is received
+const IR_DARK: u8 = 0xC0;
+
+/// Hudson's HuC1: up to 1 MiB of ROM, 32 KiB of RAM and an infrared port.
+///
+/// There's no RAM enable, instead writing 0x0E to 0x0000 - 0x1FFF maps
+/// the IR port to 0xA000 - 0xBFFF and any other value maps the RAM back.
+/// The ROM bank takes 6 bits at 0x2000 - 0x3FFF, the RAM bank 2 bits at 0x4000 - 0x5FFF.
+pub struct Huc1 {
+    ir_mode: bool,
+    rom_bank: u8,
+    ram_bank: u8,
+    ram: Vec<u8>,
+}
+
+impl Huc1 {
+    pub fn new(ram_size: usize) -> Huc1 {
+        Huc1 {
+            ir_mode: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            ram: vec![0; ram_size],
+        }
+    }
+}
+
+impl Mapper for Huc1 {
+    fn read_u8(&mut self, rom: &[u8], addr: Addr) -> u8 {
+        match *addr {
+            0x0000 ..= 0x3FFF => rom[rom_offset(rom, 0, addr)],
+            0x4000 ..= 0x7FFF => rom[rom_offset(rom, self.rom_bank as usize, addr)],
+            // Nothing is on the other end to send light
+            0xA000 ..= 0xBFFF if self.ir_mode => IR_DARK,
+            0xA000 ..= 0xBFFF => if self.ram.is_empty() {
+                0xFF
+            } else {
+                self.ram[ram_offset(&self.ram, self.ram_bank as usize, addr)]
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn write_u8(&mut self, _rom: &[u8], addr: Addr, value: u8) {
+        match *addr {
+            0x0000 ..= 0x1FFF => self.ir_mode = value & 0x0F == 0x0E,
+            0x2000 ..= 0x3FFF => self.rom_bank = value & 0x3F,
+            0x4000 ..= 0x5FFF => self.ram_bank = value & 0x03,
+            0x6000 ..= 0x7FFF => {},
+            // Bit 0 turns the LED on, whose light goes nowhere
+            0xA000 ..= 0xBFFF if self.ir_mode => {},
+            0xA000 ..= 0xBFFF => if !self.ram.is_empty() {
+                let offset = ram_offset(&self.ram, self.ram_bank as usize, addr);
+                self.ram[offset] = value;
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mapper::tests::{banked_rom, bank_at};
+
+    #[test]
+    fn selects_banks() {
+        let rom = banked_rom(64);
+        let mut mbc = Huc1::new(0x8000);
+
+        mbc.write_u8(&rom, Addr(0x2000), 0xFF);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x3F);
+        mbc.write_u8(&rom, Addr(0x2000), 0x00);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x00);
+
+        mbc.write_u8(&rom, Addr(0x4000), 0x02);
+        mbc.write_u8(&rom, Addr(0xA000), 0x22);
+        assert_eq!(mbc.ram()[0x4000], 0x22);
+    }
+
+    #[test]
+    fn ir_mode_replaces_ram() {
+        let rom = banked_rom(2);
+        let mut mbc = Huc1::new(0x2000);
+        mbc.write_u8(&rom, Addr(0xA000), 0x12);
+
+        mbc.write_u8(&rom, Addr(0x0000), 0x0E);
+        assert_eq!(mbc.read_u8(&rom, Addr(0xA000)), IR_DARK);
+        mbc.write_u8(&rom, Addr(0xA000), 0x01);
+
+        mbc.write_u8(&rom, Addr(0x0000), 0x00);
+        assert_eq!(mbc.read_u8(&rom, Addr(0xA000)), 0x12);
+    }
+}
@@ -0,0 +1,338 @@
+use super::{Mapper, rom_offset, ram_offset};
+use super::clock::{self, Clock, ClockSource, FOOTER_WORDS};
+use memory::Addr;
+
+const MINUTES_PER_DAY: u64 = 24 * 60;
+
+// Register modes selected at 0x0000 - 0x1FFF
+const MODE_RAM_READ: u8 = 0x00;
+const MODE_RAM: u8 = 0x0A;
+const MODE_RTC_COMMAND: u8 = 0x0B;
+const MODE_RTC_RESPONSE: u8 = 0x0C;
+const MODE_RTC_SEMAPHORE: u8 = 0x0D;
+const MODE_IR: u8 = 0x0E;
+
+// RTC commands, in the upper nibble of a command byte
+const CMD_READ: u8 = 0x1;
+const CMD_WRITE: u8 = 0x2;
+const CMD_WRITE_INC: u8 = 0x3;
+const CMD_INDEX_LOW: u8 = 0x4;
+const CMD_INDEX_HIGH: u8 = 0x5;
+
+// Nibble indices of the current time in the RTC's memory
+const MINUTES_INDEX: usize = 0x00;
+const DAYS_INDEX: usize = 0x03;
+const TIME_END: usize = 0x07;
+
+const READY: u8 = 0x01;
+const IR_DARK: u8 = 0xC0;
+
+/// Hudson's HuC3: up to 2 MiB of ROM, 32 KiB of RAM, a real-time clock and an infrared port.
+///
+/// The low nibble written to 0x0000 - 0x1FFF selects what appears at 0xA000 - 0xBFFF:
+/// RAM (0x0A, or read-only with 0x00), the RTC's command (0x0B), response (0x0C)
+/// and semaphore (0x0D) registers or the IR port (0x0E).
+///
+/// The RTC is driven by commands with a 4 bit argument, run once 0xFE is written
+/// to the semaphore. They access a memory of 256 nibbles through an index,
+/// with the minute of the day in nibbles 0 - 2 and the day count in nibbles 3 - 6.
+pub struct Huc3 {
+    mode: u8,
+    rom_bank: u8,
+    ram_bank: u8,
+    ram: Vec<u8>,
+    command: u8,
+    response: u8,
+    index: u8,
+    memory: [u8; 256],
+    seconds: u64,
+    clock: Clock,
+}
+
+impl Huc3 {
+    pub fn new(ram_size: usize) -> Huc3 {
+        Huc3 {
+            mode: MODE_RAM_READ,
+            rom_bank: 1,
+            ram_bank: 0,
+            ram: vec![0; ram_size],
+            command: 0,
+            response: 0,
+            index: 0,
+            memory: [0; 256],
+            seconds: 0,
+            clock: Clock::new(ClockSource::Host),
+        }
+    }
+
+    fn time_value(&self, start: usize, end: usize) -> u64 {
+        self.memory[start .. end].iter().rev().fold(0, |value, &nibble| value << 4 | nibble as u64)
+    }
+
+    fn set_time_value(&mut self, start: usize, end: usize, mut value: u64) {
+        for nibble in &mut self.memory[start .. end] {
+            *nibble = value as u8 & 0x0F;
+            value >>= 4;
+        }
+    }
+
+    // The minute of the day, day count and second after the given seconds passed
+    fn time_after(&self, seconds: u64) -> (u64, u64, u64) {
+        let seconds = self.seconds + seconds;
+        let minutes = self.time_value(MINUTES_INDEX, DAYS_INDEX) + seconds / 60;
+        let days = self.time_value(DAYS_INDEX, TIME_END) + minutes / MINUTES_PER_DAY;
+
+        (minutes % MINUTES_PER_DAY, days, seconds % 60)
+    }
+
+    fn set_time(&mut self, (minutes, days, seconds): (u64, u64, u64)) {
+        self.set_time_value(MINUTES_INDEX, DAYS_INDEX, minutes);
+        self.set_time_value(DAYS_INDEX, TIME_END, days);
+        self.seconds = seconds;
+    }
+
+    // Adds the minutes passed since the clock was last accessed
+    fn update_rtc(&mut self) {
+        let seconds = self.clock.take_seconds();
+        let time = self.time_after(seconds);
+        self.set_time(time);
+    }
+
+    fn run_command(&mut self) {
+        let argument = self.command & 0x0F;
+        let index = self.index as usize;
+
+        match self.command >> 4 & 0x07 {
+            CMD_READ => {
+                self.update_rtc();
+                self.response = self.memory[index];
+                self.index = self.index.wrapping_add(1);
+            },
+            CMD_WRITE | CMD_WRITE_INC => {
+                self.update_rtc();
+                self.memory[index] = argument;
+                if self.command >> 4 & 0x07 == CMD_WRITE_INC {
+                    self.index = self.index.wrapping_add(1);
+                }
+                if index < TIME_END {
+                    self.seconds = 0;
+                    self.clock.reset_subsecond();
+                }
+            },
+            CMD_INDEX_LOW => self.index = self.index & 0xF0 | argument,
+            CMD_INDEX_HIGH => self.index = self.index & 0x0F | argument << 4,
+            _ => {},
+        }
+    }
+}
+
+impl Mapper for Huc3 {
+    fn read_u8(&mut self, rom: &[u8], addr: Addr) -> u8 {
+        match *addr {
+            0x0000 ..= 0x3FFF => rom[rom_offset(rom, 0, addr)],
+            0x4000 ..= 0x7FFF => rom[rom_offset(rom, self.rom_bank as usize, addr)],
+            0xA000 ..= 0xBFFF => match self.mode {
+                MODE_RAM_READ | MODE_RAM if !self.ram.is_empty() => {
+                    self.ram[ram_offset(&self.ram, self.ram_bank as usize, addr)]
+                },
+                MODE_RTC_RESPONSE => 0x80 | self.command & 0x70 | self.response,
+                // Commands complete immediately
+                MODE_RTC_SEMAPHORE => 0xFE | READY,
+                MODE_IR => IR_DARK,
+                _ => 0xFF,
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn write_u8(&mut self, _rom: &[u8], addr: Addr, value: u8) {
+        match *addr {
+            0x0000 ..= 0x1FFF => self.mode = value & 0x0F,
+            0x2000 ..= 0x3FFF => self.rom_bank = value & 0x7F,
+            0x4000 ..= 0x5FFF => self.ram_bank = value & 0x03,
+            0x6000 ..= 0x7FFF => {},
+            0xA000 ..= 0xBFFF => match self.mode {
+                MODE_RAM if !self.ram.is_empty() => {
+                    let offset = ram_offset(&self.ram, self.ram_bank as usize, addr);
+                    self.ram[offset] = value;
+                },
+                MODE_RTC_COMMAND => self.command = value,
+                MODE_RTC_SEMAPHORE if value & READY == 0 => self.run_command(),
+                // Neither writing read-only RAM nor turning on the LED has any effect here
+                _ => {},
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    // The time in the usual footer layout, as the minute of the day, day count and second
+    fn save_footer(&self) -> Vec<u8> {
+        let (minutes, days, seconds) = self.time_after(self.clock.seconds_pending());
+
+        let mut words = [0; FOOTER_WORDS];
+        words[0] = minutes as u32;
+        words[1] = days as u32;
+        words[2] = seconds as u32;
+
+        clock::write_footer(&words)
+    }
+
+    // Catches up with the time passed since the save was written
+    fn load_footer(&mut self, footer: &[u8]) {
+        let (words, timestamp) = match clock::read_footer(footer) {
+            Some(footer) => footer,
+            None => return,
+        };
+
+        self.set_time((words[0] as u64 % MINUTES_PER_DAY, words[1] as u64, words[2] as u64 % 60));
+
+        let time = self.time_after(self.clock.seconds_since(timestamp));
+        self.set_time(time);
+    }
+
+    fn tick(&mut self, cycles: u16) {
+        self.clock.tick(cycles);
+    }
+
+    fn set_clock_source(&mut self, source: ClockSource) {
+        self.clock.set_source(source);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: u64 = 4194304;
+
+    fn write(mbc: &mut Huc3, addr: u16, value: u8) {
+        mbc.write_u8(&[], Addr(addr), value);
+    }
+
+    fn command(mbc: &mut Huc3, command: u8) -> u8 {
+        write(mbc, 0x0000, MODE_RTC_COMMAND);
+        write(mbc, 0xA000, command);
+        write(mbc, 0x0000, MODE_RTC_SEMAPHORE);
+        write(mbc, 0xA000, 0xFE);
+        write(mbc, 0x0000, MODE_RTC_RESPONSE);
+        mbc.read_u8(&[], Addr(0xA000))
+    }
+
+    fn set_index(mbc: &mut Huc3, index: u8) {
+        command(mbc, CMD_INDEX_LOW << 4 | index & 0x0F);
+        command(mbc, CMD_INDEX_HIGH << 4 | index >> 4);
+    }
+
+    // The minute of the day and the day count
+    fn read_time(mbc: &mut Huc3) -> (u64, u64) {
+        set_index(mbc, 0);
+        let nibbles: Vec<u64> = (0 .. TIME_END).map(|_| (command(mbc, CMD_READ << 4) & 0x0F) as u64).collect();
+        let value = |nibbles: &[u64]| nibbles.iter().rev().fold(0, |value, &nibble| value << 4 | nibble);
+
+        (value(&nibbles[..3]), value(&nibbles[3..]))
+    }
+
+    fn write_time(mbc: &mut Huc3, minutes: u64, days: u64) {
+        set_index(mbc, 0);
+        let time = days << 12 | minutes;
+        for i in 0 .. TIME_END {
+            command(mbc, CMD_WRITE_INC << 4 | (time >> (i * 4)) as u8 & 0x0F);
+        }
+    }
+
+    fn emulated_huc3() -> Huc3 {
+        let mut mbc = Huc3::new(0x8000);
+        mbc.set_clock_source(ClockSource::Emulated);
+        mbc
+    }
+
+    fn run(mbc: &mut Huc3, cycles: u64) {
+        for _ in 0 .. cycles / 0x4000 {
+            mbc.tick(0x4000);
+        }
+    }
+
+    #[test]
+    fn commands_access_clock_memory() {
+        let mut mbc = emulated_huc3();
+
+        set_index(&mut mbc, 0x42);
+        command(&mut mbc, CMD_WRITE << 4 | 0x7);
+        assert_eq!(mbc.index, 0x42);
+        assert_eq!(command(&mut mbc, CMD_READ << 4), 0x80 | CMD_READ << 4 | 0x7);
+        assert_eq!(mbc.index, 0x43);
+
+        write(&mut mbc, 0x0000, MODE_RTC_SEMAPHORE);
+        assert_eq!(mbc.read_u8(&[], Addr(0xA000)), 0xFF);
+        write(&mut mbc, 0x0000, MODE_IR);
+        assert_eq!(mbc.read_u8(&[], Addr(0xA000)), IR_DARK);
+    }
+
+    #[test]
+    fn clock_counts_minutes_and_days() {
+        let mut mbc = emulated_huc3();
+
+        write_time(&mut mbc, 1439, 2);
+        assert_eq!(read_time(&mut mbc), (1439, 2));
+
+        run(&mut mbc, 59 * SECOND);
+        assert_eq!(read_time(&mut mbc), (1439, 2));
+        run(&mut mbc, SECOND);
+        assert_eq!(read_time(&mut mbc), (0, 3));
+    }
+
+    #[test]
+    fn ram_is_read_only_in_mode_0() {
+        let mut mbc = emulated_huc3();
+
+        write(&mut mbc, 0x0000, MODE_RAM);
+        write(&mut mbc, 0x4000, 0x01);
+        write(&mut mbc, 0xA000, 0x12);
+        write(&mut mbc, 0x0000, MODE_RAM_READ);
+        write(&mut mbc, 0xA000, 0x34);
+        assert_eq!(mbc.read_u8(&[], Addr(0xA000)), 0x12);
+        assert_eq!(mbc.ram()[0x2000], 0x12);
+    }
+
+    #[test]
+    fn saves_clock_after_ram() {
+        let mut mbc = emulated_huc3();
+        write_time(&mut mbc, 100, 7);
+        run(&mut mbc, 30 * SECOND);
+
+        let footer = mbc.save_footer();
+        let (words, _) = clock::read_footer(&footer).unwrap();
+        assert_eq!(&words[..3], &[100, 7, 30]);
+
+        let mut restored = emulated_huc3();
+        restored.load_footer(&footer);
+        run(&mut restored, 30 * SECOND);
+        assert_eq!(read_time(&mut restored), (101, 7));
+    }
+
+    #[test]
+    fn catches_up_with_time_passed_since_saving() {
+        let mut words = [0; FOOTER_WORDS];
+        words[0] = 1430;
+        words[1] = 5;
+        let mut footer = clock::write_footer(&words);
+
+        // Saved 20 minutes earlier
+        let (_, timestamp) = clock::read_footer(&footer).unwrap();
+        for (i, byte) in footer[40..].iter_mut().enumerate() {
+            *byte = ((timestamp - 1200) >> (i * 8)) as u8;
+        }
+
+        let mut mbc = Huc3::new(0);
+        mbc.load_footer(&footer);
+        assert_eq!(read_time(&mut mbc), (10, 6));
+    }
+}
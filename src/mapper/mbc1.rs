@@ -1,17 +1,51 @@
-use super::Mapper;
+use super::{Mapper, rom_offset, ram_offset};
 use memory::Addr;
+use header::{self, NINTENDO_LOGO};
 use self::Mode::*;
 
+// Multicarts combine 256 KiB games, each starting with its own header
+const MULTICART_SIZE: usize = 1024 * 1024;
+const MULTICART_GAME_SIZE: usize = 256 * 1024;
+
+/// Guesses whether the ROM is an MBC1M multicart, which the header doesn't tell.
+pub fn is_multicart(rom: &[u8]) -> bool {
+    rom.len() == MULTICART_SIZE && rom.chunks(MULTICART_GAME_SIZE).all(|game| header::logo(game) == &NINTENDO_LOGO[..])
+}
+
+/// MBC1: up to 2 MiB of ROM and 32 KiB of RAM.
+///
+/// The ROM bank is selected by a 5 bit register at 0x2000 - 0x3FFF,
+/// in which 0 selects bank 1, and a 2 bit register at 0x4000 - 0x5FFF
+/// providing bank bits 5 and 6. In the second mode, selected at 0x6000 - 0x7FFF,
+/// the 2 bit register also banks 0x0000 - 0x3FFF and the RAM.
+///
+/// Multicarts wire only 4 bits of the first register,
+/// so the 2 bit register provides bank bits 4 and 5 instead.
 pub struct Mbc1 {
     mode: Mode,
+    multicart: bool,
+    ram_enabled: bool,
     rom_bank: u8,
+    bank_high: u8,
+    ram: Vec<u8>,
 }
 
 impl Mbc1 {
-    pub fn new() -> Mbc1 {
+    pub fn new(ram_size: usize) -> Mbc1 {
         Mbc1 {
             mode: Mode16MbitRom8KbyteRam,
+            multicart: false,
+            ram_enabled: false,
             rom_bank: 1,
+            bank_high: 0,
+            ram: vec![0; ram_size],
+        }
+    }
+
+    pub fn multicart(ram_size: usize) -> Mbc1 {
+        Mbc1 {
+            multicart: true,
+            .. Mbc1::new(ram_size)
         }
     }
 
@@ -24,37 +58,76 @@ impl Mbc1 {
     }
 
     fn select_rom_bank(&mut self, value: u8) {
-        let bank = value & 0b11;
+        let bank = value & 0b1_1111;
         if bank == 0 {
             self.rom_bank = 1;
         } else {
             self.rom_bank = bank;
         }
     }
+
+    // The 2 bit register only affects 0x0000 - 0x3FFF and RAM in the second mode
+    fn secondary_bank(&self) -> usize {
+        match self.mode {
+            Mode16MbitRom8KbyteRam => 0,
+            Mode4MbitRom32KbyteRam => self.bank_high as usize,
+        }
+    }
+
+    // Bank 0 is only avoided based on all 5 bits, so a multicart can still select it
+    fn switchable_bank(&self) -> usize {
+        if self.multicart {
+            (self.bank_high as usize) << 4 | (self.rom_bank & 0b1111) as usize
+        } else {
+            (self.bank_high as usize) << 5 | self.rom_bank as usize
+        }
+    }
+
+    fn fixed_bank(&self) -> usize {
+        let shift = if self.multicart { 4 } else { 5 };
+        self.secondary_bank() << shift
+    }
+
+    fn ram_accessible(&self) -> bool {
+        self.ram_enabled && !self.ram.is_empty()
+    }
 }
 
 impl Mapper for Mbc1 {
     fn read_u8(&mut self, rom: &[u8], addr: Addr) -> u8 {
         match *addr {
-            0xA000 ... 0xBFFF => {
-                println!("MCB1_STUB: read at RAM 0x{:04X}", *addr);
-                0
+            0x0000 ..= 0x3FFF => rom[rom_offset(rom, self.fixed_bank(), addr)],
+            0x4000 ..= 0x7FFF => rom[rom_offset(rom, self.switchable_bank(), addr)],
+            0xA000 ..= 0xBFFF => if self.ram_accessible() {
+                self.ram[ram_offset(&self.ram, self.secondary_bank(), addr)]
+            } else {
+                0xFF
             },
-            0x4000 ... 0x7FFF => rom[*addr as usize],
-            _ => panic!("MBC1_STUB: read at 0x{:04X}", *addr)
+            _ => unreachable!()
         }
     }
 
-    fn write_u8(&mut self, rom: &[u8], addr: Addr, value: u8) {
-        match self.mode {
-            Mode16MbitRom8KbyteRam => match *addr {
-                0x6000 ... 0x7FFF => self.select_memory_model(value),
-                0x4000 ... 0x5FFF => self.select_rom_bank(value),
-                _ => panic!("MBC1_STUB: {:?} write at 0x{:04X} ← 0b{:08b}", self.mode, *addr, value)
+    fn write_u8(&mut self, _rom: &[u8], addr: Addr, value: u8) {
+        match *addr {
+            0x0000 ..= 0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000 ..= 0x3FFF => self.select_rom_bank(value),
+            0x4000 ..= 0x5FFF => self.bank_high = value & 0b11,
+            0x6000 ..= 0x7FFF => self.select_memory_model(value),
+            0xA000 ..= 0xBFFF => if self.ram_accessible() {
+                let offset = ram_offset(&self.ram, self.secondary_bank(), addr);
+                self.ram[offset] = value;
             },
-            Mode4MbitRom32KbyteRam => panic!("MBC1_STUB: {:?} write at 0x{:04X} ← 0b{:08b}", self.mode, *addr, value)
+            _ => unreachable!()
         }
     }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
 }
 
 #[derive(Debug)]
@@ -62,3 +135,144 @@ enum Mode {
     Mode16MbitRom8KbyteRam,
     Mode4MbitRom32KbyteRam,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mapper::tests::{banked_rom, bank_at};
+
+    fn write(mbc: &mut Mbc1, rom: &[u8], addr: u16, value: u8) {
+        mbc.write_u8(rom, Addr(addr), value);
+    }
+
+    #[test]
+    fn selects_rom_banks() {
+        let rom = banked_rom(128);
+        let mut mbc = Mbc1::new(0);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x0000), 0);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 1);
+
+        write(&mut mbc, &rom, 0x2000, 0x1F);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x1F);
+
+        // Only 5 bits are stored and 0 selects bank 1
+        write(&mut mbc, &rom, 0x3FFF, 0xE5);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x05);
+        write(&mut mbc, &rom, 0x2000, 0x00);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x01);
+        write(&mut mbc, &rom, 0x2000, 0x20);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x01);
+    }
+
+    #[test]
+    fn upper_bits_skip_banks_0x20_0x40_0x60() {
+        let rom = banked_rom(128);
+        let mut mbc = Mbc1::new(0);
+
+        for &high in &[1, 2, 3] {
+            write(&mut mbc, &rom, 0x4000, high);
+            write(&mut mbc, &rom, 0x2000, 0x00);
+            assert_eq!(bank_at(&mut mbc, &rom, 0x4000), (high as usize) << 5 | 1);
+            write(&mut mbc, &rom, 0x2000, 0x02);
+            assert_eq!(bank_at(&mut mbc, &rom, 0x4000), (high as usize) << 5 | 2);
+        }
+
+        // Only affects 0x0000 - 0x3FFF in the second mode
+        assert_eq!(bank_at(&mut mbc, &rom, 0x0000), 0x00);
+        write(&mut mbc, &rom, 0x6000, 0x01);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x0000), 0x60);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x62);
+    }
+
+    #[test]
+    fn banks_wrap_around_small_roms() {
+        let rom = banked_rom(8);
+        let mut mbc = Mbc1::new(0);
+
+        write(&mut mbc, &rom, 0x2000, 0x0B);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x03);
+    }
+
+    #[test]
+    fn ram_needs_enabling() {
+        let rom = banked_rom(4);
+        let mut mbc = Mbc1::new(0x2000);
+
+        write(&mut mbc, &rom, 0xA000, 0x12);
+        assert_eq!(mbc.read_u8(&rom, Addr(0xA000)), 0xFF);
+
+        write(&mut mbc, &rom, 0x0000, 0x0A);
+        write(&mut mbc, &rom, 0xA000, 0x12);
+        assert_eq!(mbc.read_u8(&rom, Addr(0xA000)), 0x12);
+
+        write(&mut mbc, &rom, 0x1FFF, 0x00);
+        assert_eq!(mbc.read_u8(&rom, Addr(0xA000)), 0xFF);
+        assert_eq!(mbc.ram()[0], 0x12);
+    }
+
+    #[test]
+    fn ram_banks_only_in_second_mode() {
+        let rom = banked_rom(4);
+        let mut mbc = Mbc1::new(0x8000);
+        write(&mut mbc, &rom, 0x0000, 0x0A);
+
+        write(&mut mbc, &rom, 0x4000, 0x02);
+        write(&mut mbc, &rom, 0xA000, 0x11);
+        assert_eq!(mbc.ram()[0x0000], 0x11);
+
+        write(&mut mbc, &rom, 0x6000, 0x01);
+        write(&mut mbc, &rom, 0xA000, 0x22);
+        assert_eq!(mbc.ram()[0x4000], 0x22);
+        assert_eq!(mbc.read_u8(&rom, Addr(0xA000)), 0x22);
+
+        write(&mut mbc, &rom, 0x6000, 0x00);
+        assert_eq!(mbc.read_u8(&rom, Addr(0xA000)), 0x11);
+    }
+
+    fn multicart_rom() -> Vec<u8> {
+        let mut rom = banked_rom(64);
+        for game in rom.chunks_mut(MULTICART_GAME_SIZE) {
+            game[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+        }
+        rom
+    }
+
+    #[test]
+    fn detects_multicarts() {
+        let rom = multicart_rom();
+        assert!(is_multicart(&rom));
+
+        let mut rom = multicart_rom();
+        rom[3 * MULTICART_GAME_SIZE + 0x104] ^= 0xFF;
+        assert!(!is_multicart(&rom));
+
+        let mut rom = banked_rom(64);
+        rom[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+        assert!(!is_multicart(&rom));
+
+        let rom = multicart_rom();
+        assert!(!is_multicart(&rom[..MULTICART_SIZE / 2]));
+    }
+
+    #[test]
+    fn multicart_upper_bits_select_game() {
+        let rom = multicart_rom();
+        let mut mbc = Mbc1::multicart(0);
+
+        // Bit 4 of the bank register isn't wired, so 0x10 selects a game's bank 0
+        write(&mut mbc, &rom, 0x4000, 0x02);
+        write(&mut mbc, &rom, 0x2000, 0x13);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x23);
+        write(&mut mbc, &rom, 0x2000, 0x10);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x20);
+        write(&mut mbc, &rom, 0x2000, 0x00);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x21);
+
+        // The second mode maps the game's first bank at 0x0000
+        assert_eq!(bank_at(&mut mbc, &rom, 0x0000), 0x00);
+        write(&mut mbc, &rom, 0x6000, 0x01);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x0000), 0x20);
+        write(&mut mbc, &rom, 0x4000, 0x03);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x0000), 0x30);
+    }
+}
@@ -0,0 +1,118 @@
+use super::{Mapper, rom_offset};
+use memory::Addr;
+
+const RAM_SIZE: usize = 512;
+
+/// MBC2: up to 256 KiB of ROM and 512 x 4 bits of built-in RAM.
+///
+/// Both registers live at 0x0000 - 0x3FFF, address bit 8 selects
+/// between RAM enable (cleared) and the 4 bit ROM bank (set), in which 0 selects bank 1.
+/// Only the lower 9 address bits reach the RAM, repeating it across 0xA000 - 0xBFFF.
+pub struct Mbc2 {
+    ram_enabled: bool,
+    rom_bank: u8,
+    ram: Vec<u8>,
+}
+
+impl Mbc2 {
+    pub fn new() -> Mbc2 {
+        Mbc2 {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram: vec![0; RAM_SIZE],
+        }
+    }
+
+    fn select_rom_bank(&mut self, value: u8) {
+        let bank = value & 0b1111;
+        if bank == 0 {
+            self.rom_bank = 1;
+        } else {
+            self.rom_bank = bank;
+        }
+    }
+}
+
+impl Mapper for Mbc2 {
+    fn read_u8(&mut self, rom: &[u8], addr: Addr) -> u8 {
+        match *addr {
+            0x0000 ..= 0x3FFF => rom[rom_offset(rom, 0, addr)],
+            0x4000 ..= 0x7FFF => rom[rom_offset(rom, self.rom_bank as usize, addr)],
+            // The upper 4 bits aren't connected and read as 1
+            0xA000 ..= 0xBFFF => if self.ram_enabled {
+                0xF0 | self.ram[*addr as usize % RAM_SIZE]
+            } else {
+                0xFF
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn write_u8(&mut self, _rom: &[u8], addr: Addr, value: u8) {
+        match *addr {
+            0x0000 ..= 0x3FFF => if *addr & 0x0100 == 0 {
+                self.ram_enabled = value & 0x0F == 0x0A;
+            } else {
+                self.select_rom_bank(value);
+            },
+            0x4000 ..= 0x7FFF => {},
+            0xA000 ..= 0xBFFF => if self.ram_enabled {
+                self.ram[*addr as usize % RAM_SIZE] = value & 0x0F;
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mapper::tests::{banked_rom, bank_at};
+
+    #[test]
+    fn address_bit_8_selects_register() {
+        let rom = banked_rom(16);
+        let mut mbc = Mbc2::new();
+
+        // Bit 8 clear: RAM enable, the ROM bank stays
+        mbc.write_u8(&rom, Addr(0x2000), 0x0A);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 1);
+        assert_eq!(mbc.read_u8(&rom, Addr(0xA000)), 0xF0);
+
+        // Bit 8 set: 4 bit ROM bank, 0 selects bank 1
+        mbc.write_u8(&rom, Addr(0x0100), 0x0A);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x0A);
+        mbc.write_u8(&rom, Addr(0x3F00), 0x1F);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x0F);
+        mbc.write_u8(&rom, Addr(0x2100), 0x10);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x01);
+        assert_eq!(mbc.read_u8(&rom, Addr(0xA000)), 0xF0);
+
+        mbc.write_u8(&rom, Addr(0x0000), 0x00);
+        assert_eq!(mbc.read_u8(&rom, Addr(0xA000)), 0xFF);
+    }
+
+    #[test]
+    fn ram_stores_nibbles_and_repeats() {
+        let rom = banked_rom(2);
+        let mut mbc = Mbc2::new();
+        mbc.write_u8(&rom, Addr(0x0000), 0x0A);
+
+        mbc.write_u8(&rom, Addr(0xA001), 0xAB);
+        assert_eq!(mbc.read_u8(&rom, Addr(0xA001)), 0xFB);
+        assert_eq!(mbc.ram()[1], 0x0B);
+
+        assert_eq!(mbc.read_u8(&rom, Addr(0xA201)), 0xFB);
+        assert_eq!(mbc.read_u8(&rom, Addr(0xBE01)), 0xFB);
+        mbc.write_u8(&rom, Addr(0xBFFF), 0x05);
+        assert_eq!(mbc.read_u8(&rom, Addr(0xA1FF)), 0xF5);
+    }
+}
@@ -0,0 +1,450 @@
+use super::{Mapper, rom_offset, ram_offset};
+use super::clock::{self, Clock, ClockSource, FOOTER_WORDS};
+use memory::Addr;
+
+const DAY_HIGH: u8 = 0x01;
+const HALT: u8 = 0x40;
+const DAY_CARRY: u8 = 0x80;
+
+/// The MBC3's real-time clock registers, selected as RAM banks 0x08 - 0x0C.
+#[derive(Copy, Clone, Debug, Default)]
+struct Rtc {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    day_low: u8,
+    // Bit 0: day bit 8, bit 6: halt, bit 7: day counter carry
+    day_high: u8,
+}
+
+impl Rtc {
+    fn read(&self, register: u8) -> u8 {
+        match register {
+            0x08 => self.seconds,
+            0x09 => self.minutes,
+            0x0A => self.hours,
+            0x0B => self.day_low,
+            0x0C => self.day_high,
+            _ => unreachable!()
+        }
+    }
+
+    fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0x08 => self.seconds = value & 0x3F,
+            0x09 => self.minutes = value & 0x3F,
+            0x0A => self.hours = value & 0x1F,
+            0x0B => self.day_low = value,
+            0x0C => self.day_high = value & (DAY_HIGH | HALT | DAY_CARRY),
+            _ => unreachable!()
+        }
+    }
+
+    fn registers(&self) -> [u8; 5] {
+        [self.seconds, self.minutes, self.hours, self.day_low, self.day_high]
+    }
+
+    fn halted(&self) -> bool {
+        self.day_high & HALT != 0
+    }
+
+    // Out of range values count up to the register's limit and wrap to 0 without a carry
+    fn advance_second(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+
+        self.day_low = self.day_low.wrapping_add(1);
+        if self.day_low == 0 {
+            if self.day_high & DAY_HIGH != 0 {
+                self.day_high = self.day_high & !DAY_HIGH | DAY_CARRY;
+            } else {
+                self.day_high |= DAY_HIGH;
+            }
+        }
+    }
+
+    fn advance(&mut self, mut seconds: u64) {
+        // Out of range values only get back in range by counting up
+        while seconds > 0 && (self.seconds >= 60 || self.minutes >= 60 || self.hours >= 24) {
+            self.advance_second();
+            seconds -= 1;
+        }
+        if seconds == 0 {
+            return;
+        }
+
+        let time = self.hours as u64 * 3600 + self.minutes as u64 * 60 + self.seconds as u64 + seconds;
+        self.seconds = (time % 60) as u8;
+        self.minutes = (time / 60 % 60) as u8;
+        self.hours = (time / 3600 % 24) as u8;
+
+        let day = (self.day_high & DAY_HIGH) as u64 * 0x100 + self.day_low as u64 + time / 86400;
+        if day > 0x1FF {
+            self.day_high |= DAY_CARRY;
+        }
+        self.day_low = day as u8;
+        self.day_high = self.day_high & !DAY_HIGH | (day >> 8) as u8 & DAY_HIGH;
+    }
+}
+
+/// MBC3: up to 2 MiB of ROM, 32 KiB of RAM and an optional real-time clock.
+///
+/// The 7 bit ROM bank is selected at 0x2000 - 0x3FFF, in which 0 selects bank 1.
+/// Values 0x00 - 0x03 written to 0x4000 - 0x5FFF select a RAM bank,
+/// 0x08 - 0x0C one of the clock registers instead.
+/// Writing 0 and then 1 to 0x6000 - 0x7FFF latches the clock registers for reading.
+pub struct Mbc3 {
+    ram_enabled: bool,
+    rom_bank: u8,
+    ram_bank: u8,
+    ram: Vec<u8>,
+    has_rtc: bool,
+    rtc: Rtc,
+    latched: Rtc,
+    latch_armed: bool,
+    clock: Clock,
+}
+
+impl Mbc3 {
+    pub fn new(ram_size: usize, has_rtc: bool) -> Mbc3 {
+        Mbc3 {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            ram: vec![0; ram_size],
+            has_rtc: has_rtc,
+            rtc: Rtc::default(),
+            latched: Rtc::default(),
+            latch_armed: false,
+            clock: Clock::new(ClockSource::Host),
+        }
+    }
+
+    fn select_rom_bank(&mut self, value: u8) {
+        let bank = value & 0x7F;
+        if bank == 0 {
+            self.rom_bank = 1;
+        } else {
+            self.rom_bank = bank;
+        }
+    }
+
+    fn rtc_selected(&self) -> bool {
+        self.has_rtc && self.ram_bank >= 0x08 && self.ram_bank <= 0x0C
+    }
+
+    // Catches the clock up with the time passed since it was last accessed
+    fn update_rtc(&mut self) {
+        let seconds = self.clock.take_seconds();
+        self.advance_rtc(seconds);
+    }
+
+    fn advance_rtc(&mut self, seconds: u64) {
+        if !self.rtc.halted() {
+            self.rtc.advance(seconds);
+        }
+    }
+
+    fn latch(&mut self, value: u8) {
+        if self.latch_armed && value == 0x01 {
+            self.update_rtc();
+            self.latched = self.rtc;
+        }
+
+        self.latch_armed = value == 0x00;
+    }
+
+    fn write_rtc(&mut self, register: u8, value: u8) {
+        self.update_rtc();
+        self.rtc.write(register, value);
+
+        if register == 0x08 {
+            self.clock.reset_subsecond();
+        }
+    }
+}
+
+impl Mapper for Mbc3 {
+    fn read_u8(&mut self, rom: &[u8], addr: Addr) -> u8 {
+        match *addr {
+            0x0000 ..= 0x3FFF => rom[rom_offset(rom, 0, addr)],
+            0x4000 ..= 0x7FFF => rom[rom_offset(rom, self.rom_bank as usize, addr)],
+            0xA000 ..= 0xBFFF => if !self.ram_enabled {
+                0xFF
+            } else if self.rtc_selected() {
+                self.latched.read(self.ram_bank)
+            } else if self.ram_bank <= 0x03 && !self.ram.is_empty() {
+                self.ram[ram_offset(&self.ram, self.ram_bank as usize, addr)]
+            } else {
+                0xFF
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn write_u8(&mut self, _rom: &[u8], addr: Addr, value: u8) {
+        match *addr {
+            0x0000 ..= 0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000 ..= 0x3FFF => self.select_rom_bank(value),
+            0x4000 ..= 0x5FFF => self.ram_bank = value & 0x0F,
+            0x6000 ..= 0x7FFF => if self.has_rtc {
+                self.latch(value);
+            },
+            0xA000 ..= 0xBFFF if !self.ram_enabled => {},
+            0xA000 ..= 0xBFFF => if self.rtc_selected() {
+                let register = self.ram_bank;
+                self.write_rtc(register, value);
+            } else if self.ram_bank <= 0x03 && !self.ram.is_empty() {
+                let offset = ram_offset(&self.ram, self.ram_bank as usize, addr);
+                self.ram[offset] = value;
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    // The clock and latched registers, in the usual footer layout
+    fn save_footer(&self) -> Vec<u8> {
+        if !self.has_rtc {
+            return Vec::new();
+        }
+
+        let mut rtc = self.rtc;
+        let seconds = self.clock.seconds_pending();
+        if !rtc.halted() {
+            rtc.advance(seconds);
+        }
+
+        let mut words = [0; FOOTER_WORDS];
+        for (word, &register) in words.iter_mut().zip(rtc.registers().iter().chain(&self.latched.registers())) {
+            *word = register as u32;
+        }
+
+        clock::write_footer(&words)
+    }
+
+    // Catches up with the time passed since the save was written
+    fn load_footer(&mut self, footer: &[u8]) {
+        let (words, timestamp) = match clock::read_footer(footer) {
+            Some(footer) if self.has_rtc => footer,
+            _ => return,
+        };
+
+        for (register, &word) in (0x08 .. 0x0D).zip(&words[..5]) {
+            self.rtc.write(register, word as u8);
+        }
+        for (register, &word) in (0x08 .. 0x0D).zip(&words[5..]) {
+            self.latched.write(register, word as u8);
+        }
+
+        let seconds = self.clock.seconds_since(timestamp);
+        self.advance_rtc(seconds);
+    }
+
+    fn tick(&mut self, cycles: u16) {
+        self.clock.tick(cycles);
+    }
+
+    fn set_clock_source(&mut self, source: ClockSource) {
+        self.clock.set_source(source);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mapper::tests::{banked_rom, bank_at};
+
+    const SECOND: u64 = 4194304;
+
+    fn write(mbc: &mut Mbc3, addr: u16, value: u8) {
+        mbc.write_u8(&[], Addr(addr), value);
+    }
+
+    fn run(mbc: &mut Mbc3, cycles: u64) {
+        for _ in 0 .. cycles / 0x4000 {
+            mbc.tick(0x4000);
+        }
+    }
+
+    fn latch(mbc: &mut Mbc3) {
+        write(mbc, 0x6000, 0x00);
+        write(mbc, 0x6000, 0x01);
+    }
+
+    fn read_rtc(mbc: &mut Mbc3, register: u8) -> u8 {
+        write(mbc, 0x4000, register);
+        mbc.read_u8(&[], Addr(0xA000))
+    }
+
+    fn write_rtc(mbc: &mut Mbc3, register: u8, value: u8) {
+        write(mbc, 0x4000, register);
+        write(mbc, 0xA000, value);
+    }
+
+    fn rtc_mbc3() -> Mbc3 {
+        let mut mbc = Mbc3::new(0x8000, true);
+        mbc.set_clock_source(ClockSource::Emulated);
+        write(&mut mbc, 0x0000, 0x0A);
+        mbc
+    }
+
+    #[test]
+    fn selects_rom_and_ram_banks() {
+        let rom = banked_rom(128);
+        let mut mbc = Mbc3::new(0x8000, false);
+
+        mbc.write_u8(&rom, Addr(0x2000), 0x00);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x01);
+        mbc.write_u8(&rom, Addr(0x2000), 0xFF);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x7F);
+        mbc.write_u8(&rom, Addr(0x2000), 0x20);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x20);
+
+        write(&mut mbc, 0x0000, 0x0A);
+        write(&mut mbc, 0x4000, 0x03);
+        write(&mut mbc, 0xA000, 0x33);
+        assert_eq!(mbc.ram()[0x6000], 0x33);
+
+        // Without a clock its registers are unmapped
+        write(&mut mbc, 0x4000, 0x08);
+        assert_eq!(mbc.read_u8(&rom, Addr(0xA000)), 0xFF);
+    }
+
+    #[test]
+    fn latched_registers_hold_still() {
+        let mut mbc = rtc_mbc3();
+
+        run(&mut mbc, 61 * SECOND);
+        assert_eq!(read_rtc(&mut mbc, 0x08), 0);
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc, 0x08), 1);
+        assert_eq!(read_rtc(&mut mbc, 0x09), 1);
+
+        run(&mut mbc, SECOND);
+        assert_eq!(read_rtc(&mut mbc, 0x08), 1);
+
+        // Latching needs a 0 written first
+        write(&mut mbc, 0x6000, 0x01);
+        assert_eq!(read_rtc(&mut mbc, 0x08), 1);
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc, 0x08), 2);
+    }
+
+    #[test]
+    fn halt_stops_the_clock() {
+        let mut mbc = rtc_mbc3();
+
+        write_rtc(&mut mbc, 0x0C, HALT);
+        write_rtc(&mut mbc, 0x08, 30);
+        run(&mut mbc, 10 * SECOND);
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc, 0x08), 30);
+
+        write_rtc(&mut mbc, 0x0C, 0x00);
+        run(&mut mbc, 10 * SECOND);
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc, 0x08), 40);
+    }
+
+    #[test]
+    fn days_carry_over() {
+        let mut rtc = Rtc { seconds: 59, minutes: 59, hours: 23, day_low: 0xFF, day_high: 0 };
+        rtc.advance(1);
+        assert_eq!(rtc.registers(), [0, 0, 0, 0x00, DAY_HIGH]);
+
+        rtc.advance(511 * 86400 + 3661);
+        assert_eq!(rtc.registers(), [1, 1, 1, 0xFF, DAY_CARRY]);
+
+        // The carry stays until written
+        rtc.advance(86400);
+        assert_eq!(rtc.registers(), [1, 1, 1, 0x00, DAY_CARRY | DAY_HIGH]);
+    }
+
+    #[test]
+    fn out_of_range_values_wrap_without_carry() {
+        let mut rtc = Rtc { seconds: 63, minutes: 59, hours: 31, day_low: 0, day_high: 0 };
+        rtc.advance(1);
+        assert_eq!(rtc.registers(), [0, 59, 31, 0, 0]);
+
+        rtc.advance(60);
+        assert_eq!(rtc.registers(), [0, 0, 0, 0, 0]);
+
+        let mut stepped = Rtc { seconds: 62, minutes: 61, hours: 25, day_low: 7, day_high: 0 };
+        let mut advanced = stepped;
+        for _ in 0 .. 100000 {
+            stepped.advance_second();
+        }
+        advanced.advance(100000);
+        assert_eq!(stepped.registers(), advanced.registers());
+    }
+
+    #[test]
+    fn saves_clock_after_ram() {
+        let mut mbc = rtc_mbc3();
+        write_rtc(&mut mbc, 0x0A, 5);
+        latch(&mut mbc);
+        write_rtc(&mut mbc, 0x09, 7);
+
+        let footer = mbc.save_footer();
+        let (words, _) = clock::read_footer(&footer).unwrap();
+        assert_eq!(words, [0, 7, 5, 0, 0, 0, 0, 5, 0, 0]);
+
+        let mut restored = rtc_mbc3();
+        restored.load_footer(&footer);
+        assert_eq!(read_rtc(&mut restored, 0x09), 0);
+        latch(&mut restored);
+        assert_eq!(read_rtc(&mut restored, 0x09), 7);
+        assert_eq!(read_rtc(&mut restored, 0x0A), 5);
+
+        assert!(Mbc3::new(0x2000, false).save_footer().is_empty());
+    }
+
+    #[test]
+    fn catches_up_with_time_passed_since_saving() {
+        let mut words = [0; FOOTER_WORDS];
+        words[1] = 58;
+        let mut footer = clock::write_footer(&words);
+
+        // Saved two minutes earlier
+        let (_, timestamp) = clock::read_footer(&footer).unwrap();
+        for (i, byte) in footer[40..].iter_mut().enumerate() {
+            *byte = ((timestamp - 120) >> (i * 8)) as u8;
+        }
+
+        let mut mbc = Mbc3::new(0, true);
+        write(&mut mbc, 0x0000, 0x0A);
+        mbc.load_footer(&footer);
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc, 0x09), 0);
+        assert_eq!(read_rtc(&mut mbc, 0x0A), 1);
+
+        // Emulated time doesn't depend on when the save was written
+        let mut mbc = rtc_mbc3();
+        mbc.load_footer(&footer);
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc, 0x09), 58);
+    }
+}
@@ -0,0 +1,164 @@
+use super::{Mapper, RumbleCallback, rom_offset, ram_offset};
+use memory::Addr;
+
+const MOTOR: u8 = 0x08;
+
+/// MBC5: up to 8 MiB of ROM and 128 KiB of RAM.
+///
+/// The 9 bit ROM bank is written to 0x2000 - 0x2FFF (bits 0 - 7)
+/// and 0x3000 - 0x3FFF (bit 8), bank 0 can be selected as well.
+/// 0x4000 - 0x5FFF selects one of 16 RAM banks. On rumble carts
+/// bit 3 of that register drives the motor instead, leaving 8 RAM banks.
+pub struct Mbc5 {
+    ram_enabled: bool,
+    rom_bank: u16,
+    ram_bank: u8,
+    ram: Vec<u8>,
+    has_rumble: bool,
+    motor: bool,
+    rumble_callback: Option<RumbleCallback>,
+}
+
+impl Mbc5 {
+    pub fn new(ram_size: usize, has_rumble: bool) -> Mbc5 {
+        Mbc5 {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            ram: vec![0; ram_size],
+            has_rumble: has_rumble,
+            motor: false,
+            rumble_callback: None,
+        }
+    }
+
+    fn select_ram_bank(&mut self, value: u8) {
+        if !self.has_rumble {
+            self.ram_bank = value & 0x0F;
+            return;
+        }
+
+        self.ram_bank = value & 0x07;
+
+        let motor = value & MOTOR != 0;
+        if motor != self.motor {
+            self.motor = motor;
+            if let Some(ref mut callback) = self.rumble_callback {
+                callback(motor);
+            }
+        }
+    }
+
+    fn ram_accessible(&self) -> bool {
+        self.ram_enabled && !self.ram.is_empty()
+    }
+}
+
+impl Mapper for Mbc5 {
+    fn read_u8(&mut self, rom: &[u8], addr: Addr) -> u8 {
+        match *addr {
+            0x0000 ..= 0x3FFF => rom[rom_offset(rom, 0, addr)],
+            0x4000 ..= 0x7FFF => rom[rom_offset(rom, self.rom_bank as usize, addr)],
+            0xA000 ..= 0xBFFF => if self.ram_accessible() {
+                self.ram[ram_offset(&self.ram, self.ram_bank as usize, addr)]
+            } else {
+                0xFF
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn write_u8(&mut self, _rom: &[u8], addr: Addr, value: u8) {
+        match *addr {
+            0x0000 ..= 0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000 ..= 0x2FFF => self.rom_bank = self.rom_bank & 0x100 | value as u16,
+            0x3000 ..= 0x3FFF => self.rom_bank = self.rom_bank & 0xFF | (value as u16 & 0x01) << 8,
+            0x4000 ..= 0x5FFF => self.select_ram_bank(value),
+            0x6000 ..= 0x7FFF => {},
+            0xA000 ..= 0xBFFF => if self.ram_accessible() {
+                let offset = ram_offset(&self.ram, self.ram_bank as usize, addr);
+                self.ram[offset] = value;
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn set_rumble_callback(&mut self, callback: RumbleCallback) {
+        self.rumble_callback = Some(callback);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use std::cell::RefCell;
+    use mapper::tests::{banked_rom, bank_at};
+
+    #[test]
+    fn selects_9_bit_rom_banks() {
+        let rom = banked_rom(512);
+        let mut mbc = Mbc5::new(0, false);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 1);
+
+        mbc.write_u8(&rom, Addr(0x2000), 0x00);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0);
+
+        mbc.write_u8(&rom, Addr(0x2FFF), 0x23);
+        mbc.write_u8(&rom, Addr(0x3000), 0xFF);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x123);
+
+        mbc.write_u8(&rom, Addr(0x2000), 0x45);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x145);
+        mbc.write_u8(&rom, Addr(0x3FFF), 0x00);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x045);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x0000), 0);
+    }
+
+    #[test]
+    fn selects_ram_banks() {
+        let rom = banked_rom(2);
+        let mut mbc = Mbc5::new(0x20000, false);
+
+        mbc.write_u8(&rom, Addr(0xA000), 0x12);
+        assert_eq!(mbc.read_u8(&rom, Addr(0xA000)), 0xFF);
+
+        mbc.write_u8(&rom, Addr(0x0000), 0x0A);
+        for bank in 0 .. 16 {
+            mbc.write_u8(&rom, Addr(0x4000), bank);
+            mbc.write_u8(&rom, Addr(0xA000), bank);
+        }
+        for bank in 0 .. 16 {
+            assert_eq!(mbc.ram()[bank * 0x2000], bank as u8);
+        }
+    }
+
+    #[test]
+    fn rumble_drives_motor() {
+        let rom = banked_rom(2);
+        let motor = Rc::new(RefCell::new(Vec::new()));
+        let mut mbc = Mbc5::new(0x20000, true);
+        {
+            let motor = motor.clone();
+            mbc.set_rumble_callback(Box::new(move |on| motor.borrow_mut().push(on)));
+        }
+        mbc.write_u8(&rom, Addr(0x0000), 0x0A);
+
+        mbc.write_u8(&rom, Addr(0x4000), MOTOR | 0x03);
+        mbc.write_u8(&rom, Addr(0xA000), 0x33);
+        mbc.write_u8(&rom, Addr(0x4000), MOTOR | 0x03);
+        mbc.write_u8(&rom, Addr(0x4000), 0x00);
+        assert_eq!(*motor.borrow(), vec![true, false]);
+
+        // The motor bit doesn't select a RAM bank
+        assert_eq!(mbc.ram()[0x6000], 0x33);
+    }
+}
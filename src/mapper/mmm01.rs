@@ -0,0 +1,229 @@
+use super::{Mapper, rom_offset, ram_offset};
+use memory::Addr;
+
+/// MMM01: the mapper of multicarts, up to 8 MiB of ROM and 128 KiB of RAM.
+///
+/// At power on the last 32 KiB of ROM, holding the menu, are mapped to 0x0000 - 0x7FFF.
+/// The menu then configures the game's upper ROM and RAM bank bits and which
+/// of the lower bits stay fixed, and maps the game by setting bit 6 at 0x0000 - 0x1FFF.
+/// From then on the configuration is locked and the game sees an MBC1 like mapper.
+pub struct Mmm01 {
+    mapped: bool,
+    ram_enabled: bool,
+    rom_bank_low: u8,
+    rom_bank_mid: u8,
+    rom_bank_high: u8,
+    // Bits of the low ROM bank the game can't change
+    rom_bank_fixed: u8,
+    ram_bank_low: u8,
+    ram_bank_high: u8,
+    ram_bank_fixed: u8,
+    mode: bool,
+    mode_locked: bool,
+    ram: Vec<u8>,
+}
+
+impl Mmm01 {
+    pub fn new(ram_size: usize) -> Mmm01 {
+        Mmm01 {
+            mapped: false,
+            ram_enabled: false,
+            rom_bank_low: 0,
+            rom_bank_mid: 0,
+            rom_bank_high: 0,
+            rom_bank_fixed: 0,
+            ram_bank_low: 0,
+            ram_bank_high: 0,
+            ram_bank_fixed: 0,
+            mode: false,
+            mode_locked: false,
+            ram: vec![0; ram_size],
+        }
+    }
+
+    fn game_base(&self) -> usize {
+        (self.rom_bank_high as usize) << 7 | (self.rom_bank_mid as usize) << 5
+    }
+
+    fn fixed_bank(&self, rom: &[u8]) -> usize {
+        if self.mapped {
+            self.game_base() | (self.rom_bank_low & self.rom_bank_fixed) as usize
+        } else {
+            (rom.len() / 0x4000).saturating_sub(2)
+        }
+    }
+
+    fn switchable_bank(&self, rom: &[u8]) -> usize {
+        if self.mapped {
+            let low = if self.rom_bank_low == 0 { 1 } else { self.rom_bank_low };
+            self.game_base() | low as usize
+        } else {
+            (rom.len() / 0x4000).saturating_sub(1)
+        }
+    }
+
+    // Like on MBC1, the low RAM bank bits only apply in the second mode
+    fn ram_bank(&self) -> usize {
+        let low = if self.mode { self.ram_bank_low } else { self.ram_bank_low & self.ram_bank_fixed };
+        (self.ram_bank_high << 2 | low) as usize
+    }
+
+    fn ram_accessible(&self) -> bool {
+        self.ram_enabled && !self.ram.is_empty()
+    }
+
+    fn write_ram_enable(&mut self, value: u8) {
+        self.ram_enabled = value & 0x0F == 0x0A;
+
+        if !self.mapped {
+            self.ram_bank_fixed = value >> 4 & 0x03;
+            self.mapped = value & 0x40 != 0;
+        }
+    }
+
+    fn write_rom_bank(&mut self, value: u8) {
+        if self.mapped {
+            let fixed = self.rom_bank_fixed;
+            self.rom_bank_low = self.rom_bank_low & fixed | value & 0x1F & !fixed;
+        } else {
+            self.rom_bank_low = value & 0x1F;
+            self.rom_bank_mid = value >> 5 & 0x03;
+        }
+    }
+
+    fn write_ram_bank(&mut self, value: u8) {
+        if self.mapped {
+            let fixed = self.ram_bank_fixed;
+            self.ram_bank_low = self.ram_bank_low & fixed | value & 0x03 & !fixed;
+        } else {
+            self.ram_bank_low = value & 0x03;
+            self.ram_bank_high = value >> 2 & 0x03;
+            self.rom_bank_high = value >> 4 & 0x03;
+            self.mode_locked = value & 0x40 != 0;
+        }
+    }
+
+    fn write_mode(&mut self, value: u8) {
+        if !(self.mapped && self.mode_locked) {
+            self.mode = value & 0x01 != 0;
+        }
+
+        // Bits 2 - 5 fix bits 1 - 4 of the low ROM bank
+        if !self.mapped {
+            self.rom_bank_fixed = value >> 1 & 0x1E;
+        }
+    }
+}
+
+impl Mapper for Mmm01 {
+    fn read_u8(&mut self, rom: &[u8], addr: Addr) -> u8 {
+        match *addr {
+            0x0000 ..= 0x3FFF => rom[rom_offset(rom, self.fixed_bank(rom), addr)],
+            0x4000 ..= 0x7FFF => rom[rom_offset(rom, self.switchable_bank(rom), addr)],
+            0xA000 ..= 0xBFFF => if self.ram_accessible() {
+                self.ram[ram_offset(&self.ram, self.ram_bank(), addr)]
+            } else {
+                0xFF
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn write_u8(&mut self, _rom: &[u8], addr: Addr, value: u8) {
+        match *addr {
+            0x0000 ..= 0x1FFF => self.write_ram_enable(value),
+            0x2000 ..= 0x3FFF => self.write_rom_bank(value),
+            0x4000 ..= 0x5FFF => self.write_ram_bank(value),
+            0x6000 ..= 0x7FFF => self.write_mode(value),
+            0xA000 ..= 0xBFFF => if self.ram_accessible() {
+                let offset = ram_offset(&self.ram, self.ram_bank(), addr);
+                self.ram[offset] = value;
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mapper::tests::{banked_rom, bank_at};
+
+    #[test]
+    fn starts_in_last_32k() {
+        let rom = banked_rom(64);
+        let mut mbc = Mmm01::new(0);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x0000), 62);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 63);
+
+        // ROMs too small for a menu don't underflow
+        let rom = banked_rom(1);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x0000), 0);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0);
+    }
+
+    #[test]
+    fn menu_maps_game() {
+        let rom = banked_rom(512);
+        let mut mbc = Mmm01::new(0);
+
+        // Game at bank 0xA0: ROM bank bits 5 - 6 and 7 - 8
+        mbc.write_u8(&rom, Addr(0x2000), 0x20);
+        mbc.write_u8(&rom, Addr(0x4000), 0x10);
+        mbc.write_u8(&rom, Addr(0x0000), 0x40);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x0000), 0xA0);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0xA1);
+
+        mbc.write_u8(&rom, Addr(0x2000), 0x1F);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0xBF);
+
+        // The configuration is locked once mapped
+        mbc.write_u8(&rom, Addr(0x2000), 0x65);
+        mbc.write_u8(&rom, Addr(0x4000), 0x30);
+        mbc.write_u8(&rom, Addr(0x0000), 0x00);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x0000), 0xA0);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0xA5);
+    }
+
+    #[test]
+    fn menu_fixes_low_bank_bits() {
+        let rom = banked_rom(64);
+        let mut mbc = Mmm01::new(0);
+
+        // A 32 KiB game at bank 6, only bit 0 is left to the game
+        mbc.write_u8(&rom, Addr(0x2000), 0x06);
+        mbc.write_u8(&rom, Addr(0x6000), 0x3C);
+        mbc.write_u8(&rom, Addr(0x0000), 0x40);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x0000), 0x06);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x06);
+
+        mbc.write_u8(&rom, Addr(0x2000), 0x1F);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x07);
+    }
+
+    #[test]
+    fn ram_banks() {
+        let rom = banked_rom(4);
+        let mut mbc = Mmm01::new(0x20000);
+
+        // RAM bank bits 2 - 3 from the menu, bits 0 - 1 left to the game in the second mode
+        mbc.write_u8(&rom, Addr(0x4000), 0x04);
+        mbc.write_u8(&rom, Addr(0x0000), 0x4A);
+        mbc.write_u8(&rom, Addr(0x6000), 0x01);
+        mbc.write_u8(&rom, Addr(0x4000), 0x02);
+        mbc.write_u8(&rom, Addr(0xA000), 0x66);
+        assert_eq!(mbc.ram()[0x06 * 0x2000], 0x66);
+        assert_eq!(mbc.read_u8(&rom, Addr(0xA000)), 0x66);
+
+        mbc.write_u8(&rom, Addr(0x0000), 0x00);
+        assert_eq!(mbc.read_u8(&rom, Addr(0xA000)), 0xFF);
+    }
+}
@@ -1,15 +1,124 @@
 use memory::Addr;
 use rom::{Rom, Type};
+mod clock;
+mod rom_only;
 mod mbc1;
+mod mbc2;
+mod mbc3;
+mod mbc5;
+mod mmm01;
+mod huc1;
+mod huc3;
+mod tama5;
+
+pub use self::clock::ClockSource;
+
+/// Called with the new state whenever a rumble cart turns its motor on or off.
+pub type RumbleCallback = Box<dyn FnMut(bool)>;
 
 pub trait Mapper {
     fn read_u8(&mut self, rom: &[u8], addr: Addr) -> u8;
     fn write_u8(&mut self, rom: &[u8], addr: Addr, value: u8);
+
+    /// The cartridge RAM, saved across runs on carts with a battery.
+    fn ram(&self) -> &[u8] {
+        &[]
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut []
+    }
+
+    /// State saved after the RAM on carts with a battery, like a real-time clock's.
+    fn save_footer(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Restores the state saved by `save_footer`.
+    fn load_footer(&mut self, _footer: &[u8]) {}
+
+    /// Advances the cartridge's clock, if any, at the normal speed's rate.
+    fn tick(&mut self, _cycles: u16) {}
+
+    fn set_clock_source(&mut self, _source: ClockSource) {}
+
+    fn set_rumble_callback(&mut self, _callback: RumbleCallback) {}
 }
 
-pub fn from_rom(rom: &Rom) -> Box<Mapper> {
+pub fn from_rom(rom: &Rom) -> Box<dyn Mapper> {
     match rom.typ() {
-        Type::RomMbc1 => Box::new(mbc1::Mbc1::new()),
+        Type::Rom |
+        Type::RomRam |
+        Type::RomRamBatt => Box::new(rom_only::RomOnly::new(rom.ram_size().bytes())),
+        Type::RomMbc1 |
+        Type::RomMbc1Ram |
+        Type::RomMbc1RamBatt => if mbc1::is_multicart(&rom.data) {
+            Box::new(mbc1::Mbc1::multicart(rom.ram_size().bytes()))
+        } else {
+            Box::new(mbc1::Mbc1::new(rom.ram_size().bytes()))
+        },
+        Type::RomMbc2 |
+        Type::RomMbc2Batt => Box::new(mbc2::Mbc2::new()),
+        Type::RomMbc3 |
+        Type::RomMbc3Ram |
+        Type::RomMbc3RamBatt => Box::new(mbc3::Mbc3::new(rom.ram_size().bytes(), false)),
+        Type::RomMbc3TimerBatt |
+        Type::RomMbc3TimerRamBatt => Box::new(mbc3::Mbc3::new(rom.ram_size().bytes(), true)),
+        Type::RomMbc5 |
+        Type::RomMbc5Ram |
+        Type::RomMbc5RamBatt => Box::new(mbc5::Mbc5::new(rom.ram_size().bytes(), false)),
+        Type::RomMbc5Rumble |
+        Type::RomMbc5RumbleSram |
+        Type::RomMbc5RumbleSramBatt => Box::new(mbc5::Mbc5::new(rom.ram_size().bytes(), true)),
+        Type::RomMmm01 |
+        Type::RomMmm01Sram |
+        Type::RomMmm01SramBatt => Box::new(mmm01::Mmm01::new(rom.ram_size().bytes())),
+        Type::HudsonHuC1 => Box::new(huc1::Huc1::new(rom.ram_size().bytes())),
+        Type::HudsonHuC3 => Box::new(huc3::Huc3::new(rom.ram_size().bytes())),
+        Type::BandaiTAMA5 => Box::new(tama5::Tama5::new()),
         typ => panic!("Mapper not implemented: {:?}", typ)
     }
 }
+
+// Bank numbers beyond the ROM's size wrap around, as the upper bank lines aren't connected
+fn rom_offset(rom: &[u8], bank: usize, addr: Addr) -> usize {
+    ((bank * 0x4000) | (*addr as usize & 0x3FFF)) % rom.len()
+}
+
+// RAM smaller than a bank is mirrored across it
+fn ram_offset(ram: &[u8], bank: usize, addr: Addr) -> usize {
+    ((bank * 0x2000) | (*addr as usize & 0x1FFF)) % ram.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A ROM in which the first two bytes of every bank hold the bank's number.
+    pub fn banked_rom(banks: usize) -> Vec<u8> {
+        let mut rom = vec![0; banks * 0x4000];
+        for (bank, data) in rom.chunks_mut(0x4000).enumerate() {
+            data[0] = bank as u8;
+            data[1] = (bank >> 8) as u8;
+        }
+        rom
+    }
+
+    /// The number of the bank mapped at `addr`, as stored by `banked_rom`.
+    pub fn bank_at<M: Mapper>(mapper: &mut M, rom: &[u8], addr: u16) -> usize {
+        let low = mapper.read_u8(rom, Addr(addr)) as usize;
+        let high = mapper.read_u8(rom, Addr(addr + 1)) as usize;
+        high << 8 | low
+    }
+
+    #[test]
+    fn offsets_wrap_around() {
+        let rom = vec![0; 0x8000];
+        assert_eq!(rom_offset(&rom, 3, Addr(0x4123)), 0x4123);
+        assert_eq!(rom_offset(&rom, 2, Addr(0x7FFF)), 0x3FFF);
+
+        let ram = vec![0; 0x800];
+        assert_eq!(ram_offset(&ram, 0, Addr(0xA800)), 0x000);
+        assert_eq!(ram_offset(&ram, 1, Addr(0xA123)), 0x123);
+    }
+}
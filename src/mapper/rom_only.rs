@@ -0,0 +1,74 @@
+use super::{Mapper, rom_offset, ram_offset};
+use memory::Addr;
+
+/// Carts without a mapper: 32 KiB of ROM and up to 8 KiB of RAM.
+pub struct RomOnly {
+    ram: Vec<u8>,
+}
+
+impl RomOnly {
+    pub fn new(ram_size: usize) -> RomOnly {
+        RomOnly {
+            ram: vec![0; ram_size],
+        }
+    }
+}
+
+impl Mapper for RomOnly {
+    fn read_u8(&mut self, rom: &[u8], addr: Addr) -> u8 {
+        match *addr {
+            0x0000 ..= 0x3FFF => rom[rom_offset(rom, 0, addr)],
+            0x4000 ..= 0x7FFF => rom[rom_offset(rom, 1, addr)],
+            0xA000 ..= 0xBFFF => if self.ram.is_empty() {
+                0xFF
+            } else {
+                self.ram[ram_offset(&self.ram, 0, addr)]
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn write_u8(&mut self, _rom: &[u8], addr: Addr, value: u8) {
+        match *addr {
+            0x0000 ..= 0x7FFF => {},
+            0xA000 ..= 0xBFFF => if !self.ram.is_empty() {
+                let offset = ram_offset(&self.ram, 0, addr);
+                self.ram[offset] = value;
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mapper::tests::{banked_rom, bank_at};
+
+    #[test]
+    fn maps_rom_and_ram() {
+        let rom = banked_rom(2);
+        let mut mbc = RomOnly::new(0x800);
+
+        mbc.write_u8(&rom, Addr(0x2000), 0x01);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x0000), 0);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 1);
+
+        // 2 KiB of RAM repeat across the area
+        mbc.write_u8(&rom, Addr(0xA010), 0x42);
+        assert_eq!(mbc.read_u8(&rom, Addr(0xA810)), 0x42);
+        assert_eq!(mbc.read_u8(&rom, Addr(0xB810)), 0x42);
+
+        let mut mbc = RomOnly::new(0);
+        mbc.write_u8(&rom, Addr(0xA000), 0x42);
+        assert_eq!(mbc.read_u8(&rom, Addr(0xA000)), 0xFF);
+    }
+}
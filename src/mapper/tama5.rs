@@ -0,0 +1,162 @@
+use super::{Mapper, rom_offset};
+use memory::Addr;
+
+const RAM_SIZE: usize = 32;
+
+// Registers written through 0xA000 after selecting them at 0xA001
+const REG_ROM_BANK_LOW: u8 = 0x0;
+const REG_ROM_BANK_HIGH: u8 = 0x1;
+const REG_DATA_LOW: u8 = 0x4;
+const REG_DATA_HIGH: u8 = 0x5;
+const REG_CONTROL: u8 = 0x6;
+const REG_ADDRESS: u8 = 0x7;
+// Registers read through 0xA000
+const REG_STATUS: u8 = 0xA;
+const REG_RESULT_LOW: u8 = 0xC;
+const REG_RESULT_HIGH: u8 = 0xD;
+
+const CMD_WRITE: u8 = 0x0;
+const CMD_READ: u8 = 0x1;
+
+const READY: u8 = 0x01;
+
+/// Bandai's TAMA5: up to 512 KiB of ROM and 32 bytes of RAM inside the mapper.
+///
+/// Everything goes through a window of two registers, 0xA001 selects one of
+/// the 4 bit registers and 0xA000 writes or reads it. A RAM access is prepared
+/// with the data and control registers and runs when its address is written,
+/// bit 0 of the control register being bit 4 of the address and bits 1 - 3 the command.
+/// The TAMA6's clock, reached with other commands, isn't emulated.
+pub struct Tama5 {
+    register: u8,
+    rom_bank: u8,
+    data: u8,
+    control: u8,
+    result: u8,
+    ram: Vec<u8>,
+}
+
+impl Tama5 {
+    pub fn new() -> Tama5 {
+        Tama5 {
+            register: 0,
+            rom_bank: 1,
+            data: 0,
+            control: 0,
+            result: 0,
+            ram: vec![0; RAM_SIZE],
+        }
+    }
+
+    fn write_register(&mut self, value: u8) {
+        let value = value & 0x0F;
+
+        match self.register {
+            REG_ROM_BANK_LOW => self.rom_bank = self.rom_bank & 0x10 | value,
+            REG_ROM_BANK_HIGH => self.rom_bank = self.rom_bank & 0x0F | (value & 0x01) << 4,
+            REG_DATA_LOW => self.data = self.data & 0xF0 | value,
+            REG_DATA_HIGH => self.data = self.data & 0x0F | value << 4,
+            REG_CONTROL => self.control = value,
+            REG_ADDRESS => {
+                let address = ((self.control & 0x01) << 4 | value) as usize;
+                match self.control >> 1 {
+                    CMD_WRITE => self.ram[address] = self.data,
+                    CMD_READ => self.result = self.ram[address],
+                    _ => {},
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn read_register(&self) -> u8 {
+        match self.register {
+            REG_STATUS => 0xF0 | READY,
+            REG_RESULT_LOW => 0xF0 | self.result & 0x0F,
+            REG_RESULT_HIGH => 0xF0 | self.result >> 4,
+            _ => 0xFF,
+        }
+    }
+}
+
+impl Mapper for Tama5 {
+    fn read_u8(&mut self, rom: &[u8], addr: Addr) -> u8 {
+        match *addr {
+            0x0000 ..= 0x3FFF => rom[rom_offset(rom, 0, addr)],
+            0x4000 ..= 0x7FFF => rom[rom_offset(rom, self.rom_bank as usize, addr)],
+            0xA000 ..= 0xBFFF => if *addr & 0x01 == 0 {
+                self.read_register()
+            } else {
+                0xFF
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn write_u8(&mut self, _rom: &[u8], addr: Addr, value: u8) {
+        match *addr {
+            0x0000 ..= 0x7FFF => {},
+            0xA000 ..= 0xBFFF => if *addr & 0x01 == 0 {
+                self.write_register(value);
+            } else {
+                self.register = value & 0x0F;
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mapper::tests::{banked_rom, bank_at};
+
+    fn write_register(mbc: &mut Tama5, register: u8, value: u8) {
+        mbc.write_u8(&[], Addr(0xA001), register);
+        mbc.write_u8(&[], Addr(0xA000), value);
+    }
+
+    fn read_register(mbc: &mut Tama5, register: u8) -> u8 {
+        mbc.write_u8(&[], Addr(0xA001), register);
+        mbc.read_u8(&[], Addr(0xA000))
+    }
+
+    #[test]
+    fn selects_rom_bank_in_nibbles() {
+        let rom = banked_rom(32);
+        let mut mbc = Tama5::new();
+
+        write_register(&mut mbc, REG_ROM_BANK_LOW, 0x0A);
+        write_register(&mut mbc, REG_ROM_BANK_HIGH, 0x01);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x1A);
+
+        write_register(&mut mbc, REG_ROM_BANK_LOW, 0x03);
+        assert_eq!(bank_at(&mut mbc, &rom, 0x4000), 0x13);
+    }
+
+    #[test]
+    fn accesses_ram_through_registers() {
+        let mut mbc = Tama5::new();
+        assert_eq!(read_register(&mut mbc, REG_STATUS), 0xF0 | READY);
+
+        // Write 0xA5 to address 0x13
+        write_register(&mut mbc, REG_DATA_LOW, 0x05);
+        write_register(&mut mbc, REG_DATA_HIGH, 0x0A);
+        write_register(&mut mbc, REG_CONTROL, CMD_WRITE << 1 | 0x01);
+        write_register(&mut mbc, REG_ADDRESS, 0x03);
+        assert_eq!(mbc.ram()[0x13], 0xA5);
+
+        write_register(&mut mbc, REG_CONTROL, CMD_READ << 1 | 0x01);
+        write_register(&mut mbc, REG_ADDRESS, 0x03);
+        assert_eq!(read_register(&mut mbc, REG_RESULT_LOW), 0xF5);
+        assert_eq!(read_register(&mut mbc, REG_RESULT_HIGH), 0xFA);
+    }
+}
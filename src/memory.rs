@@ -1,86 +1,286 @@
-use std::ops::{Deref, Add, Sub, Index};
+use std::ops::{Deref, Add, Sub};
 use rom::Rom;
 use monster::incubation::SplitInt;
-use mapper::Mapper;
+use mapper::{Mapper, ClockSource, RumbleCallback};
+use interrupts::Interrupts;
+use timer::Timer;
+use ppu::{Ppu, OamCorruption};
+use dma::Dma;
+use apu::Apu;
+use joypad::{Joypad, Button};
+use serial::Serial;
 
 pub struct Memory {
-    mapper: Box<Mapper>,
+    mapper: Box<dyn Mapper>,
     stack: [u8; 128], // 0xFF = IF
     ram: [u8; 8*1024],
     rom: Rom,
-    pub serial_line: String
+    model: Model,
+    double_speed: bool,
+    speed_switch_armed: bool,
+    cycles: u64,
+    pub interrupts: Interrupts,
+    pub timer: Timer,
+    pub ppu: Ppu,
+    pub dma: Dma,
+    pub apu: Apu,
+    pub joypad: Joypad,
+    pub serial: Serial,
 }
 
 impl Memory {
+    #[cfg(test)]
     pub fn new(rom: Rom) -> Self {
+        Self::with_model(rom, Model::Dmg)
+    }
+
+    pub fn with_model(rom: Rom, model: Model) -> Self {
         Memory {
+            model: model,
+            double_speed: false,
+            speed_switch_armed: false,
             mapper: ::mapper::from_rom(&rom),
             stack: [0; 128],
             ram: [0; 8*1024],
             rom: rom,
-            serial_line: String::new(),
+            cycles: 0,
+            interrupts: Interrupts::new(),
+            timer: Timer::new(),
+            ppu: Ppu::new(),
+            dma: Dma::new(),
+            apu: Apu::new(model),
+            joypad: Joypad::new(),
+            serial: Serial::new(model),
         }
     }
 
+    // The CPU's bus accesses each take one M-cycle, during which
+    // the rest of the system is advanced before the access happens
+
     pub fn read_u8(&mut self, addr: Addr) -> u8 {
+        self.tick(4);
+
+        if self.dma_blocks(addr) {
+            return self.dma_conflict_read(addr);
+        }
+
+        self.corrupt_oam(addr, OamCorruption::Read);
+        self.read_u8_untimed(addr)
+    }
+
+    pub fn write_u8(&mut self, addr: Addr, value: u8) {
+        self.tick(4);
+
+        if self.dma_blocks(addr) {
+            return;
+        }
+
+        self.corrupt_oam(addr, OamCorruption::Write);
+        self.write_u8_untimed(addr, value);
+    }
+
+    /// A read during which the address is also incremented or decremented,
+    /// as done by `LD A,(HL+)`, `LD A,(HL-)` and `POP`.
+    pub fn read_u8_inc_dec(&mut self, addr: Addr) -> u8 {
+        self.tick(4);
+
+        if self.dma_blocks(addr) {
+            return self.dma_conflict_read(addr);
+        }
+
+        self.corrupt_oam(addr, OamCorruption::ReadIncDec);
+        self.read_u8_untimed(addr)
+    }
+
+    /// An internal M-cycle during which the CPU increments or decrements
+    /// a 16 bit register, putting its value on the address bus.
+    pub fn tick_inc_dec(&mut self, addr: Addr) {
+        self.tick(4);
+        self.corrupt_oam(addr, OamCorruption::Write);
+    }
+
+    // DMG corrupts OAM when the address bus points at it during OAM scan
+    fn corrupt_oam(&mut self, addr: Addr, corruption: OamCorruption) {
+        if self.model == Model::Dmg && addr.in_range(0xFE00, 0xFF00) {
+            self.ppu.corrupt_oam(corruption);
+        }
+    }
+
+    // While OAM DMA is running the CPU can only access HRAM and I/O registers,
+    // reads from the buses used by the transfer see the byte being copied
+    fn dma_blocks(&self, addr: Addr) -> bool {
+        self.dma.is_active() && *addr < 0xFF00
+    }
+
+    fn dma_conflict_read(&self, addr: Addr) -> u8 {
+        match Location::from_addr(*addr) {
+            Location::OAM(_) => 0xFF,
+            _ => self.dma.last_byte(),
+        }
+    }
+
+    pub fn read_u8_untimed(&mut self, addr: Addr) -> u8 {
         fn read_stub(msg: &str, addr: u16, value: u8) -> u8 {
             println!("READ_STUB: 0x{:04X} {}", addr, msg);
             value
         }
         use self::Location::*;
-        let result = match Location::from_addr(*addr) {
-            InterruptEnable => read_stub("IE register", *addr, 0),
+        match Location::from_addr(*addr) {
+            InterruptEnable => self.interrupts.read_enable(),
             InternalRam128(offset) => self.stack[offset as usize],
             Empty => 0,
-            SerialPort => read_stub("serial port", *addr, 0),
+            Joypad => self.joypad.read(),
+            SerialPort => self.serial.read(*addr),
+            Timer => self.timer.read(*addr),
+            Sound => self.apu.read(*addr),
+            InterruptFlag => self.interrupts.read_flags(),
+            SpeedSwitch => match self.model {
+                Model::Cgb => 0x7E | (self.double_speed as u8) << 7 | self.speed_switch_armed as u8,
+                Model::Dmg => 0xFF,
+            },
             IOStub => read_stub("I/O port", *addr, 0),
-            OAM(_offset) => read_stub("OAM access", *addr, 0),
+            Lcd => self.ppu.read(*addr),
+            OamDma => self.dma.read(),
+            OAM(offset) => self.ppu.read_oam(offset),
             InternalRam8k(offset) => self.ram[offset as usize],
             SwitchableRam => self.mapper.read_u8(&self.rom.data, addr),
-            VRAM(_offset) => read_stub("Video RAM read", *addr, 0),
+            VRAM(offset) => self.ppu.read_vram(offset),
             SwitchableRom => self.mapper.read_u8(&self.rom.data, addr),
-            ROM0(offset) => {
-                debug_assert!(self.rom.data.len() >= 0x4000);
-                self.rom.data[offset as usize]
-            }
-            Stub => panic!("READ_STUB: 0x{:02X}", *addr)
-        };
-        println!("READ at 0x{:04X} = 0x{:04X}", *addr, result);
-        result
+            ROM0 => self.mapper.read_u8(&self.rom.data, addr),
+        }
     }
 
-    pub fn write_u8(&mut self, addr: Addr, value: u8) {
+    pub fn write_u8_untimed(&mut self, addr: Addr, value: u8) {
         fn write_stub(msg: &str, addr: u16, value: u8) {
             println!("WRITE_STUB: 0x{:04X} ← 0x{:02X} {}", addr, value, msg);
         }
         use self::Location::*;
         match Location::from_addr(*addr) {
-            InterruptEnable => write_stub("IE register", *addr, value),
-            InternalRam128(offset) => {
-                println!("InternalRam8k[{:04X}] = {:02X}", *addr, value);
-                self.stack[offset as usize] = value
-            },
+            InterruptEnable => self.interrupts.write_enable(value),
+            InternalRam128(offset) => self.stack[offset as usize] = value,
             Empty => {},
-            SerialPort => self.serial_log(value),
+            Joypad => self.joypad.write(value, &mut self.interrupts),
+            SerialPort => self.serial.write(*addr, value),
+            Timer => self.timer.write(*addr, value),
+            Sound => self.apu.write(*addr, value),
+            InterruptFlag => self.interrupts.write_flags(value),
+            SpeedSwitch => if self.model == Model::Cgb {
+                self.speed_switch_armed = value & 1 == 1;
+            },
             IOStub => write_stub("I/O port write", *addr, value),
-            OAM(_offset) => write_stub("OAM", *addr, value),
+            Lcd => self.ppu.write(*addr, value, &mut self.interrupts),
+            OamDma => self.dma.start(value),
+            OAM(offset) => self.ppu.write_oam(offset, value),
             InternalRam8k(offset) => self.ram[offset as usize] = value,
             SwitchableRam => self.mapper.write_u8(&self.rom.data, addr, value),
-            VRAM(_offset) => write_stub("Video RAM", *addr, value),
+            VRAM(offset) => self.ppu.write_vram(offset, value),
             SwitchableRom => self.mapper.write_u8(&self.rom.data, addr, value),
-            ROM0(offset) => write_stub("ROM bank #0", *addr, value),
-            Stub => panic!("WRITE_STUB: 0x{:02X} ← 0x{:02X}", *addr, value)
+            ROM0 => self.mapper.write_u8(&self.rom.data, addr, value),
         }
     }
 
-    fn serial_log(&mut self, ch: u8) {
-        let ch = ch as char;
-        if ch == '\n' {
-            println!("SERIAL OUT: {}", self.serial_line);
-            self.serial_line.clear();
+    /// Advances all peripherals by the given number of CPU cycles.
+    pub fn tick(&mut self, cycles: u16) {
+        self.cycles += cycles as u64;
+
+        for _ in 0 .. cycles / 4 {
+            self.tick_dma();
+        }
+
+        self.timer.tick(cycles, &mut self.interrupts);
+        self.serial.tick(cycles, &mut self.interrupts);
+
+        // The PPU, APU and cartridge clock keep running at the normal rate in CGB double speed mode
+        let dots = if self.double_speed { cycles / 2 } else { cycles };
+        self.ppu.tick(dots, &mut self.interrupts);
+        self.mapper.tick(dots);
+
+        let div_bit = self.timer.apu_div_bit(self.double_speed);
+        self.apu.tick(dots, div_bit);
+    }
+
+    fn tick_dma(&mut self) {
+        if let Some((source, offset)) = self.dma.step() {
+            let value = self.read_u8_untimed(Addr(source));
+            self.ppu.write_oam_dma(offset, value);
+            self.dma.set_last_byte(value);
+        }
+    }
+
+    /// The cartridge RAM followed by the mapper's footer if it's battery backed, to be kept across runs.
+    pub fn battery_save(&self) -> Option<Vec<u8>> {
+        if self.rom.typ().has_battery() {
+            let mut save = self.mapper.ram().to_vec();
+            save.extend(self.mapper.save_footer());
+            Some(save)
         } else {
-            self.serial_line.push(ch);
+            None
+        }
+    }
+
+    /// Restores battery backed cartridge state saved by an earlier run.
+    pub fn load_battery_save(&mut self, data: &[u8]) {
+        let len = {
+            let ram = self.mapper.ram_mut();
+            let len = ram.len().min(data.len());
+            ram[..len].copy_from_slice(&data[..len]);
+            len
+        };
+
+        if data.len() > len {
+            self.mapper.load_footer(&data[len..]);
+        }
+    }
+
+    /// Selects what drives the cartridge's real-time clock, if it has one.
+    pub fn set_clock_source(&mut self, source: ClockSource) {
+        self.mapper.set_clock_source(source);
+    }
+
+    /// Registers a callback for the motor of rumble carts.
+    pub fn set_rumble_callback(&mut self, callback: RumbleCallback) {
+        self.mapper.set_rumble_callback(callback);
+    }
+
+    /// Total number of CPU cycles elapsed since power on.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    /// Performs a prepared CGB speed switch. Returns false if none was armed via KEY1.
+    pub fn switch_speed(&mut self) -> bool {
+        if !self.speed_switch_armed {
+            return false;
         }
+
+        self.speed_switch_armed = false;
+        self.double_speed = !self.double_speed;
+        true
+    }
+
+    /// Replaces the set of pressed buttons, e.g. once per frame.
+    pub fn set_buttons(&mut self, buttons: &[Button]) {
+        for &button in Button::ALL.iter() {
+            let held = buttons.contains(&button);
+
+            if held && !self.joypad.is_pressed(button) {
+                self.press(button);
+            } else if !held && self.joypad.is_pressed(button) {
+                self.release(button);
+            }
+        }
+    }
+
+    pub fn press(&mut self, button: Button) {
+        self.joypad.press(button, &mut self.interrupts);
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.joypad.release(button);
     }
 
     pub fn read_u16(&mut self, addr: Addr) -> u16 {
@@ -97,15 +297,19 @@ impl Memory {
         self.write_u8(addr + 1, high);
     }
 
-    pub fn size(&self) -> u16 {
-        0xFFFF
-    }
 }
 
 pub enum Location {
     InterruptEnable,
     InternalRam128(u16),
+    Joypad,
     SerialPort,
+    Timer,
+    Sound,
+    InterruptFlag,
+    SpeedSwitch,
+    Lcd,
+    OamDma,
     Empty,
     IOStub,
     OAM(u16),
@@ -113,8 +317,7 @@ pub enum Location {
     SwitchableRam,
     VRAM(u16),
     SwitchableRom,
-    ROM0(u16),
-    Stub,
+    ROM0,
 }
 
 impl Location {
@@ -122,23 +325,37 @@ impl Location {
         use self::Location::*;
         match addr {
             0xFFFF            => InterruptEnable,
-            0xFF80 ... 0xFFFE => InternalRam128(addr - 0xFF80),
-            0xFF4C ... 0xFF7F => Empty,
-            0xFF01            => SerialPort,
-            0xFF00 ... 0xFF4B => IOStub,
-            0xFEA0 ... 0xFEFF => Empty,
-            0xFE00 ... 0xFE9F => OAM(addr - 0xFE00),
-            0xE000 ... 0xFDFF => InternalRam8k(addr - 0xE000),
-            0xC000 ... 0xDFFF => InternalRam8k(addr - 0xC000),
-            0xA000 ... 0xBFFF => SwitchableRam,
-            0x8000 ... 0x9FFF => VRAM(addr - 0x8000),
-            0x4000 ... 0x7FFF => SwitchableRom,
-            0x0000 ... 0x3FFF => ROM0(addr),
-            _                 => Stub
+            0xFF80 ..= 0xFFFE => InternalRam128(addr - 0xFF80),
+            0xFF4D            => SpeedSwitch,
+            0xFF76 ..= 0xFF77 => Sound,
+            0xFF4C | 0xFF4E ..= 0xFF75 | 0xFF78 ..= 0xFF7F => Empty,
+            0xFF00            => Joypad,
+            0xFF01 ..= 0xFF02 => SerialPort,
+            0xFF04 ..= 0xFF07 => Timer,
+            0xFF0F            => InterruptFlag,
+            0xFF10 ..= 0xFF3F => Sound,
+            0xFF40 ..= 0xFF45 => Lcd,
+            0xFF46            => OamDma,
+            0xFF47 ..= 0xFF4B => Lcd,
+            0xFF03 | 0xFF08 ..= 0xFF0E => IOStub,
+            0xFEA0 ..= 0xFEFF => Empty,
+            0xFE00 ..= 0xFE9F => OAM(addr - 0xFE00),
+            0xE000 ..= 0xFDFF => InternalRam8k(addr - 0xE000),
+            0xC000 ..= 0xDFFF => InternalRam8k(addr - 0xC000),
+            0xA000 ..= 0xBFFF => SwitchableRam,
+            0x8000 ..= 0x9FFF => VRAM(addr - 0x8000),
+            0x4000 ..= 0x7FFF => SwitchableRom,
+            0x0000 ..= 0x3FFF => ROM0,
         }
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Model {
+    Dmg,
+    Cgb,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Addr(pub u16);
 
@@ -0,0 +1,140 @@
+use std::io::{self, Write};
+use byteorder::{ByteOrder, BigEndian};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+// Stored deflate blocks hold at most 65535 bytes
+const MAX_BLOCK_SIZE: usize = 0xFFFF;
+
+/// Writes an 8 bit grayscale PNG, one byte per pixel in row major order.
+///
+/// The image data is stored uncompressed, which keeps
+/// the encoder trivial at the cost of larger files.
+pub fn write_grayscale<W: Write>(out: &mut W, width: u32, height: u32, pixels: &[u8]) -> io::Result<()> {
+    debug_assert_eq!(pixels.len(), (width * height) as usize);
+
+    out.write_all(&SIGNATURE)?;
+
+    let mut header = [0; 13];
+    BigEndian::write_u32(&mut header[0..4], width);
+    BigEndian::write_u32(&mut header[4..8], height);
+    header[8] = 8; // bit depth
+    header[9] = 0; // grayscale
+    header[10] = 0; // deflate
+    header[11] = 0; // adaptive filtering
+    header[12] = 0; // no interlacing
+    write_chunk(out, b"IHDR", &header)?;
+
+    // Each scanline starts with its filter type, 0 = none
+    let mut raw = Vec::with_capacity(pixels.len() + height as usize);
+    for row in pixels.chunks(width as usize) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    write_chunk(out, b"IDAT", &zlib_stored(&raw))?;
+
+    write_chunk(out, b"IEND", &[])
+}
+
+fn write_chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut length = [0; 4];
+    BigEndian::write_u32(&mut length, data.len() as u32);
+    out.write_all(&length)?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+
+    let mut crc = [0; 4];
+    BigEndian::write_u32(&mut crc, crc32(kind.iter().chain(data)));
+    out.write_all(&crc)
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // Deflate with a 32K window, no preset dictionary
+    let mut out = vec![0x78, 0x01];
+
+    let mut blocks = data.chunks(MAX_BLOCK_SIZE).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let length = block.len() as u16;
+
+        out.push(last as u8);
+        out.extend_from_slice(&[length as u8, (length >> 8) as u8]);
+        out.extend_from_slice(&[!length as u8, (!length >> 8) as u8]);
+        out.extend_from_slice(block);
+    }
+
+    let mut adler = [0; 4];
+    BigEndian::write_u32(&mut adler, adler32(data));
+    out.extend_from_slice(&adler);
+
+    out
+}
+
+fn crc32<'a, I: Iterator<Item = &'a u8>>(bytes: I) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0 .. 8 {
+            crc = if crc & 1 != 0 { crc >> 1 ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b"123456789".iter()), 0xCBF43926);
+        assert_eq!(crc32(b"IEND".iter()), 0xAE426082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+    }
+
+    #[test]
+    fn stored_blocks() {
+        assert_eq!(zlib_stored(&[]), vec![0x78, 0x01, 0x01, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x01]);
+
+        let data = vec![0xAB; MAX_BLOCK_SIZE + 1];
+        let out = zlib_stored(&data);
+        assert_eq!(out.len(), 2 + 5 + MAX_BLOCK_SIZE + 5 + 1 + 4);
+        assert_eq!(&out[2..7], &[0x00, 0xFF, 0xFF, 0x00, 0x00]);
+        assert_eq!(&out[7 + MAX_BLOCK_SIZE..][..5], &[0x01, 0x01, 0x00, 0xFE, 0xFF]);
+    }
+
+    #[test]
+    fn writes_grayscale_image() {
+        let mut out = Vec::new();
+        write_grayscale(&mut out, 3, 2, &[0, 1, 2, 3, 4, 5]).unwrap();
+
+        assert_eq!(&out[..8], &SIGNATURE);
+        assert_eq!(&out[8..16], b"\x00\x00\x00\x0DIHDR");
+        assert_eq!(&out[16..29], &[0, 0, 0, 3, 0, 0, 0, 2, 8, 0, 0, 0, 0]);
+
+        // Filter bytes in front of each scanline, stored in a single block
+        let idat = &out[33..];
+        let length = idat[..4].iter().fold(0, |length, &byte| length << 8 | byte as usize);
+        assert_eq!(&idat[4..8], b"IDAT");
+        let zlib = &idat[8..8 + length];
+        assert_eq!(&zlib[7..15], &[0, 0, 1, 2, 0, 3, 4, 5]);
+
+        assert_eq!(&out[out.len() - 12..], b"\x00\x00\x00\x00IEND\xAE\x42\x60\x82");
+    }
+}
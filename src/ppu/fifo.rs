@@ -0,0 +1,279 @@
+use std::collections::VecDeque;
+use super::{Ppu, SCREEN_WIDTH};
+use super::scanline::shade;
+use self::FetchStep::*;
+
+// The first tile fetch of each line is thrown away
+const DUMMY_FETCH_DOTS: u8 = 6;
+const SPRITE_FETCH_DOTS: u8 = 6;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum FetchStep {
+    Tile,
+    DataLow,
+    DataHigh,
+    Push,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct SpritePixel {
+    color: u8,
+    obp1: bool,
+    behind_bg: bool,
+}
+
+/// State of the pixel FIFO renderer during mode 3.
+///
+/// Models the background fetcher feeding an 8 pixel FIFO, the discarding
+/// of SCX % 8 pixels at the start of a line, the fetcher restart when
+/// the window is hit and the stalls caused by sprite fetches.
+pub struct Fifo {
+    bg: VecDeque<u8>,
+    sprites: VecDeque<SpritePixel>,
+    step: FetchStep,
+    step_dots: u8,
+    tile: u8,
+    low: u8,
+    high: u8,
+    fetcher_x: u8,
+    window: bool,
+    x: u8,
+    discard: u8,
+    startup_dots: u8,
+    sprite_fetch: Option<(usize, u8)>,
+    fetched_sprites: Vec<bool>,
+}
+
+impl Fifo {
+    pub fn new() -> Fifo {
+        Fifo {
+            bg: VecDeque::with_capacity(16),
+            sprites: VecDeque::with_capacity(8),
+            step: Tile,
+            step_dots: 0,
+            tile: 0,
+            low: 0,
+            high: 0,
+            fetcher_x: 0,
+            window: false,
+            x: 0,
+            discard: 0,
+            startup_dots: 0,
+            sprite_fetch: None,
+            fetched_sprites: Vec::new(),
+        }
+    }
+}
+
+impl Ppu {
+    pub(super) fn fifo_start_line(&mut self) {
+        let fifo = &mut self.fifo;
+
+        fifo.bg.clear();
+        fifo.sprites.clear();
+        fifo.step = Tile;
+        fifo.step_dots = 0;
+        fifo.fetcher_x = 0;
+        fifo.window = false;
+        fifo.x = 0;
+        fifo.discard = self.scx % 8;
+        fifo.startup_dots = DUMMY_FETCH_DOTS;
+        fifo.sprite_fetch = None;
+        fifo.fetched_sprites.clear();
+        fifo.fetched_sprites.resize(self.sprites.len(), false);
+    }
+
+    /// Advances mode 3 by one dot. Returns true once the line is complete.
+    pub(super) fn fifo_dot(&mut self) -> bool {
+        if self.fifo.startup_dots > 0 {
+            self.fifo.startup_dots -= 1;
+            return false;
+        }
+
+        if let Some((index, dots)) = self.fifo.sprite_fetch {
+            // The background fetch in progress is completed first
+            if self.fifo.step != Push {
+                self.fetcher_dot();
+                return false;
+            }
+
+            if dots > 1 {
+                self.fifo.sprite_fetch = Some((index, dots - 1));
+            } else {
+                self.fetch_sprite(index);
+                self.fifo.sprite_fetch = None;
+            }
+
+            return false;
+        }
+
+        if let Some(index) = self.next_sprite() {
+            self.fifo.fetched_sprites[index] = true;
+            self.fifo.sprite_fetch = Some((index, SPRITE_FETCH_DOTS));
+            return false;
+        }
+
+        if !self.fifo.window && self.window_reached() {
+            self.start_window();
+        }
+
+        self.fetcher_dot();
+
+        let color = match self.fifo.bg.pop_front() {
+            Some(color) => color,
+            None => return false,
+        };
+
+        if self.fifo.discard > 0 {
+            self.fifo.discard -= 1;
+            return false;
+        }
+
+        let sprite = self.fifo.sprites.pop_front();
+        self.output_pixel(color, sprite);
+        self.fifo.x += 1;
+
+        if self.fifo.x as usize == SCREEN_WIDTH {
+            if self.fifo.window {
+                self.window_line += 1;
+            }
+            return true;
+        }
+
+        false
+    }
+
+    fn output_pixel(&mut self, bg_color: u8, sprite: Option<SpritePixel>) {
+        let bg_color = if self.lcdc & 0x01 != 0 { bg_color } else { 0 };
+        let mut shade_value = shade(self.bgp, bg_color);
+
+        if let Some(sprite) = sprite {
+            let visible = self.lcdc & 0x02 != 0 && sprite.color != 0;
+            if visible && !(sprite.behind_bg && bg_color != 0) {
+                let palette = if sprite.obp1 { self.obp1 } else { self.obp0 };
+                shade_value = shade(palette, sprite.color);
+            }
+        }
+
+        let index = self.ly as usize * SCREEN_WIDTH + self.fifo.x as usize;
+        self.back_buffer[index] = shade_value;
+    }
+
+    fn window_reached(&self) -> bool {
+        self.lcdc & 0x21 == 0x21 &&
+        self.ly >= self.wy &&
+        self.wx <= 166 &&
+        self.fifo.x as u16 + 7 >= self.wx as u16
+    }
+
+    // Hitting the window clears the background FIFO and restarts the fetcher
+    fn start_window(&mut self) {
+        let fifo = &mut self.fifo;
+
+        fifo.window = true;
+        fifo.bg.clear();
+        fifo.step = Tile;
+        fifo.step_dots = 0;
+        fifo.fetcher_x = 0;
+
+        if self.wx < 7 {
+            fifo.discard = 7 - self.wx;
+        }
+    }
+
+    fn next_sprite(&self) -> Option<usize> {
+        if self.lcdc & 0x02 == 0 || self.fifo.discard > 0 {
+            return None;
+        }
+
+        let x = self.fifo.x as u16 + 8;
+
+        self.sprites.iter()
+            .enumerate()
+            .position(|(index, sprite)| !self.fifo.fetched_sprites[index] && sprite.x as u16 <= x)
+    }
+
+    fn fetch_sprite(&mut self, index: usize) {
+        let sprite = self.sprites[index];
+        let height = self.sprite_height();
+
+        // OBJ size may have changed since the OAM scan, so the row wraps at the current height
+        let mut row = (self.ly + 16 - sprite.y) & (height - 1);
+        if sprite.flags & 0x40 != 0 {
+            row = height - 1 - row;
+        }
+        let tile = if height == 16 { sprite.tile & 0xFE } else { sprite.tile };
+
+        // Sprites partially off the left edge skip their first columns
+        let skip = (self.fifo.x as u16 + 8 - sprite.x as u16) as u8;
+
+        for col in skip .. 8 {
+            let tile_col = if sprite.flags & 0x20 != 0 { 7 - col } else { col };
+            let pixel = SpritePixel {
+                color: self.tile_pixel(tile as usize * 16, row, tile_col),
+                obp1: sprite.flags & 0x10 != 0,
+                behind_bg: sprite.flags & 0x80 != 0,
+            };
+
+            // Pixels of sprites already in the FIFO take priority
+            let slot = (col - skip) as usize;
+            if slot < self.fifo.sprites.len() {
+                if self.fifo.sprites[slot].color == 0 {
+                    self.fifo.sprites[slot] = pixel;
+                }
+            } else {
+                self.fifo.sprites.push_back(pixel);
+            }
+        }
+    }
+
+    // Tile, DataLow and DataHigh take 2 dots each,
+    // Push is retried every dot until the FIFO is empty
+    fn fetcher_dot(&mut self) {
+        if self.fifo.step == Push {
+            if self.fifo.bg.is_empty() {
+                for col in 0 .. 8 {
+                    let bit = 7 - col;
+                    let color = (self.fifo.high >> bit & 1) << 1 | self.fifo.low >> bit & 1;
+                    self.fifo.bg.push_back(color);
+                }
+                self.fifo.fetcher_x = self.fifo.fetcher_x.wrapping_add(1);
+                self.fifo.step = Tile;
+            }
+            return;
+        }
+
+        self.fifo.step_dots += 1;
+        if self.fifo.step_dots < 2 {
+            return;
+        }
+        self.fifo.step_dots = 0;
+
+        let (map, x, y) = if self.fifo.window {
+            let map = if self.lcdc & 0x40 != 0 { 0x1C00 } else { 0x1800 };
+            (map, self.fifo.fetcher_x, self.window_line)
+        } else {
+            let map = if self.lcdc & 0x08 != 0 { 0x1C00 } else { 0x1800 };
+            let x = (self.scx / 8).wrapping_add(self.fifo.fetcher_x) % 32;
+            (map, x, self.ly.wrapping_add(self.scy))
+        };
+
+        self.fifo.step = match self.fifo.step {
+            Tile => {
+                self.fifo.tile = self.vram[map + (y as usize / 8) * 32 + x as usize % 32];
+                DataLow
+            },
+            DataLow => {
+                let addr = self.tile_data_addr(self.fifo.tile) + (y as usize % 8) * 2;
+                self.fifo.low = self.vram[addr];
+                DataHigh
+            },
+            DataHigh => {
+                let addr = self.tile_data_addr(self.fifo.tile) + (y as usize % 8) * 2;
+                self.fifo.high = self.vram[addr + 1];
+                Push
+            },
+            Push => unreachable!(),
+        };
+    }
+}
@@ -0,0 +1,428 @@
+use interrupts::{Interrupts, Interrupt};
+use self::Mode::*;
+use self::fifo::Fifo;
+pub use self::oam_bug::OamCorruption;
+mod scanline;
+mod fifo;
+mod oam_bug;
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+const DOTS_PER_LINE: u16 = 456;
+const OAM_SCAN_DOTS: u16 = 80;
+const LCD_ON_DOT: u16 = 4;
+const VBLANK_LINE: u8 = 144;
+const LINES_PER_FRAME: u8 = 154;
+const SPRITES_PER_LINE: usize = 10;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Mode {
+    HBlank  = 0,
+    VBlank  = 1,
+    OamScan = 2,
+    Drawing = 3,
+}
+
+/// How mode 3 is emulated.
+///
+/// `Scanline` draws each line in one go at a fixed mode 3 length.
+/// `Fifo` emulates the pixel FIFO dot by dot, so mid-scanline register
+/// changes take effect and mode 3 length varies with SCX, the window and sprites.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Renderer {
+    Scanline,
+    Fifo,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Sprite {
+    y: u8,
+    x: u8,
+    tile: u8,
+    flags: u8,
+}
+
+/// The DMG picture processing unit.
+///
+/// Owns VRAM, OAM and the LCD registers (0xFF40 - 0xFF4B except DMA)
+/// and produces one 160x144 buffer of shades (0 = white, 3 = black) per frame.
+pub struct Ppu {
+    vram: [u8; 0x2000],
+    oam: [u8; 0xA0],
+    lcdc: u8,
+    stat: u8,
+    scy: u8,
+    scx: u8,
+    ly: u8,
+    lyc: u8,
+    bgp: u8,
+    obp0: u8,
+    obp1: u8,
+    wy: u8,
+    wx: u8,
+    mode: Mode,
+    renderer: Renderer,
+    fifo: Fifo,
+    dot: u16,
+    window_line: u8,
+    stat_line: bool,
+    sprites: Vec<Sprite>,
+    back_buffer: Vec<u8>,
+    frame: Vec<u8>,
+    frame_count: u64,
+}
+
+impl Ppu {
+    pub fn new() -> Ppu {
+        Ppu {
+            vram: [0; 0x2000],
+            oam: [0; 0xA0],
+            lcdc: 0x91,
+            stat: 0x00,
+            scy: 0,
+            scx: 0,
+            ly: 0,
+            lyc: 0,
+            bgp: 0xFC,
+            obp0: 0xFF,
+            obp1: 0xFF,
+            wy: 0,
+            wx: 0,
+            mode: OamScan,
+            renderer: Renderer::Scanline,
+            fifo: Fifo::new(),
+            dot: 0,
+            window_line: 0,
+            stat_line: false,
+            sprites: Vec::with_capacity(SPRITES_PER_LINE),
+            back_buffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            frame: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            frame_count: 0,
+        }
+    }
+
+    /// The last completed frame, one shade (0 - 3) per pixel in row major order.
+    pub fn frame(&self) -> &[u8] {
+        &self.frame
+    }
+
+    /// Number of frames completed since power on.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    pub fn set_renderer(&mut self, renderer: Renderer) {
+        self.renderer = renderer;
+    }
+
+    fn lcd_enabled(&self) -> bool {
+        self.lcdc & 0x80 != 0
+    }
+
+    pub fn tick(&mut self, dots: u16, interrupts: &mut Interrupts) {
+        if !self.lcd_enabled() {
+            return;
+        }
+
+        for _ in 0 .. dots {
+            self.dot += 1;
+
+            match self.mode {
+                OamScan if self.dot == OAM_SCAN_DOTS => {
+                    self.scan_oam();
+                    if self.renderer == Renderer::Fifo {
+                        self.fifo_start_line();
+                    }
+                    self.set_mode(Drawing, interrupts);
+                },
+                Drawing => match self.renderer {
+                    Renderer::Scanline => if self.dot == OAM_SCAN_DOTS + scanline::DRAWING_DOTS {
+                        self.render_scanline();
+                        self.set_mode(HBlank, interrupts);
+                    },
+                    Renderer::Fifo => if self.fifo_dot() {
+                        self.set_mode(HBlank, interrupts);
+                    },
+                },
+                _ if self.dot == DOTS_PER_LINE => self.next_line(interrupts),
+                _ => {},
+            }
+        }
+    }
+
+    fn next_line(&mut self, interrupts: &mut Interrupts) {
+        self.dot = 0;
+        self.ly += 1;
+
+        if self.ly == VBLANK_LINE {
+            ::std::mem::swap(&mut self.frame, &mut self.back_buffer);
+            self.frame_count += 1;
+            interrupts.request(Interrupt::VBlank);
+            self.set_mode(VBlank, interrupts);
+        } else if self.ly == LINES_PER_FRAME {
+            self.ly = 0;
+            self.window_line = 0;
+            self.set_mode(OamScan, interrupts);
+        } else if self.ly < VBLANK_LINE {
+            self.set_mode(OamScan, interrupts);
+        } else {
+            self.update_stat_interrupt(interrupts);
+        }
+    }
+
+    fn set_mode(&mut self, mode: Mode, interrupts: &mut Interrupts) {
+        self.mode = mode;
+        self.update_stat_interrupt(interrupts);
+    }
+
+    // The STAT interrupt is requested on the rising edge of
+    // all enabled STAT conditions ORed together
+    fn update_stat_interrupt(&mut self, interrupts: &mut Interrupts) {
+        let line = self.lcd_enabled() && (
+            self.stat & 0x40 != 0 && self.ly == self.lyc ||
+            self.stat & 0x20 != 0 && self.mode == OamScan ||
+            self.stat & 0x10 != 0 && self.mode == VBlank ||
+            self.stat & 0x08 != 0 && self.mode == HBlank
+        );
+
+        if line && !self.stat_line {
+            interrupts.request(Interrupt::LcdStat);
+        }
+
+        self.stat_line = line;
+    }
+
+    fn sprite_height(&self) -> u8 {
+        if self.lcdc & 0x04 != 0 { 16 } else { 8 }
+    }
+
+    // Selects the first 10 sprites in OAM order that overlap the current line
+    fn scan_oam(&mut self) {
+        let height = self.sprite_height();
+        let line = self.ly as u16 + 16;
+
+        self.sprites.clear();
+
+        for entry in self.oam.chunks(4) {
+            let y = entry[0] as u16;
+            if line >= y && line < y + height as u16 {
+                self.sprites.push(Sprite {
+                    y: entry[0],
+                    x: entry[1],
+                    tile: entry[2],
+                    flags: entry[3],
+                });
+
+                if self.sprites.len() == SPRITES_PER_LINE {
+                    break;
+                }
+            }
+        }
+    }
+
+    // The CPU can't access VRAM while it's being drawn from,
+    // nor OAM during OAM scan and drawing
+
+    fn vram_accessible(&self) -> bool {
+        !self.lcd_enabled() || self.mode != Drawing
+    }
+
+    fn oam_accessible(&self) -> bool {
+        !self.lcd_enabled() || self.mode == HBlank || self.mode == VBlank
+    }
+
+    pub fn read_vram(&self, offset: u16) -> u8 {
+        if self.vram_accessible() {
+            self.vram[offset as usize]
+        } else {
+            0xFF
+        }
+    }
+
+    pub fn write_vram(&mut self, offset: u16, value: u8) {
+        if self.vram_accessible() {
+            self.vram[offset as usize] = value;
+        }
+    }
+
+    pub fn read_oam(&self, offset: u16) -> u8 {
+        if self.oam_accessible() {
+            self.oam[offset as usize]
+        } else {
+            0xFF
+        }
+    }
+
+    pub fn write_oam(&mut self, offset: u16, value: u8) {
+        if self.oam_accessible() {
+            self.oam[offset as usize] = value;
+        }
+    }
+
+    // OAM DMA writes regardless of the PPU mode
+    pub fn write_oam_dma(&mut self, offset: u16, value: u8) {
+        self.oam[offset as usize] = value;
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0xFF40 => self.lcdc,
+            0xFF41 => {
+                let mode = if self.lcd_enabled() { self.mode as u8 } else { 0 };
+                let coincidence = (self.ly == self.lyc) as u8;
+                0x80 | self.stat | coincidence << 2 | mode
+            },
+            0xFF42 => self.scy,
+            0xFF43 => self.scx,
+            0xFF44 => self.ly,
+            0xFF45 => self.lyc,
+            0xFF47 => self.bgp,
+            0xFF48 => self.obp0,
+            0xFF49 => self.obp1,
+            0xFF4A => self.wy,
+            0xFF4B => self.wx,
+            _ => unreachable!()
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8, interrupts: &mut Interrupts) {
+        match addr {
+            0xFF40 => {
+                let was_enabled = self.lcd_enabled();
+                self.lcdc = value;
+
+                if was_enabled && !self.lcd_enabled() {
+                    self.ly = 0;
+                    self.dot = 0;
+                    self.window_line = 0;
+                    self.mode = HBlank;
+                } else if !was_enabled && self.lcd_enabled() {
+                    // The first line after turning the LCD on is 4 dots shorter
+                    self.dot = LCD_ON_DOT;
+                    self.mode = OamScan;
+                }
+            },
+            0xFF41 => self.stat = value & 0b01111000,
+            0xFF42 => self.scy = value,
+            0xFF43 => self.scx = value,
+            0xFF44 => {}, // LY is read only
+            0xFF45 => self.lyc = value,
+            0xFF47 => self.bgp = value,
+            0xFF48 => self.obp0 = value,
+            0xFF49 => self.obp1 = value,
+            0xFF4A => self.wy = value,
+            0xFF4B => self.wx = value,
+            _ => unreachable!()
+        }
+
+        self.update_stat_interrupt(interrupts);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs up to the next mode 3 and returns how many dots it lasts
+    fn drawing_dots(ppu: &mut Ppu) -> u16 {
+        let mut interrupts = Interrupts::new();
+
+        while ppu.mode != Drawing {
+            ppu.tick(1, &mut interrupts);
+        }
+
+        let mut dots = 0;
+        while ppu.mode == Drawing {
+            ppu.tick(1, &mut interrupts);
+            dots += 1;
+        }
+
+        dots
+    }
+
+    fn fifo_ppu() -> Ppu {
+        let mut ppu = Ppu::new();
+        ppu.set_renderer(Renderer::Fifo);
+        ppu
+    }
+
+    #[test]
+    fn fifo_mode3_grows_with_scx() {
+        let base = drawing_dots(&mut fifo_ppu());
+        assert_eq!(base, 172);
+
+        for scx in 1 .. 16 {
+            let mut ppu = fifo_ppu();
+            ppu.scx = scx;
+            assert_eq!(drawing_dots(&mut ppu), base + (scx % 8) as u16, "SCX = {}", scx);
+        }
+    }
+
+    #[test]
+    fn fifo_mode3_grows_with_sprites() {
+        let base = drawing_dots(&mut fifo_ppu());
+        let mut previous = base;
+
+        for count in 1 .. 4u8 {
+            let mut ppu = fifo_ppu();
+            ppu.lcdc |= 0x02;
+
+            // Sprites on line 0, 16 pixels apart
+            for index in 0 .. count {
+                let entry = index as u16 * 4;
+                ppu.write_oam_dma(entry, 16);
+                ppu.write_oam_dma(entry + 1, 8 + index * 16);
+            }
+
+            let dots = drawing_dots(&mut ppu);
+            assert!(dots >= previous + 6 && dots <= previous + 11, "{} sprites took {} dots", count, dots);
+            previous = dots;
+        }
+    }
+
+    // Draws line 8 with a Y flipped 8x16 sprite at the top left, switching to 8x8
+    // sprites after the OAM scan, and returns the sprite's leftmost pixel
+    fn draw_shrunk_flipped_sprite(mut ppu: Ppu) -> u8 {
+        let mut interrupts = Interrupts::new();
+        ppu.lcdc |= 0x06;
+        ppu.write_oam_dma(0, 16);
+        ppu.write_oam_dma(1, 8);
+        ppu.write_oam_dma(3, 0x40);
+        // Only the last row of tile 0 is drawn in color 3
+        ppu.vram[14] = 0xFF;
+        ppu.vram[15] = 0xFF;
+
+        while !(ppu.ly == 8 && ppu.mode == Drawing) {
+            ppu.tick(1, &mut interrupts);
+        }
+        ppu.lcdc &= !0x04;
+        while ppu.mode == Drawing {
+            ppu.tick(1, &mut interrupts);
+        }
+
+        ppu.back_buffer[8 * SCREEN_WIDTH]
+    }
+
+    #[test]
+    fn scanline_sprite_row_wraps_at_new_height() {
+        // Row 8 wraps to row 0, flipped to row 7
+        assert_eq!(draw_shrunk_flipped_sprite(Ppu::new()), 3);
+    }
+
+    #[test]
+    fn fifo_sprite_row_wraps_at_new_height() {
+        assert_eq!(draw_shrunk_flipped_sprite(fifo_ppu()), 3);
+    }
+
+    #[test]
+    fn scanline_mode3_is_fixed() {
+        let mut ppu = Ppu::new();
+        ppu.scx = 5;
+        ppu.lcdc |= 0x02;
+        ppu.write_oam_dma(0, 16);
+        ppu.write_oam_dma(1, 8);
+
+        assert_eq!(drawing_dots(&mut ppu), scanline::DRAWING_DOTS);
+    }
+}
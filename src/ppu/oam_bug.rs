@@ -0,0 +1,177 @@
+use super::{Ppu, Mode, OAM_SCAN_DOTS};
+
+const OAM_ROWS: usize = 20;
+
+/// The kinds of CPU bus activity that corrupt OAM on DMG
+/// when they target 0xFE00 - 0xFEFF during OAM scan.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum OamCorruption {
+    // Writes and 16 bit increments/decrements
+    Write,
+    Read,
+    // A read with the address being incremented/decremented in the same cycle
+    ReadIncDec,
+}
+
+impl Ppu {
+    /// Corrupts the OAM row the PPU is currently scanning, if any.
+    pub fn corrupt_oam(&mut self, corruption: OamCorruption) {
+        if !self.lcd_enabled() || self.mode != Mode::OamScan || self.dot >= OAM_SCAN_DOTS {
+            return;
+        }
+
+        // OAM is scanned in rows of 8 bytes, one row per M-cycle.
+        // The first row is never affected.
+        let row = self.dot as usize / 4;
+        if row == 0 || row >= OAM_ROWS {
+            return;
+        }
+
+        match corruption {
+            OamCorruption::Write => self.corrupt_oam_write(row),
+            OamCorruption::Read => self.corrupt_oam_read(row),
+            OamCorruption::ReadIncDec => {
+                if (4 .. OAM_ROWS - 1).contains(&row) {
+                    let a = self.oam_word(row - 2, 0);
+                    let b = self.oam_word(row - 1, 0);
+                    let c = self.oam_word(row, 0);
+                    let d = self.oam_word(row - 1, 2);
+                    self.set_oam_word(row - 1, 0, (b & (a | c | d)) | (a & c & d));
+
+                    self.copy_oam_row(row - 1, row - 2);
+                    self.copy_oam_row(row - 1, row);
+                }
+
+                self.corrupt_oam_read(row);
+            },
+        }
+    }
+
+    fn corrupt_oam_write(&mut self, row: usize) {
+        let a = self.oam_word(row, 0);
+        let b = self.oam_word(row - 1, 0);
+        let c = self.oam_word(row - 1, 2);
+        self.set_oam_word(row, 0, ((a ^ c) & (b ^ c)) ^ c);
+        self.copy_oam_row_tail(row - 1, row);
+    }
+
+    fn corrupt_oam_read(&mut self, row: usize) {
+        let a = self.oam_word(row, 0);
+        let b = self.oam_word(row - 1, 0);
+        let c = self.oam_word(row - 1, 2);
+        self.set_oam_word(row, 0, b | (a & c));
+        self.copy_oam_row_tail(row - 1, row);
+    }
+
+    fn oam_word(&self, row: usize, word: usize) -> u16 {
+        let index = row * 8 + word * 2;
+        self.oam[index] as u16 | (self.oam[index + 1] as u16) << 8
+    }
+
+    fn set_oam_word(&mut self, row: usize, word: usize, value: u16) {
+        let index = row * 8 + word * 2;
+        self.oam[index] = value as u8;
+        self.oam[index + 1] = (value >> 8) as u8;
+    }
+
+    fn copy_oam_row(&mut self, from: usize, to: usize) {
+        for i in 0 .. 8 {
+            self.oam[to * 8 + i] = self.oam[from * 8 + i];
+        }
+    }
+
+    // Copies all but the first word
+    fn copy_oam_row_tail(&mut self, from: usize, to: usize) {
+        for i in 2 .. 8 {
+            self.oam[to * 8 + i] = self.oam[from * 8 + i];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A PPU in the OAM scan M-cycle of the given row, with OAM holding its offsets
+    // except for a few first and third words picked to tell the patterns apart
+    fn scanning_ppu(row: usize) -> Ppu {
+        let mut ppu = Ppu::new();
+        for (i, byte) in ppu.oam.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+
+        ppu.set_oam_word(3, 0, 0x0FF0);
+        ppu.set_oam_word(4, 0, 0xCCCC);
+        ppu.set_oam_word(4, 2, 0xAAAA);
+        ppu.set_oam_word(5, 0, 0xF0F0);
+        ppu.dot = row as u16 * 4;
+        ppu
+    }
+
+    fn row(ppu: &Ppu, row: usize) -> &[u8] {
+        &ppu.oam[row * 8 .. row * 8 + 8]
+    }
+
+    #[test]
+    fn write_corrupts_the_current_row() {
+        let mut ppu = scanning_ppu(5);
+        ppu.corrupt_oam(OamCorruption::Write);
+
+        assert_eq!(ppu.oam_word(5, 0), 0xE8E8);
+        assert_eq!(row(&ppu, 5)[2 ..], row(&ppu, 4)[2 ..]);
+        assert_eq!(ppu.oam_word(4, 0), 0xCCCC);
+    }
+
+    #[test]
+    fn read_corrupts_the_current_row() {
+        let mut ppu = scanning_ppu(5);
+        ppu.corrupt_oam(OamCorruption::Read);
+
+        assert_eq!(ppu.oam_word(5, 0), 0xECEC);
+        assert_eq!(row(&ppu, 5)[2 ..], row(&ppu, 4)[2 ..]);
+        assert_eq!(ppu.oam_word(4, 0), 0xCCCC);
+    }
+
+    #[test]
+    fn read_inc_dec_corrupts_the_preceding_rows() {
+        let mut ppu = scanning_ppu(5);
+        ppu.corrupt_oam(OamCorruption::ReadIncDec);
+
+        assert_eq!(ppu.oam_word(4, 0), 0xCCE8);
+        assert_eq!(row(&ppu, 3), row(&ppu, 4));
+        assert_eq!(row(&ppu, 5), row(&ppu, 4));
+        assert_eq!(row(&ppu, 2), &[16, 17, 18, 19, 20, 21, 22, 23]);
+    }
+
+    #[test]
+    fn read_inc_dec_only_reads_in_the_first_rows() {
+        let mut ppu = scanning_ppu(3);
+        let expected = scanning_ppu(3);
+        ppu.corrupt_oam(OamCorruption::ReadIncDec);
+
+        // Row 2 starts with 0x1110 and its third word is 0x1514
+        assert_eq!(ppu.oam_word(3, 0), 0x1510);
+        assert_eq!(row(&ppu, 3)[2 ..], row(&expected, 2)[2 ..]);
+        assert_eq!(row(&ppu, 1), row(&expected, 1));
+        assert_eq!(row(&ppu, 2), row(&expected, 2));
+    }
+
+    #[test]
+    fn only_rows_being_scanned_are_corrupted() {
+        let expected = scanning_ppu(0);
+
+        let mut ppu = scanning_ppu(0);
+        ppu.corrupt_oam(OamCorruption::Write);
+        assert_eq!(&ppu.oam[..], &expected.oam[..]);
+
+        let mut ppu = scanning_ppu(5);
+        ppu.mode = Mode::HBlank;
+        ppu.corrupt_oam(OamCorruption::Write);
+        assert_eq!(&ppu.oam[..], &expected.oam[..]);
+
+        let mut ppu = scanning_ppu(5);
+        ppu.lcdc &= !0x80;
+        ppu.corrupt_oam(OamCorruption::Write);
+        assert_eq!(&ppu.oam[..], &expected.oam[..]);
+    }
+}
@@ -0,0 +1,231 @@
+use super::{Ppu, SCREEN_WIDTH};
+
+// The scanline renderer draws a whole line at once,
+// so mode 3 always takes its minimum length
+pub const DRAWING_DOTS: u16 = 172;
+
+impl Ppu {
+    pub(super) fn render_scanline(&mut self) {
+        let ly = self.ly;
+        let mut bg_colors = [0u8; SCREEN_WIDTH];
+
+        let bg_enabled = self.lcdc & 0x01 != 0;
+        let window_visible = bg_enabled && self.lcdc & 0x20 != 0 && ly >= self.wy && self.wx <= 166;
+
+        if bg_enabled {
+            for x in 0 .. SCREEN_WIDTH as u8 {
+                let color = if window_visible && x as u16 + 7 >= self.wx as u16 {
+                    let map = if self.lcdc & 0x40 != 0 { 0x1C00 } else { 0x1800 };
+                    self.tile_map_pixel(map, x + 7 - self.wx, self.window_line)
+                } else {
+                    let map = if self.lcdc & 0x08 != 0 { 0x1C00 } else { 0x1800 };
+                    self.tile_map_pixel(map, x.wrapping_add(self.scx), ly.wrapping_add(self.scy))
+                };
+
+                bg_colors[x as usize] = color;
+            }
+        }
+
+        if window_visible {
+            self.window_line += 1;
+        }
+
+        let line_start = ly as usize * SCREEN_WIDTH;
+        for (x, &color) in bg_colors.iter().enumerate() {
+            self.back_buffer[line_start + x] = shade(self.bgp, color);
+        }
+
+        if self.lcdc & 0x02 != 0 {
+            self.render_sprites(&bg_colors);
+        }
+    }
+
+    fn render_sprites(&mut self, bg_colors: &[u8; SCREEN_WIDTH]) {
+        let ly = self.ly;
+        let height = self.sprite_height();
+
+        // On DMG the sprite with the smaller X wins, ties are won by OAM order
+        let mut sprites = self.sprites.clone();
+        sprites.sort_by_key(|sprite| sprite.x);
+
+        for (x, &bg_color) in bg_colors.iter().enumerate() {
+            let screen_x = x as i16 + 8;
+
+            for sprite in &sprites {
+                let sprite_x = sprite.x as i16;
+                if screen_x < sprite_x || screen_x >= sprite_x + 8 {
+                    continue;
+                }
+
+                // OBJ size may have changed since the OAM scan, so the row wraps at the current height
+                let mut row = (ly + 16 - sprite.y) & (height - 1);
+                if sprite.flags & 0x40 != 0 {
+                    row = height - 1 - row;
+                }
+                let mut col = (screen_x - sprite_x) as u8;
+                if sprite.flags & 0x20 != 0 {
+                    col = 7 - col;
+                }
+
+                let tile = if height == 16 { sprite.tile & 0xFE } else { sprite.tile };
+                let color = self.tile_pixel(tile as usize * 16, row, col);
+
+                if color == 0 {
+                    continue;
+                }
+
+                let behind_bg = sprite.flags & 0x80 != 0;
+                if !(behind_bg && bg_color != 0) {
+                    let palette = if sprite.flags & 0x10 != 0 { self.obp1 } else { self.obp0 };
+                    self.back_buffer[ly as usize * SCREEN_WIDTH + x] = shade(palette, color);
+                }
+
+                break;
+            }
+        }
+    }
+
+    fn tile_map_pixel(&self, map: usize, x: u8, y: u8) -> u8 {
+        let tile = self.vram[map + (y as usize / 8) * 32 + x as usize / 8];
+        self.tile_pixel(self.tile_data_addr(tile), y % 8, x % 8)
+    }
+
+    pub(super) fn tile_data_addr(&self, tile: u8) -> usize {
+        if self.lcdc & 0x10 != 0 {
+            tile as usize * 16
+        } else {
+            (0x1000 + tile as i8 as isize * 16) as usize
+        }
+    }
+
+    pub(super) fn tile_pixel(&self, tile_addr: usize, row: u8, col: u8) -> u8 {
+        let low  = self.vram[tile_addr + row as usize * 2];
+        let high = self.vram[tile_addr + row as usize * 2 + 1];
+        let bit = 7 - col;
+
+        (high >> bit & 1) << 1 | low >> bit & 1
+    }
+}
+
+pub fn shade(palette: u8, color: u8) -> u8 {
+    palette >> (color * 2) & 0b11
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tiles 1 - 3 are filled with colors 1 - 3, palettes map colors to the same shades
+    fn ppu() -> Ppu {
+        let mut ppu = Ppu::new();
+        ppu.bgp = 0xE4;
+        ppu.obp0 = 0xE4;
+
+        for tile in 1 .. 4 {
+            for row in 0 .. 8 {
+                let addr = tile * 16 + row * 2;
+                ppu.vram[addr] = if tile & 1 != 0 { 0xFF } else { 0x00 };
+                ppu.vram[addr + 1] = if tile & 2 != 0 { 0xFF } else { 0x00 };
+            }
+        }
+
+        ppu
+    }
+
+    fn set_sprite(ppu: &mut Ppu, index: usize, y: u8, x: u8, tile: u8, flags: u8) {
+        ppu.oam[index * 4 .. index * 4 + 4].copy_from_slice(&[y, x, tile, flags]);
+    }
+
+    fn render_line(ppu: &mut Ppu, ly: u8) -> &[u8] {
+        ppu.ly = ly;
+        ppu.scan_oam();
+        ppu.render_scanline();
+
+        let start = ly as usize * SCREEN_WIDTH;
+        &ppu.back_buffer[start .. start + SCREEN_WIDTH]
+    }
+
+    #[test]
+    fn background_scrolls() {
+        let mut ppu = ppu();
+        // Tile 1 covers BG pixels 16 - 23 of rows 8 - 15
+        ppu.vram[0x1800 + 32 + 2] = 1;
+        ppu.scx = 3;
+        ppu.scy = 5;
+
+        let line = render_line(&mut ppu, 3);
+        assert_eq!(line[12 .. 22], [0, 1, 1, 1, 1, 1, 1, 1, 1, 0]);
+    }
+
+    #[test]
+    fn background_wraps_around() {
+        let mut ppu = ppu();
+        ppu.vram[0x1800] = 1;
+        ppu.scx = 252;
+        ppu.scy = 254;
+
+        assert_eq!(render_line(&mut ppu, 1)[4], 0);
+        assert_eq!(render_line(&mut ppu, 2)[3 .. 13], [0, 1, 1, 1, 1, 1, 1, 1, 1, 0]);
+    }
+
+    #[test]
+    fn window_starts_at_wx_and_wy_and_ignores_scrolling() {
+        let mut ppu = ppu();
+        ppu.lcdc |= 0x60;
+        for entry in 0 .. 32 {
+            ppu.vram[0x1C00 + entry] = 2;
+        }
+        ppu.wx = 47;
+        ppu.wy = 10;
+        ppu.scx = 3;
+        ppu.scy = 5;
+
+        assert_eq!(render_line(&mut ppu, 9)[40], 0);
+        assert_eq!(ppu.window_line, 0);
+
+        let line = render_line(&mut ppu, 10);
+        assert_eq!(line[39 .. 41], [0, 2]);
+        assert_eq!(ppu.window_line, 1);
+    }
+
+    #[test]
+    fn sprite_with_smaller_x_wins() {
+        let mut ppu = ppu();
+        ppu.lcdc |= 0x02;
+        set_sprite(&mut ppu, 0, 16, 12, 1, 0x00);
+        set_sprite(&mut ppu, 1, 16, 10, 2, 0x00);
+        // Same X, earlier in OAM wins
+        set_sprite(&mut ppu, 2, 16, 100, 3, 0x00);
+        set_sprite(&mut ppu, 3, 16, 100, 1, 0x00);
+
+        let line = render_line(&mut ppu, 0);
+        assert_eq!(line[2 .. 12], [2, 2, 2, 2, 2, 2, 2, 2, 1, 1]);
+        assert_eq!(line[92], 3);
+    }
+
+    #[test]
+    fn sprite_behind_background_only_covers_color_0() {
+        let mut ppu = ppu();
+        ppu.lcdc |= 0x02;
+        ppu.vram[0x1800] = 1;
+        set_sprite(&mut ppu, 0, 16, 12, 3, 0x80);
+
+        let line = render_line(&mut ppu, 0);
+        assert_eq!(line[6 .. 10], [1, 1, 3, 3]);
+    }
+
+    #[test]
+    fn ten_sprites_per_line() {
+        let mut ppu = ppu();
+        ppu.lcdc |= 0x02;
+        // Sprites on other lines don't count
+        set_sprite(&mut ppu, 0, 100, 8, 1, 0x00);
+        for index in 1 .. 12 {
+            set_sprite(&mut ppu, index, 16, index as u8 * 8, 1, 0x00);
+        }
+
+        let line = render_line(&mut ppu, 0);
+        assert!(line[.. 80].iter().all(|&shade| shade == 1));
+        assert!(line[80 ..].iter().all(|&shade| shade == 0));
+    }
+}
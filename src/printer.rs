@@ -0,0 +1,397 @@
+use std::path::PathBuf;
+use std::io::{self, BufWriter};
+use std::fs::File;
+use serial::{SerialDevice, Port};
+use png;
+use self::State::*;
+
+const MAGIC: [u8; 2] = [0x88, 0x33];
+const DEVICE_ID: u8 = 0x81;
+const WIDTH: usize = 160;
+const TILES_PER_ROW: usize = WIDTH / 8;
+const TILE_SIZE: usize = 16;
+// Up to 9 bands of 16 lines fit into the printer's buffer
+const BUFFER_SIZE: usize = 0x2000;
+// How long printing a band takes
+const PRINT_CYCLES: u32 = 4194304 / 4;
+
+const CMD_INIT: u8 = 0x01;
+const CMD_PRINT: u8 = 0x02;
+const CMD_DATA: u8 = 0x04;
+const CMD_STATUS: u8 = 0x0F;
+
+const STATUS_CHECKSUM_ERROR: u8 = 0x01;
+const STATUS_PRINTING: u8 = 0x02;
+const STATUS_FULL: u8 = 0x04;
+const STATUS_UNPROCESSED: u8 = 0x08;
+const STATUS_PACKET_ERROR: u8 = 0x10;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum State {
+    Magic(usize),
+    Command,
+    Compression,
+    Length(usize),
+    Data,
+    Checksum(usize),
+    Alive,
+    Status,
+}
+
+/// The Game Boy Printer.
+///
+/// Receives packets of the form `88 33 command compression length(2) data checksum(2)`
+/// followed by two bytes during which it answers with its ID and status.
+/// Image data arrives as 2bpp tiles, 20 per row, optionally run length encoded.
+/// Printed bands are joined until one is printed with a bottom margin,
+/// which completes the printout and saves it as a PNG in the output directory.
+pub struct Printer {
+    dir: PathBuf,
+    state: State,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+    status: u8,
+    buffer: Vec<u8>,
+    printout: Vec<u8>,
+    printouts: u32,
+    busy_cycles: u32,
+}
+
+impl Printer {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Printer {
+        Printer {
+            dir: dir.into(),
+            state: Magic(0),
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            checksum: 0,
+            received_checksum: 0,
+            status: 0,
+            buffer: Vec::with_capacity(BUFFER_SIZE),
+            printout: Vec::new(),
+            printouts: 0,
+            busy_cycles: 0,
+        }
+    }
+
+    // Takes the next packet byte, returns the next state
+    fn receive(&mut self, byte: u8) -> State {
+        match self.state {
+            Magic(index) => if byte != MAGIC[index] {
+                Magic(0)
+            } else if index + 1 < MAGIC.len() {
+                Magic(index + 1)
+            } else {
+                self.checksum = 0;
+                self.data.clear();
+                Command
+            },
+            Command => {
+                self.command = byte;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                Compression
+            },
+            Compression => {
+                self.compressed = byte & 0x01 != 0;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                Length(0)
+            },
+            Length(index) => {
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                if index == 0 {
+                    self.length = byte as u16;
+                    Length(1)
+                } else {
+                    self.length |= (byte as u16) << 8;
+                    if self.length == 0 { Checksum(0) } else { Data }
+                }
+            },
+            Data => {
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                self.data.push(byte);
+                if self.data.len() == self.length as usize { Checksum(0) } else { Data }
+            },
+            Checksum(index) => if index == 0 {
+                self.received_checksum = byte as u16;
+                Checksum(1)
+            } else {
+                self.received_checksum |= (byte as u16) << 8;
+                Alive
+            },
+            Alive => Status,
+            Status => Magic(0),
+        }
+    }
+
+    fn handle_packet(&mut self) {
+        if self.received_checksum != self.checksum {
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !STATUS_CHECKSUM_ERROR;
+
+        match self.command {
+            CMD_INIT => {
+                self.buffer.clear();
+                self.status = 0;
+            },
+            CMD_DATA => {
+                let data = if self.compressed { decompress(&self.data) } else { self.data.clone() };
+                let space = BUFFER_SIZE - self.buffer.len();
+                self.buffer.extend(data.into_iter().take(space));
+
+                if !self.buffer.is_empty() {
+                    self.status |= STATUS_UNPROCESSED;
+                }
+                if self.buffer.len() == BUFFER_SIZE {
+                    self.status |= STATUS_FULL;
+                }
+            },
+            CMD_PRINT => if self.data.len() == 4 {
+                let margins = self.data[1];
+                let palette = self.data[2];
+                self.print(palette);
+
+                if margins & 0x0F != 0 {
+                    if let Err(err) = self.save_printout() {
+                        println!("Printer: failed to save printout: {}", err);
+                    }
+                }
+            } else {
+                self.status |= STATUS_PACKET_ERROR;
+            },
+            CMD_STATUS => {},
+            _ => self.status |= STATUS_PACKET_ERROR,
+        }
+    }
+
+    // Appends the buffered tiles to the printout
+    fn print(&mut self, palette: u8) {
+        // A palette of 0 is treated like the default palette
+        let palette = if palette == 0 { 0xE4 } else { palette };
+        let rows = self.buffer.len() / (TILES_PER_ROW * TILE_SIZE);
+
+        for row in 0 .. rows * 8 {
+            for x in 0 .. WIDTH {
+                let tile = (row / 8) * TILES_PER_ROW + x / 8;
+                let addr = tile * TILE_SIZE + (row % 8) * 2;
+                let bit = 7 - x % 8;
+                let color = (self.buffer[addr + 1] >> bit & 1) << 1 | self.buffer[addr] >> bit & 1;
+                let shade = palette >> (color * 2) & 0x03;
+
+                self.printout.push(255 - shade * 85);
+            }
+        }
+
+        self.buffer.clear();
+        self.status &= !(STATUS_UNPROCESSED | STATUS_FULL);
+        self.status |= STATUS_PRINTING;
+        self.busy_cycles = PRINT_CYCLES;
+    }
+
+    fn save_printout(&mut self) -> io::Result<()> {
+        if self.printout.is_empty() {
+            return Ok(());
+        }
+
+        self.printouts += 1;
+        let path = self.dir.join(format!("printout-{:03}.png", self.printouts));
+        let height = self.printout.len() / WIDTH;
+
+        let mut file = BufWriter::new(File::create(&path)?);
+        png::write_grayscale(&mut file, WIDTH as u32, height as u32, &self.printout)?;
+        self.printout.clear();
+
+        println!("Printer: saved {}", path.display());
+        Ok(())
+    }
+}
+
+// Runs start with a control byte: with bit 7 set the next byte is repeated
+// (control & 0x7F) + 2 times, otherwise (control + 1) literal bytes follow
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut bytes = data.iter().cloned();
+
+    while let Some(control) = bytes.next() {
+        if control & 0x80 != 0 {
+            let byte = bytes.next().unwrap_or(0);
+            for _ in 0 .. (control & 0x7F) as usize + 2 {
+                out.push(byte);
+            }
+        } else {
+            out.extend(bytes.by_ref().take(control as usize + 1));
+        }
+    }
+
+    out
+}
+
+impl SerialDevice for Printer {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        let response = match self.state {
+            Alive => DEVICE_ID,
+            Status => self.status,
+            _ => 0x00,
+        };
+
+        self.state = self.receive(byte);
+
+        if self.state == Alive {
+            self.handle_packet();
+        }
+
+        response
+    }
+
+    fn tick(&mut self, cycles: u16, _port: Port) {
+        if self.busy_cycles > 0 {
+            self.busy_cycles = self.busy_cycles.saturating_sub(cycles as u32);
+            if self.busy_cycles == 0 {
+                self.status &= !STATUS_PRINTING;
+            }
+        }
+    }
+}
+
+// Whatever is left is printed when the printer is unplugged
+impl Drop for Printer {
+    fn drop(&mut self) {
+        if let Err(err) = self.save_printout() {
+            println!("Printer: failed to save printout: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+
+    fn with_printer<F: FnOnce(&mut Printer, &Path)>(name: &str, f: F) {
+        let dir = env::temp_dir().join(format!("rust-gb-printer-{}", name));
+        fs::create_dir_all(&dir).unwrap();
+
+        {
+            let mut printer = Printer::new(&dir);
+            f(&mut printer, &dir);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn packet(command: u8, compression: u8, data: &[u8]) -> Vec<u8> {
+        let length = data.len() as u16;
+        let mut packet = vec![MAGIC[0], MAGIC[1], command, compression, length as u8, (length >> 8) as u8];
+        packet.extend_from_slice(data);
+
+        let checksum = packet[2..].iter().fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+        packet.extend_from_slice(&[checksum as u8, (checksum >> 8) as u8, 0x00, 0x00]);
+        packet
+    }
+
+    // Sends a packet and returns the alive and status bytes
+    fn send(printer: &mut Printer, packet: &[u8]) -> (u8, u8) {
+        let responses: Vec<u8> = packet.iter().map(|&byte| printer.exchange(byte)).collect();
+        let n = responses.len();
+        assert!(responses[..n - 2].iter().all(|&byte| byte == 0x00));
+        (responses[n - 2], responses[n - 1])
+    }
+
+    // A row of tiles, the first one with the colors 3 3 1 1 2 2 0 0 on each line
+    // and the others blank, run length encoded
+    fn compressed_row() -> Vec<u8> {
+        let mut data = vec![15];
+        for _ in 0 .. 8 {
+            data.extend_from_slice(&[0xF0, 0xCC]);
+        }
+        data.extend_from_slice(&[0xFF, 0x00, 0xFF, 0x00, 0x80 | 44, 0x00]);
+        data
+    }
+
+    fn print(printer: &mut Printer, margins: u8, palette: u8) -> (u8, u8) {
+        send(printer, &packet(CMD_PRINT, 0, &[0x01, margins, palette, 0x40]))
+    }
+
+    #[test]
+    fn prints_compressed_data() {
+        with_printer("print", |printer, _| {
+            assert_eq!(send(printer, &packet(CMD_INIT, 0, &[])), (DEVICE_ID, 0x00));
+            assert_eq!(send(printer, &packet(CMD_DATA, 1, &compressed_row())), (DEVICE_ID, STATUS_UNPROCESSED));
+            assert_eq!(printer.buffer.len(), TILES_PER_ROW * TILE_SIZE);
+
+            assert_eq!(print(printer, 0x00, 0xE4), (DEVICE_ID, STATUS_PRINTING));
+            assert_eq!(printer.printout.len(), WIDTH * 8);
+            for line in printer.printout.chunks(WIDTH) {
+                assert_eq!(&line[..10], &[0, 0, 170, 170, 85, 85, 255, 255, 255, 255]);
+            }
+
+            printer.tick(0xFFFF, Port { data: 0, external_ready: false });
+            assert_eq!(send(printer, &packet(CMD_STATUS, 0, &[])), (DEVICE_ID, STATUS_PRINTING));
+            for _ in 0 .. PRINT_CYCLES / 0xFFFF {
+                printer.tick(0xFFFF, Port { data: 0, external_ready: false });
+            }
+            assert_eq!(send(printer, &packet(CMD_STATUS, 0, &[])), (DEVICE_ID, 0x00));
+        });
+    }
+
+    #[test]
+    fn maps_colors_through_palette() {
+        with_printer("palette", |printer, _| {
+            send(printer, &packet(CMD_DATA, 1, &compressed_row()));
+            print(printer, 0x00, 0x1B);
+            assert_eq!(&printer.printout[..8], &[255, 255, 85, 85, 170, 170, 0, 0]);
+
+            // 0 is the same as the default palette
+            printer.printout.clear();
+            send(printer, &packet(CMD_DATA, 1, &compressed_row()));
+            print(printer, 0x00, 0x00);
+            assert_eq!(&printer.printout[..8], &[0, 0, 170, 170, 85, 85, 255, 255]);
+        });
+    }
+
+    #[test]
+    fn reports_checksum_errors() {
+        with_printer("checksum", |printer, _| {
+            let mut corrupted = packet(CMD_DATA, 1, &compressed_row());
+            let checksum_index = corrupted.len() - 4;
+            corrupted[checksum_index] ^= 0x01;
+
+            assert_eq!(send(printer, &corrupted), (DEVICE_ID, STATUS_CHECKSUM_ERROR));
+            assert!(printer.buffer.is_empty());
+
+            assert_eq!(send(printer, &packet(CMD_DATA, 1, &compressed_row())), (DEVICE_ID, STATUS_UNPROCESSED));
+        });
+    }
+
+    #[test]
+    fn bottom_margin_saves_printout() {
+        with_printer("save", |printer, dir| {
+            send(printer, &packet(CMD_DATA, 1, &compressed_row()));
+            print(printer, 0x00, 0xE4);
+            assert!(!dir.join("printout-001.png").exists());
+
+            send(printer, &packet(CMD_DATA, 1, &compressed_row()));
+            print(printer, 0x03, 0xE4);
+            let png = fs::read(dir.join("printout-001.png")).unwrap();
+            assert_eq!(&png[..4], &[0x89, b'P', b'N', b'G']);
+            assert!(printer.printout.is_empty());
+        });
+    }
+
+    #[test]
+    fn decompresses_runs() {
+        assert_eq!(decompress(&[0x81, 0xAA, 0x01, 0x12, 0x34]), vec![0xAA, 0xAA, 0xAA, 0x12, 0x34]);
+        assert_eq!(decompress(&[0x80, 0x55]), vec![0x55, 0x55]);
+        assert_eq!(decompress(&[0xFF, 0x00]).len(), 129);
+        assert_eq!(decompress(&[0x00, 0x77]), vec![0x77]);
+    }
+}
@@ -13,23 +13,46 @@ impl Rom {
             data: Vec::new()
         };
 
-        let mut file = try!(File::open(path));
+        let mut file = File::open(path)?;
 
-        try!(file.read_to_end(&mut rom.data));
+        file.read_to_end(&mut rom.data)?;
 
         Ok(rom)
     }
 
+    // MMM01 multicarts start up in their last 32 KiB, which hold the cart's header.
+    // Other ROMs may have anything there, so it has to pass the boot ROM's checks.
+    fn header(&self) -> &[u8] {
+        if self.data.len() > 0x8000 {
+            let menu = &self.data[self.data.len() - 0x8000 ..];
+            let is_mmm01 = matches!(Type::try_from(::header::typ(menu)),
+                Ok(Type::RomMmm01) |
+                Ok(Type::RomMmm01Sram) |
+                Ok(Type::RomMmm01SramBatt));
+
+            if is_mmm01 && ::header::is_valid(menu) {
+                return menu;
+            }
+        }
+
+        &self.data
+    }
+
     pub fn typ(&self) -> Type {
-        Type::try_from(::header::typ(&self.data)).unwrap_or(Type::UNKNOWN)
+        Type::try_from(::header::typ(self.header())).unwrap_or(Type::UNKNOWN)
+    }
+
+    /// Whether the ROM only runs on a CGB, rather than just supporting it.
+    pub fn requires_cgb(&self) -> bool {
+        ::header::cgb_flag(self.header()) & 0xC0 == 0xC0
     }
 
     pub fn rom_size(&self) -> RomSize {
-        RomSize::try_from(::header::rom_size(&self.data)).unwrap_or(RomSize::UNKNOWN)
+        RomSize::try_from(::header::rom_size(self.header())).unwrap_or(RomSize::UNKNOWN)
     }
 
     pub fn ram_size(&self) -> RamSize {
-        RamSize::try_from(::header::ram_size(&self.data)).unwrap_or(RamSize::UNKNOWN)
+        RamSize::try_from(::header::ram_size(self.header())).unwrap_or(RamSize::UNKNOWN)
     }
 }
 
@@ -38,7 +61,7 @@ custom_derive! {
     pub enum Type {
         Rom                   = 0x00,
         RomMbc1               = 0x01,
-        RomMbc1_Ram           = 0x02,
+        RomMbc1Ram            = 0x02,
         RomMbc1RamBatt        = 0x03,
         RomMbc2               = 0x05,
         RomMbc2Batt           = 0x06,
@@ -47,6 +70,9 @@ custom_derive! {
         RomMmm01              = 0x0B,
         RomMmm01Sram          = 0x0C,
         RomMmm01SramBatt      = 0x0D,
+        RomMbc3TimerBatt      = 0x0F,
+        RomMbc3TimerRamBatt   = 0x10,
+        RomMbc3               = 0x11,
         RomMbc3Ram            = 0x12,
         RomMbc3RamBatt        = 0x13,
         RomMbc5               = 0x19,
@@ -58,10 +84,30 @@ custom_derive! {
         PocketCamera          = 0x1F,
         BandaiTAMA5           = 0xFD,
         HudsonHuC3            = 0xFE,
+        HudsonHuC1            = 0xFF,
         UNKNOWN
     }
 }
 
+impl Type {
+    pub fn has_battery(&self) -> bool {
+        matches!(*self,
+            Type::RomMbc1RamBatt |
+            Type::RomMbc2Batt |
+            Type::RomRamBatt |
+            Type::RomMmm01SramBatt |
+            Type::RomMbc3TimerBatt |
+            Type::RomMbc3TimerRamBatt |
+            Type::RomMbc3RamBatt |
+            Type::RomMbc5RamBatt |
+            Type::RomMbc5RumbleSramBatt |
+            Type::PocketCamera |
+            Type::BandaiTAMA5 |
+            Type::HudsonHuC3 |
+            Type::HudsonHuC1)
+    }
+}
+
 custom_derive! {
     #[derive(TryFrom(u8),Debug, Eq, PartialEq, Ord, PartialOrd)]
     pub enum RomSize {
@@ -91,4 +137,67 @@ custom_derive! {
         Kb64  = 0x05,
         UNKNOWN
     }
-}
\ No newline at end of file
+}
+
+impl RamSize {
+    pub fn bytes(&self) -> usize {
+        match *self {
+            RamSize::None | RamSize::UNKNOWN => 0,
+            RamSize::Kb2   => 2 * 1024,
+            RamSize::Kb8   => 8 * 1024,
+            RamSize::Kb32  => 32 * 1024,
+            RamSize::Kb128 => 128 * 1024,
+            RamSize::Kb64  => 64 * 1024,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Rom {
+    /// A 32 KiB ROM without a mapper running `program` from the entry point.
+    pub fn with_program(program: &[u8]) -> Rom {
+        // The program mustn't reach into the header's cartridge type
+        assert!(program.len() <= 0x47);
+
+        let mut data = vec![0; 0x8000];
+        data[0x100 .. 0x100 + program.len()].copy_from_slice(program);
+        Rom { data: data }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use header::NINTENDO_LOGO;
+
+    fn write_header(header: &mut [u8], typ: u8) {
+        header[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+        header[0x147] = typ;
+        header[0x14D] = header[0x134..0x14D].iter().fold(0u8, |x, &byte| x.wrapping_sub(byte).wrapping_sub(1));
+    }
+
+    fn mmm01_rom() -> Rom {
+        let mut data = vec![0; 0x20000];
+        write_header(&mut data, 0x01);
+        write_header(&mut data[0x18000..], 0x0D);
+        Rom { data: data }
+    }
+
+    #[test]
+    fn mmm01_header_is_at_the_end() {
+        let rom = mmm01_rom();
+        assert!(::header::is_valid(&rom.data));
+        assert_eq!(rom.typ(), Type::RomMmm01SramBatt);
+    }
+
+    #[test]
+    fn mmm01_header_needs_logo_and_checksum() {
+        let mut rom = mmm01_rom();
+        rom.data[0x18000 + 0x14D] ^= 0x01;
+        assert_eq!(rom.typ(), Type::RomMbc1);
+
+        let mut rom = mmm01_rom();
+        rom.data[0x18000 + 0x110] ^= 0x01;
+        assert_eq!(rom.typ(), Type::RomMbc1);
+    }
+}
@@ -0,0 +1,326 @@
+use interrupts::{Interrupts, Interrupt};
+use memory::Model;
+
+// Cycles per bit at 8192 Hz and, on CGB, 262144 Hz
+const NORMAL_BIT_CYCLES: u16 = 512;
+const FAST_BIT_CYCLES: u16 = 16;
+
+/// Something plugged into the link port.
+pub trait SerialDevice {
+    /// Exchanges a byte while the Game Boy drives the clock.
+    /// Receives the byte being shifted out and returns the one shifted in.
+    fn exchange(&mut self, byte: u8) -> u8;
+
+    /// Polled while the Game Boy waits for an externally clocked transfer.
+    /// Returns the byte shifted in once the device clocked a transfer,
+    /// receiving the Game Boy's outgoing byte in exchange.
+    fn external_clock(&mut self, _byte: u8) -> Option<u8> {
+        None
+    }
+
+    /// Called as time passes, whether or not a transfer is in progress.
+    fn tick(&mut self, _cycles: u16, _port: Port) {}
+}
+
+/// The state of the Game Boy's side of the link port.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Port {
+    pub data: u8,
+    /// Whether a transfer waits for an external clock
+    pub external_ready: bool,
+}
+
+/// Prints bytes sent over serial as lines of text, like the test ROMs' output.
+pub struct SerialLogger {
+    line: String,
+}
+
+impl SerialLogger {
+    pub fn new() -> SerialLogger {
+        SerialLogger {
+            line: String::new(),
+        }
+    }
+}
+
+impl SerialDevice for SerialLogger {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        let ch = byte as char;
+        if ch == '\n' {
+            println!("SERIAL OUT: {}", self.line);
+            self.line.clear();
+        } else {
+            self.line.push(ch);
+        }
+
+        // Nothing is sent back, so the line stays high
+        0xFF
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Transfer {
+    Idle,
+    // Shifting with the internal clock, bits left and the byte being received
+    Internal { bits: u8, incoming: u8 },
+    External,
+}
+
+/// The serial port: SB (0xFF01) and SC (0xFF02).
+///
+/// With the internal clock a byte is shifted out MSB first at 8192 Hz,
+/// or 262144 Hz with the CGB fast clock, while the received byte is shifted in.
+/// With the external clock, the transfer waits for the attached device.
+/// Either way the serial interrupt is requested once all 8 bits are through.
+pub struct Serial {
+    model: Model,
+    data: u8,
+    control: u8,
+    transfer: Transfer,
+    timer: u16,
+    device: Option<Box<dyn SerialDevice>>,
+}
+
+impl Serial {
+    pub fn new(model: Model) -> Serial {
+        Serial {
+            model: model,
+            data: 0,
+            control: 0,
+            transfer: Transfer::Idle,
+            timer: 0,
+            device: None,
+        }
+    }
+
+    pub fn attach(&mut self, device: Box<dyn SerialDevice>) {
+        self.device = Some(device);
+    }
+
+    fn bit_cycles(&self) -> u16 {
+        if self.model == Model::Cgb && self.control & 0x02 != 0 {
+            FAST_BIT_CYCLES
+        } else {
+            NORMAL_BIT_CYCLES
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0xFF01 => self.data,
+            0xFF02 => match self.model {
+                Model::Cgb => 0x7C | self.control,
+                Model::Dmg => 0x7E | self.control,
+            },
+            _ => unreachable!()
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0xFF01 => self.data = value,
+            0xFF02 => {
+                self.control = match self.model {
+                    Model::Cgb => value & 0x83,
+                    Model::Dmg => value & 0x81,
+                };
+
+                self.transfer = if self.control & 0x80 == 0 {
+                    Transfer::Idle
+                } else if self.control & 0x01 != 0 {
+                    self.start_internal()
+                } else {
+                    Transfer::External
+                };
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn start_internal(&mut self) -> Transfer {
+        let data = self.data;
+        let incoming = self.device.as_mut().map_or(0xFF, |device| device.exchange(data));
+        self.timer = self.bit_cycles();
+
+        Transfer::Internal { bits: 8, incoming: incoming }
+    }
+
+    pub fn tick(&mut self, cycles: u16, interrupts: &mut Interrupts) {
+        let port = Port {
+            data: self.data,
+            external_ready: self.transfer == Transfer::External,
+        };
+
+        if let Some(ref mut device) = self.device {
+            device.tick(cycles, port);
+        }
+
+        match self.transfer {
+            Transfer::Idle => {},
+            Transfer::Internal { mut bits, incoming } => {
+                let mut cycles = cycles;
+
+                while cycles >= self.timer {
+                    cycles -= self.timer;
+                    self.timer = self.bit_cycles();
+
+                    bits -= 1;
+                    self.data = self.data << 1 | incoming >> bits & 1;
+
+                    if bits == 0 {
+                        self.finish(interrupts);
+                        return;
+                    }
+                }
+
+                self.timer -= cycles;
+                self.transfer = Transfer::Internal { bits: bits, incoming: incoming };
+            },
+            Transfer::External => {
+                let data = self.data;
+                let incoming = self.device.as_mut().and_then(|device| device.external_clock(data));
+
+                if let Some(incoming) = incoming {
+                    self.data = incoming;
+                    self.finish(interrupts);
+                }
+            },
+        }
+    }
+
+    fn finish(&mut self, interrupts: &mut Interrupts) {
+        self.control &= !0x80;
+        self.transfer = Transfer::Idle;
+        interrupts.request(Interrupt::Serial);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    struct MockDevice {
+        reply: u8,
+        sent: Rc<RefCell<Vec<u8>>>,
+        external: Option<u8>,
+    }
+
+    impl SerialDevice for MockDevice {
+        fn exchange(&mut self, byte: u8) -> u8 {
+            self.sent.borrow_mut().push(byte);
+            self.reply
+        }
+
+        fn external_clock(&mut self, byte: u8) -> Option<u8> {
+            let incoming = self.external.take();
+            if incoming.is_some() {
+                self.sent.borrow_mut().push(byte);
+            }
+            incoming
+        }
+    }
+
+    fn serial_with_mock(model: Model, external: Option<u8>) -> (Serial, Rc<RefCell<Vec<u8>>>) {
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        let mut serial = Serial::new(model);
+        serial.attach(Box::new(MockDevice {
+            reply: 0xA5,
+            sent: sent.clone(),
+            external: external,
+        }));
+        (serial, sent)
+    }
+
+    fn tick(serial: &mut Serial, interrupts: &mut Interrupts, cycles: u32) {
+        for _ in 0..cycles / 4 {
+            serial.tick(4, interrupts);
+        }
+    }
+
+    fn cycles_until_interrupt(serial: &mut Serial, interrupts: &mut Interrupts) -> u32 {
+        let mut cycles = 0;
+        while !interrupts.is_requested(Interrupt::Serial) {
+            assert!(cycles < 0x10000, "transfer never finished");
+            serial.tick(4, interrupts);
+            cycles += 4;
+        }
+        cycles
+    }
+
+    #[test]
+    fn internal_clock_shifts_one_bit_per_512_cycles() {
+        let (mut serial, sent) = serial_with_mock(Model::Dmg, None);
+        let mut interrupts = Interrupts::new();
+
+        serial.write(0xFF01, 0x55);
+        serial.write(0xFF02, 0x81);
+        assert_eq!(*sent.borrow(), vec![0x55]);
+
+        tick(&mut serial, &mut interrupts, 508);
+        assert_eq!(serial.read(0xFF01), 0x55);
+        tick(&mut serial, &mut interrupts, 4);
+        assert_eq!(serial.read(0xFF01), 0xAB);
+
+        tick(&mut serial, &mut interrupts, 7 * 512 - 4);
+        assert!(!interrupts.is_requested(Interrupt::Serial));
+        assert_eq!(serial.read(0xFF02) & 0x80, 0x80);
+
+        tick(&mut serial, &mut interrupts, 4);
+        assert!(interrupts.is_requested(Interrupt::Serial));
+        assert_eq!(serial.read(0xFF01), 0xA5);
+        assert_eq!(serial.read(0xFF02) & 0x80, 0);
+    }
+
+    #[test]
+    fn cgb_fast_clock_shifts_one_bit_per_16_cycles() {
+        let (mut serial, _) = serial_with_mock(Model::Cgb, None);
+        let mut interrupts = Interrupts::new();
+
+        serial.write(0xFF02, 0x83);
+        assert_eq!(cycles_until_interrupt(&mut serial, &mut interrupts), 8 * 16);
+        assert_eq!(serial.read(0xFF01), 0xA5);
+    }
+
+    #[test]
+    fn dmg_ignores_fast_clock() {
+        let (mut serial, _) = serial_with_mock(Model::Dmg, None);
+        let mut interrupts = Interrupts::new();
+
+        serial.write(0xFF02, 0x83);
+        assert_eq!(serial.read(0xFF02), 0xFF);
+        assert_eq!(cycles_until_interrupt(&mut serial, &mut interrupts), 8 * 512);
+    }
+
+    #[test]
+    fn unplugged_port_reads_ones() {
+        let mut serial = Serial::new(Model::Dmg);
+        let mut interrupts = Interrupts::new();
+
+        serial.write(0xFF01, 0x12);
+        serial.write(0xFF02, 0x81);
+        cycles_until_interrupt(&mut serial, &mut interrupts);
+        assert_eq!(serial.read(0xFF01), 0xFF);
+    }
+
+    #[test]
+    fn external_clock_waits_for_device() {
+        let (mut serial, sent) = serial_with_mock(Model::Dmg, Some(0x3C));
+        let mut interrupts = Interrupts::new();
+
+        serial.write(0xFF01, 0x42);
+        serial.write(0xFF02, 0x80);
+        serial.tick(4, &mut interrupts);
+        assert!(interrupts.is_requested(Interrupt::Serial));
+        assert_eq!(serial.read(0xFF01), 0x3C);
+        assert_eq!(*sent.borrow(), vec![0x42]);
+
+        // Without a clock from the other side, nothing happens
+        interrupts.acknowledge(Interrupt::Serial);
+        serial.write(0xFF02, 0x80);
+        tick(&mut serial, &mut interrupts, 0x10000);
+        assert!(!interrupts.is_requested(Interrupt::Serial));
+        assert_eq!(serial.read(0xFF02) & 0x80, 0x80);
+    }
+}
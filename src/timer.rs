@@ -0,0 +1,250 @@
+use interrupts::{Interrupts, Interrupt};
+
+/// DIV, TIMA, TMA and TAC (0xFF04 - 0xFF07).
+///
+/// DIV is the upper byte of a free running 16 bit counter.
+/// TIMA is incremented on each falling edge of the counter bit
+/// selected by TAC, ANDed with the timer enable bit.
+pub struct Timer {
+    counter: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+    reload: Reload,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Reload {
+    Idle,
+    // TIMA overflowed and reads 0 for one M-cycle
+    Pending,
+    // TIMA was just reloaded from TMA, writes to TIMA are ignored for one M-cycle
+    Done,
+}
+
+impl Timer {
+    pub fn new() -> Timer {
+        Timer {
+            counter: 0xABCC,
+            tima: 0,
+            tma: 0,
+            tac: 0,
+            reload: Reload::Idle,
+        }
+    }
+
+    pub fn tick(&mut self, cycles: u16, interrupts: &mut Interrupts) {
+        for _ in 0 .. cycles / 4 {
+            self.tick_m_cycle(interrupts);
+        }
+    }
+
+    fn tick_m_cycle(&mut self, interrupts: &mut Interrupts) {
+        self.reload = match self.reload {
+            Reload::Pending => {
+                self.tima = self.tma;
+                interrupts.request(Interrupt::Timer);
+                Reload::Done
+            },
+            Reload::Done | Reload::Idle => Reload::Idle,
+        };
+
+        let signal = self.signal();
+        self.counter = self.counter.wrapping_add(4);
+        self.detect_falling_edge(signal);
+    }
+
+    fn signal(&self) -> bool {
+        let bit = match self.tac & 0b11 {
+            0b00 => 9,
+            0b01 => 3,
+            0b10 => 5,
+            0b11 => 7,
+            _ => unreachable!()
+        };
+
+        self.tac & 0b100 != 0 && self.counter >> bit & 1 == 1
+    }
+
+    fn detect_falling_edge(&mut self, old_signal: bool) {
+        if old_signal && !self.signal() {
+            self.increment_tima();
+        }
+    }
+
+    fn increment_tima(&mut self) {
+        if self.tima == 0xFF {
+            self.tima = 0;
+            self.reload = Reload::Pending;
+        } else {
+            self.tima += 1;
+        }
+    }
+
+    /// The DIV bit whose falling edge clocks the APU frame sequencer,
+    /// bit 4 or bit 5 in CGB double speed mode.
+    pub fn apu_div_bit(&self, double_speed: bool) -> bool {
+        let bit = if double_speed { 13 } else { 12 };
+        self.counter >> bit & 1 == 1
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0xFF04 => (self.counter >> 8) as u8,
+            0xFF05 => self.tima,
+            0xFF06 => self.tma,
+            0xFF07 => self.tac | 0b11111000,
+            _ => unreachable!()
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        let signal = self.signal();
+
+        match addr {
+            0xFF04 => self.counter = 0,
+            0xFF05 => match self.reload {
+                // Writing during the overflow cycle cancels the reload
+                Reload::Pending => {
+                    self.tima = value;
+                    self.reload = Reload::Idle;
+                },
+                Reload::Done => {},
+                Reload::Idle => self.tima = value,
+            },
+            0xFF06 => {
+                self.tma = value;
+                if self.reload == Reload::Done {
+                    self.tima = value;
+                }
+            },
+            0xFF07 => self.tac = value & 0b111,
+            _ => unreachable!()
+        }
+
+        // Resetting DIV or changing TAC can also produce a falling edge
+        self.detect_falling_edge(signal);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A timer counting every 16 cycles from a reset divider
+    fn timer(tima: u8) -> Timer {
+        let mut timer = Timer::new();
+        timer.write(0xFF04, 0);
+        timer.write(0xFF05, tima);
+        timer.write(0xFF06, 0x42);
+        timer.write(0xFF07, 0b101);
+        timer
+    }
+
+    #[test]
+    fn tima_counts_at_the_tac_rate() {
+        let rates = [(0b100, 1024), (0b101, 16), (0b110, 64), (0b111, 256)];
+
+        for &(tac, period) in &rates {
+            let mut interrupts = Interrupts::new();
+            let mut timer = Timer::new();
+            timer.write(0xFF04, 0);
+            timer.write(0xFF07, tac);
+
+            timer.tick(period - 4, &mut interrupts);
+            assert_eq!(timer.read(0xFF05), 0, "TAC = {:03b}", tac);
+            timer.tick(4, &mut interrupts);
+            assert_eq!(timer.read(0xFF05), 1, "TAC = {:03b}", tac);
+            timer.tick(period, &mut interrupts);
+            assert_eq!(timer.read(0xFF05), 2, "TAC = {:03b}", tac);
+        }
+    }
+
+    #[test]
+    fn tima_doesnt_count_when_disabled() {
+        let mut interrupts = Interrupts::new();
+        let mut timer = Timer::new();
+        timer.write(0xFF07, 0b001);
+
+        timer.tick(1024, &mut interrupts);
+        assert_eq!(timer.read(0xFF05), 0);
+    }
+
+    #[test]
+    fn resetting_div_can_increment_tima() {
+        let mut interrupts = Interrupts::new();
+        let mut timer = timer(0);
+
+        // The selected bit 3 is set, so resetting the counter is a falling edge
+        timer.tick(8, &mut interrupts);
+        timer.write(0xFF04, 0x12);
+        assert_eq!(timer.read(0xFF04), 0);
+        assert_eq!(timer.read(0xFF05), 1);
+
+        // With the bit clear it isn't
+        timer.tick(4, &mut interrupts);
+        timer.write(0xFF04, 0);
+        assert_eq!(timer.read(0xFF05), 1);
+    }
+
+    #[test]
+    fn writing_tac_can_increment_tima() {
+        let mut interrupts = Interrupts::new();
+        let mut timer = timer(0);
+        timer.tick(8, &mut interrupts);
+
+        // Disabling the timer while the selected bit is set
+        timer.write(0xFF07, 0b001);
+        assert_eq!(timer.read(0xFF05), 1);
+
+        // Selecting a bit that is clear
+        timer.write(0xFF07, 0b101);
+        timer.write(0xFF07, 0b100);
+        assert_eq!(timer.read(0xFF05), 2);
+    }
+
+    #[test]
+    fn overflow_reloads_tma_one_cycle_later() {
+        let mut interrupts = Interrupts::new();
+        let mut timer = timer(0xFF);
+
+        timer.tick(16, &mut interrupts);
+        assert_eq!(timer.read(0xFF05), 0);
+        assert!(!interrupts.is_requested(Interrupt::Timer));
+
+        timer.tick(4, &mut interrupts);
+        assert_eq!(timer.read(0xFF05), 0x42);
+        assert!(interrupts.is_requested(Interrupt::Timer));
+    }
+
+    #[test]
+    fn writing_tima_in_the_overflow_cycle_cancels_the_reload() {
+        let mut interrupts = Interrupts::new();
+        let mut timer = timer(0xFF);
+
+        timer.tick(16, &mut interrupts);
+        timer.write(0xFF05, 0x10);
+        timer.tick(4, &mut interrupts);
+
+        assert_eq!(timer.read(0xFF05), 0x10);
+        assert!(!interrupts.is_requested(Interrupt::Timer));
+    }
+
+    #[test]
+    fn writes_in_the_reload_cycle() {
+        let mut interrupts = Interrupts::new();
+        let mut timer = timer(0xFF);
+        timer.tick(20, &mut interrupts);
+
+        // TIMA writes are ignored, TMA writes go through to TIMA
+        timer.write(0xFF05, 0x10);
+        assert_eq!(timer.read(0xFF05), 0x42);
+        timer.write(0xFF06, 0x20);
+        assert_eq!(timer.read(0xFF05), 0x20);
+
+        // One cycle later TIMA can be written again
+        timer.tick(4, &mut interrupts);
+        timer.write(0xFF05, 0x10);
+        assert_eq!(timer.read(0xFF05), 0x10);
+    }
+}
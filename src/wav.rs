@@ -0,0 +1,118 @@
+use std::path::Path;
+use std::io::{self, Write, Seek, SeekFrom, BufWriter};
+use std::fs::File;
+use byteorder::{ByteOrder, LittleEndian};
+
+const HEADER_SIZE: u32 = 44;
+const CHANNELS: u16 = 2;
+const BITS_PER_SAMPLE: u16 = 16;
+
+/// Writes interleaved stereo samples to a 16 bit PCM `.wav` file.
+pub struct WavWriter {
+    file: BufWriter<File>,
+    sample_rate: u32,
+    data_size: u32,
+}
+
+impl WavWriter {
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> io::Result<WavWriter> {
+        let mut writer = WavWriter {
+            file: BufWriter::new(File::create(path)?),
+            sample_rate: sample_rate,
+            data_size: 0,
+        };
+
+        // The sizes are filled in by `finish`
+        writer.write_header()?;
+
+        Ok(writer)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+        let mut header = [0; HEADER_SIZE as usize];
+
+        header[0..4].copy_from_slice(b"RIFF");
+        LittleEndian::write_u32(&mut header[4..8], HEADER_SIZE - 8 + self.data_size);
+        header[8..12].copy_from_slice(b"WAVE");
+        header[12..16].copy_from_slice(b"fmt ");
+        LittleEndian::write_u32(&mut header[16..20], 16);
+        LittleEndian::write_u16(&mut header[20..22], 1); // PCM
+        LittleEndian::write_u16(&mut header[22..24], CHANNELS);
+        LittleEndian::write_u32(&mut header[24..28], self.sample_rate);
+        LittleEndian::write_u32(&mut header[28..32], self.sample_rate * block_align as u32);
+        LittleEndian::write_u16(&mut header[32..34], block_align);
+        LittleEndian::write_u16(&mut header[34..36], BITS_PER_SAMPLE);
+        header[36..40].copy_from_slice(b"data");
+        LittleEndian::write_u32(&mut header[40..44], self.data_size);
+
+        self.file.write_all(&header)
+    }
+
+    /// Writes interleaved samples in the range -1.0 - 1.0.
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        let mut bytes = [0; 2];
+
+        for &sample in samples {
+            let sample = (sample.clamp(-1.0, 1.0) * 32767.0) as i16;
+            LittleEndian::write_i16(&mut bytes, sample);
+            self.file.write_all(&bytes)?;
+        }
+
+        self.data_size += samples.len() as u32 * 2;
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        bytes[offset] as u16 | (bytes[offset + 1] as u16) << 8
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u16_at(bytes, offset) as u32 | (u16_at(bytes, offset + 2) as u32) << 16
+    }
+
+    #[test]
+    fn writes_header_and_samples() {
+        let path = env::temp_dir().join("rust-gb-wav-test.wav");
+
+        let mut writer = WavWriter::create(&path, 48000).unwrap();
+        writer.write_samples(&[0.0, 1.0, -1.0, 0.5]).unwrap();
+        writer.write_samples(&[2.0, -2.0]).unwrap();
+        writer.finish().unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(bytes.len(), 44 + 12);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32_at(&bytes, 4), 36 + 12);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&bytes, 16), 16);
+        assert_eq!(u16_at(&bytes, 20), 1);
+        assert_eq!(u16_at(&bytes, 22), 2);
+        assert_eq!(u32_at(&bytes, 24), 48000);
+        assert_eq!(u32_at(&bytes, 28), 48000 * 4);
+        assert_eq!(u16_at(&bytes, 32), 4);
+        assert_eq!(u16_at(&bytes, 34), 16);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(u32_at(&bytes, 40), 12);
+
+        // Samples are clamped to -1.0 - 1.0
+        let samples: Vec<i16> = (0 .. 6).map(|i| u16_at(&bytes, 44 + i * 2) as i16).collect();
+        assert_eq!(samples, [0, 32767, -32767, 16383, 32767, -32767]);
+    }
+}